@@ -0,0 +1,257 @@
+//! Off-chain decoding of program accounts.
+//!
+//! [`VaultAccount`] detects the account type from its discriminator, checks the
+//! owner, layout version and field consistency, and renders the result for logs
+//! (`Display`) and dashboards (serde, with pubkeys as base58 strings).
+
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::account::ReadableAccount;
+use std::fmt;
+
+use crate::state::{BasketBalance, BasketState, ProgramAccount, UserBalance, VaultMetadata, VaultState};
+
+/// A decoded account of the vault program
+#[derive(Debug, Clone, PartialEq)]
+pub enum VaultAccount {
+    Vault(Box<VaultState>),
+    UserBalance(UserBalance),
+    Basket(Box<BasketState>),
+    BasketBalance(BasketBalance),
+    Metadata(Box<VaultMetadata>),
+}
+
+impl VaultAccount {
+    /// Decode an account owned by this crate's program id
+    pub fn try_from_account(account: &impl ReadableAccount) -> Result<Self, &'static str> {
+        Self::try_from_account_with_program_id(account, &crate::id())
+    }
+
+    /// Decode an account owned by the vault program deployed at `program_id`
+    pub fn try_from_account_with_program_id(
+        account: &impl ReadableAccount,
+        program_id: &Pubkey,
+    ) -> Result<Self, &'static str> {
+        if account.owner() != program_id {
+            return Err("Account not owned by the vault program");
+        }
+        Self::try_from_data(account.data())
+    }
+
+    /// Decode raw account data, picking the type from its discriminator
+    pub fn try_from_data(data: &[u8]) -> Result<Self, &'static str> {
+        let account = if VaultState::has_discriminator(data) {
+            VaultAccount::Vault(Box::new(VaultState::from_account_bytes(data)?))
+        } else if UserBalance::has_discriminator(data) {
+            VaultAccount::UserBalance(UserBalance::from_account_bytes(data)?)
+        } else if BasketState::has_discriminator(data) {
+            VaultAccount::Basket(Box::new(BasketState::from_account_bytes(data)?))
+        } else if BasketBalance::has_discriminator(data) {
+            VaultAccount::BasketBalance(BasketBalance::from_account_bytes(data)?)
+        } else if VaultMetadata::has_discriminator(data) {
+            VaultAccount::Metadata(Box::new(VaultMetadata::from_account_bytes(data)?))
+        } else {
+            return Err("Unknown account discriminator");
+        };
+
+        match &account {
+            VaultAccount::Vault(vault) => vault.validate()?,
+            VaultAccount::UserBalance(balance) => balance.validate()?,
+            VaultAccount::Basket(basket) => basket.validate()?,
+            VaultAccount::BasketBalance(balance) => balance.validate()?,
+            VaultAccount::Metadata(metadata) => metadata.validate()?,
+        }
+        Ok(account)
+    }
+
+    /// Account type name, as used in the `type` field of the JSON form
+    pub fn account_type(&self) -> &'static str {
+        match self {
+            VaultAccount::Vault(_) => "vault",
+            VaultAccount::UserBalance(_) => "user_balance",
+            VaultAccount::Basket(_) => "basket",
+            VaultAccount::BasketBalance(_) => "basket_balance",
+            VaultAccount::Metadata(_) => "metadata",
+        }
+    }
+
+    /// The vault state, if this is one
+    pub fn as_vault(&self) -> Option<&VaultState> {
+        match self {
+            VaultAccount::Vault(vault) => Some(vault),
+            _ => None,
+        }
+    }
+
+    /// The user balance, if this is one
+    pub fn as_user_balance(&self) -> Option<&UserBalance> {
+        match self {
+            VaultAccount::UserBalance(balance) => Some(balance),
+            _ => None,
+        }
+    }
+
+    /// JSON form, tagged with `type`
+    pub fn to_json(&self) -> Value {
+        let mut value = match self {
+            VaultAccount::Vault(vault) => json!({
+                "owner": vault.owner.to_string(),
+                "token_mint": vault.token_mint.to_string(),
+                "token_account": vault.token_account.to_string(),
+                "vault_id": vault.vault_id,
+                "total_deposited": vault.total_deposited,
+                "accrued_fees": vault.accrued_fees,
+                "total_queued": vault.total_queued,
+                "yield_per_token": vault.yield_per_token.to_string(),
+                "min_idle_bps": vault.min_idle_bps,
+                "strategies": vault.active_strategies().iter().map(|strategy| json!({
+                    "program_id": strategy.program_id.to_string(),
+                    "allocated": strategy.allocated,
+                    "cap": strategy.cap,
+                })).collect::<Vec<_>>(),
+                "is_closed": vault.is_closed,
+                "is_locked": vault.is_locked,
+                "bump": vault.bump,
+            }),
+            VaultAccount::UserBalance(balance) => json!({
+                "user": balance.user.to_string(),
+                "vault": balance.vault.to_string(),
+                "balance": balance.balance,
+                "pending_withdrawal": balance.pending_withdrawal,
+                "yield_checkpoint": balance.yield_checkpoint.to_string(),
+                "delegate": balance.delegate.to_string(),
+                "delegated_amount": balance.delegated_amount,
+                "nonce": balance.nonce,
+                "bump": balance.bump,
+            }),
+            VaultAccount::Basket(basket) => json!({
+                "owner": basket.owner.to_string(),
+                "basket_id": basket.basket_id,
+                "mints": basket.active_mints().iter().zip(basket.total_deposited.iter()).map(|(mint, total)| json!({
+                    "mint": mint.to_string(),
+                    "total_deposited": total,
+                })).collect::<Vec<_>>(),
+                "is_closed": basket.is_closed,
+                "bump": basket.bump,
+            }),
+            VaultAccount::BasketBalance(balance) => json!({
+                "user": balance.user.to_string(),
+                "basket": balance.basket.to_string(),
+                "mint": balance.mint.to_string(),
+                "balance": balance.balance,
+                "bump": balance.bump,
+            }),
+            VaultAccount::Metadata(metadata) => json!({
+                "vault": metadata.vault.to_string(),
+                "name": metadata.name(),
+                "uri": metadata.uri(),
+                "decimals": metadata.decimals,
+                "created_at": metadata.created_at,
+                "bump": metadata.bump,
+            }),
+        };
+        value["type"] = json!(self.account_type());
+        value
+    }
+}
+
+impl Serialize for VaultAccount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json().serialize(serializer)
+    }
+}
+
+impl fmt::Display for VaultAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VaultAccount::Vault(vault) => {
+                writeln!(f, "Owner:           {}", vault.owner)?;
+                writeln!(f, "Mint:            {}", vault.token_mint)?;
+                writeln!(f, "Token account:   {}", vault.token_account)?;
+                writeln!(f, "Vault id:        {}", vault.vault_id)?;
+                writeln!(f, "Total deposited: {}", vault.total_deposited)?;
+                writeln!(f, "Accrued fees:    {}", vault.accrued_fees)?;
+                writeln!(f, "Total queued:    {}", vault.total_queued)?;
+                writeln!(f, "Min idle bps:    {}", vault.min_idle_bps)?;
+                writeln!(f, "Strategies:      {}", vault.active_strategies().len())?;
+                write!(f, "Status:          {}", if vault.is_closed { "closed" } else { "open" })
+            }
+            VaultAccount::UserBalance(balance) => {
+                writeln!(f, "User:               {}", balance.user)?;
+                writeln!(f, "Vault:              {}", balance.vault)?;
+                writeln!(f, "Balance:            {}", balance.balance)?;
+                writeln!(f, "Pending withdrawal: {}", balance.pending_withdrawal)?;
+                if balance.delegate != Pubkey::default() {
+                    writeln!(f, "Delegate:           {}", balance.delegate)?;
+                    writeln!(f, "Delegated amount:   {}", balance.delegated_amount)?;
+                }
+                write!(f, "Nonce:              {}", balance.nonce)
+            }
+            VaultAccount::Basket(basket) => {
+                writeln!(f, "Owner:  {}", basket.owner)?;
+                writeln!(f, "Id:     {}", basket.basket_id)?;
+                for (mint, total) in basket.active_mints().iter().zip(basket.total_deposited.iter()) {
+                    writeln!(f, "Mint:   {} ({} deposited)", mint, total)?;
+                }
+                write!(f, "Status: {}", if basket.is_closed { "closed" } else { "open" })
+            }
+            VaultAccount::BasketBalance(balance) => {
+                writeln!(f, "User:    {}", balance.user)?;
+                writeln!(f, "Basket:  {}", balance.basket)?;
+                writeln!(f, "Mint:    {}", balance.mint)?;
+                write!(f, "Balance: {}", balance.balance)
+            }
+            VaultAccount::Metadata(metadata) => {
+                writeln!(f, "Vault:      {}", metadata.vault)?;
+                writeln!(f, "Name:       {}", metadata.name())?;
+                writeln!(f, "URI:        {}", metadata.uri())?;
+                writeln!(f, "Decimals:   {}", metadata.decimals)?;
+                write!(f, "Created at: {}", metadata.created_at)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::account::Account;
+
+    fn program_account(data: Vec<u8>) -> Account {
+        Account {
+            lamports: 1,
+            data,
+            owner: crate::id(),
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    #[test]
+    fn test_detects_type_and_checks_owner() {
+        let user = Pubkey::new_unique();
+        let vault = Pubkey::new_unique();
+        let mut balance = UserBalance::new(user, vault, 255);
+        balance.balance = 42;
+        let mut account = program_account(balance.to_account_bytes().unwrap());
+
+        let decoded = VaultAccount::try_from_account(&account).unwrap();
+        assert_eq!(decoded.as_user_balance(), Some(&balance));
+        let json = serde_json::to_value(&decoded).unwrap();
+        assert_eq!(json["type"], "user_balance");
+        assert_eq!(json["user"], user.to_string());
+        assert_eq!(json["balance"], 42);
+        assert!(decoded.to_string().contains("Balance:            42"));
+
+        account.owner = Pubkey::new_unique();
+        assert_eq!(VaultAccount::try_from_account(&account), Err("Account not owned by the vault program"));
+
+        let unknown = program_account(vec![0; UserBalance::SIZE]);
+        assert_eq!(VaultAccount::try_from_account(&unknown), Err("Unknown account discriminator"));
+
+        // Header is valid but the fields are not
+        let empty = program_account(UserBalance::new(Pubkey::default(), vault, 255).to_account_bytes().unwrap());
+        assert_eq!(VaultAccount::try_from_account(&empty), Err("Invalid user pubkey"));
+    }
+}
@@ -0,0 +1,23 @@
+//! Command-line tool for operating vaults. Run `vault-cli --help` for usage.
+
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_vault_contract::cli::{parse_args, read_keypair, run, CliError};
+
+async fn main_inner() -> Result<(), CliError> {
+    let (config, command) = parse_args(std::env::args_os())?;
+    let signer = read_keypair(&config.keypair_path)?;
+    let mut rpc_client = RpcClient::new_with_commitment(config.rpc_url.clone(), CommitmentConfig::confirmed());
+
+    let output = run(&mut rpc_client, &config, &signer, command).await?;
+    println!("{}", output.render(config.output));
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = main_inner().await {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
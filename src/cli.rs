@@ -0,0 +1,415 @@
+//! Implementation of the `vault-cli` binary.
+//!
+//! Commands are parsed with [`parse_args`] and executed by [`run`] against any
+//! [`VaultBackend`]. The binary uses the nonblocking `RpcClient`; tests can plug in a
+//! program-test `BanksClient` instead. Instructions are built with
+//! [`VaultClient`], so token accounts are the associated token accounts of the
+//! vault PDA and of the signer.
+
+use clap::{Arg, ArgMatches, Command as ClapCommand};
+use serde_json::json;
+use solana_program::{instruction::Instruction, program_error::ProgramError, pubkey::Pubkey};
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    signature::{Keypair, Signature, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+use crate::{account::VaultAccount, client::VaultClient, error::VaultError};
+
+/// Default JSON RPC endpoint, a local `solana-test-validator`
+pub const DEFAULT_RPC_URL: &str = "http://127.0.0.1:8899";
+
+/// Errors reported by the CLI
+#[derive(Error, Debug)]
+pub enum CliError {
+    /// Command line could not be parsed
+    #[error("{0}")]
+    InvalidArgument(String),
+
+    /// RPC failure
+    #[error("RPC error: {0}")]
+    Rpc(String),
+
+    /// Transaction was sent but failed
+    #[error("Transaction failed: {0}")]
+    Transaction(TransactionError),
+
+    /// Transaction failed with an error of the vault program
+    #[error("Instruction {index} failed: {error} (vault error {code})", code = error.code())]
+    Vault { index: u8, error: VaultError },
+
+    /// Keypair file could not be read
+    #[error("Failed to read keypair {path}: {reason}")]
+    Keypair { path: String, reason: String },
+
+    /// Account does not exist on chain
+    #[error("Account {0} not found")]
+    AccountNotFound(Pubkey),
+
+    /// Account exists but is not a valid program account of the expected type
+    #[error("Failed to decode account {account}: {reason}")]
+    Decode { account: Pubkey, reason: &'static str },
+
+    /// Account resolution failed
+    #[error(transparent)]
+    Program(#[from] ProgramError),
+}
+
+/// Output rendering
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
+/// Global options shared by every subcommand
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub rpc_url: String,
+    pub keypair_path: String,
+    pub output: OutputFormat,
+    pub program_id: Pubkey,
+}
+
+/// Vault selection: owner (defaults to the signer), mint and vault id
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultArgs {
+    pub owner: Option<Pubkey>,
+    pub mint: Pubkey,
+    pub vault_id: u64,
+}
+
+/// Subcommands of `vault-cli`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Init(VaultArgs),
+    Deposit { vault: VaultArgs, amount: u64 },
+    Withdraw { vault: VaultArgs, amount: u64 },
+    WithdrawAll(VaultArgs),
+    Close(VaultArgs),
+    ShowVault(VaultArgs),
+    ShowBalance { vault: VaultArgs, user: Option<Pubkey> },
+}
+
+/// Result of a command
+#[derive(Debug, Clone, PartialEq)]
+pub enum Output {
+    /// A transaction was confirmed
+    Transaction { command: &'static str, vault: Pubkey, signature: Signature },
+    /// A decoded program account
+    Account { address: Pubkey, account: VaultAccount },
+}
+
+impl Output {
+    /// Render for the terminal
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Human => self.to_string(),
+            OutputFormat::Json => self.to_json().to_string(),
+        }
+    }
+
+    /// JSON representation
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Output::Transaction { command, vault, signature } => json!({
+                "command": command,
+                "vault": vault.to_string(),
+                "signature": signature.to_string(),
+            }),
+            Output::Account { address, account } => {
+                let mut value = account.to_json();
+                value["address"] = json!(address.to_string());
+                value
+            }
+        }
+    }
+}
+
+impl fmt::Display for Output {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Output::Transaction { command, vault, signature } => {
+                writeln!(f, "{} succeeded", command)?;
+                writeln!(f, "Vault:     {}", vault)?;
+                write!(f, "Signature: {}", signature)
+            }
+            Output::Account { address, account } => {
+                writeln!(f, "Address: {}", address)?;
+                write!(f, "{}", account)
+            }
+        }
+    }
+}
+
+/// Chain access used by [`run`]
+#[allow(async_fn_in_trait)]
+pub trait VaultBackend {
+    /// Data of `address`, or `None` if the account does not exist
+    async fn account_data(&mut self, address: &Pubkey) -> Result<Option<Vec<u8>>, CliError>;
+
+    /// Sign `instructions` with `signer` as fee payer, send them and wait for confirmation.
+    /// A transaction that was processed but failed is reported as [`CliError::Transaction`].
+    async fn send(&mut self, instructions: &[Instruction], signer: &Keypair) -> Result<Signature, CliError>;
+}
+
+impl VaultBackend for RpcClient {
+    async fn account_data(&mut self, address: &Pubkey) -> Result<Option<Vec<u8>>, CliError> {
+        let response = self
+            .get_account_with_commitment(address, CommitmentConfig::confirmed())
+            .await
+            .map_err(|e| CliError::Rpc(e.to_string()))?;
+        Ok(response.value.map(|account| account.data))
+    }
+
+    async fn send(&mut self, instructions: &[Instruction], signer: &Keypair) -> Result<Signature, CliError> {
+        let recent_blockhash = self
+            .get_latest_blockhash()
+            .await
+            .map_err(|e| CliError::Rpc(e.to_string()))?;
+        let transaction =
+            Transaction::new_signed_with_payer(instructions, Some(&signer.pubkey()), &[signer], recent_blockhash);
+        self.send_and_confirm_transaction(&transaction).await.map_err(|e| match e.get_transaction_error() {
+            Some(error) => CliError::Transaction(error),
+            None => CliError::Rpc(e.to_string()),
+        })
+    }
+}
+
+fn vault_args<'a>(command: ClapCommand<'a>) -> ClapCommand<'a> {
+    command
+        .arg(Arg::new("mint").long("mint").takes_value(true).required(true).help("Token mint of the vault"))
+        .arg(Arg::new("owner").long("owner").takes_value(true).help("Vault owner [default: the signer]"))
+        .arg(Arg::new("vault-id").long("vault-id").takes_value(true).default_value("0").help("Vault id"))
+}
+
+fn amount_arg<'a>(command: ClapCommand<'a>) -> ClapCommand<'a> {
+    command.arg(Arg::new("amount").required(true).help("Amount in the token's smallest unit"))
+}
+
+/// Clap definition of `vault-cli`
+pub fn app() -> ClapCommand<'static> {
+    ClapCommand::new("vault-cli")
+        .about("Operate vaults of the vault program")
+        .subcommand_required(true)
+        .arg(
+            Arg::new("url")
+                .long("url")
+                .short('u')
+                .global(true)
+                .takes_value(true)
+                .default_value(DEFAULT_RPC_URL)
+                .help("JSON RPC URL"),
+        )
+        .arg(
+            Arg::new("keypair")
+                .long("keypair")
+                .short('k')
+                .global(true)
+                .takes_value(true)
+                .help("Signer keypair file [default: ~/.config/solana/id.json]"),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .global(true)
+                .takes_value(true)
+                .possible_values(["human", "json"])
+                .default_value("human")
+                .help("Output format"),
+        )
+        .arg(
+            Arg::new("program-id")
+                .long("program-id")
+                .global(true)
+                .takes_value(true)
+                .help("Vault program id [default: the built-in id]"),
+        )
+        .subcommand(vault_args(ClapCommand::new("init").about("Create the vault token account and initialize a vault")))
+        .subcommand(amount_arg(vault_args(ClapCommand::new("deposit").about("Deposit from the signer's token account"))))
+        .subcommand(amount_arg(vault_args(ClapCommand::new("withdraw").about("Withdraw to the signer's token account"))))
+        .subcommand(vault_args(ClapCommand::new("withdraw-all").about("Owner withdraws every token in the vault")))
+        .subcommand(vault_args(ClapCommand::new("close").about("Owner closes the vault")))
+        .subcommand(vault_args(ClapCommand::new("show-vault").about("Print the vault state")))
+        .subcommand(
+            vault_args(ClapCommand::new("show-balance").about("Print a user's balance"))
+                .arg(Arg::new("user").long("user").takes_value(true).help("User [default: the signer]")),
+        )
+}
+
+fn parse_value<T: FromStr>(matches: &ArgMatches, name: &str) -> Result<Option<T>, CliError> {
+    matches
+        .value_of(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| CliError::InvalidArgument(format!("Invalid value for --{}: {}", name, value)))
+        })
+        .transpose()
+}
+
+fn parse_vault_args(matches: &ArgMatches) -> Result<VaultArgs, CliError> {
+    Ok(VaultArgs {
+        owner: parse_value(matches, "owner")?,
+        mint: parse_value(matches, "mint")?.ok_or_else(|| CliError::InvalidArgument("--mint is required".into()))?,
+        vault_id: parse_value(matches, "vault-id")?.unwrap_or_default(),
+    })
+}
+
+fn parse_amount(matches: &ArgMatches) -> Result<u64, CliError> {
+    parse_value(matches, "amount")?.ok_or_else(|| CliError::InvalidArgument("amount is required".into()))
+}
+
+/// Parse a full command line, program name included
+pub fn parse_args<I, T>(args: I) -> Result<(Config, Command), CliError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let matches = app()
+        .try_get_matches_from(args)
+        .map_err(|e| CliError::InvalidArgument(e.to_string()))?;
+
+    let config = Config {
+        rpc_url: matches.value_of("url").unwrap_or(DEFAULT_RPC_URL).to_string(),
+        keypair_path: match matches.value_of("keypair") {
+            Some(path) => path.to_string(),
+            None => default_keypair_path(),
+        },
+        output: match matches.value_of("output") {
+            Some("json") => OutputFormat::Json,
+            _ => OutputFormat::Human,
+        },
+        program_id: parse_value(&matches, "program-id")?.unwrap_or_else(crate::id),
+    };
+
+    let command = match matches.subcommand() {
+        Some(("init", sub)) => Command::Init(parse_vault_args(sub)?),
+        Some(("deposit", sub)) => Command::Deposit { vault: parse_vault_args(sub)?, amount: parse_amount(sub)? },
+        Some(("withdraw", sub)) => Command::Withdraw { vault: parse_vault_args(sub)?, amount: parse_amount(sub)? },
+        Some(("withdraw-all", sub)) => Command::WithdrawAll(parse_vault_args(sub)?),
+        Some(("close", sub)) => Command::Close(parse_vault_args(sub)?),
+        Some(("show-vault", sub)) => Command::ShowVault(parse_vault_args(sub)?),
+        Some(("show-balance", sub)) => Command::ShowBalance {
+            vault: parse_vault_args(sub)?,
+            user: parse_value(sub, "user")?,
+        },
+        _ => return Err(CliError::InvalidArgument("No subcommand given".into())),
+    };
+
+    Ok((config, command))
+}
+
+/// `~/.config/solana/id.json`, the Solana CLI's default keypair
+pub fn default_keypair_path() -> String {
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    format!("{}/.config/solana/id.json", home)
+}
+
+/// Read the signer keypair from `path`
+pub fn read_keypair(path: &str) -> Result<Keypair, CliError> {
+    solana_sdk::signature::read_keypair_file(path).map_err(|e| CliError::Keypair {
+        path: path.to_string(),
+        reason: e.to_string(),
+    })
+}
+
+/// Fetch and decode `address`, expecting the account type named `expected`
+async fn load_account<B: VaultBackend>(
+    backend: &mut B,
+    address: &Pubkey,
+    expected: &'static str,
+) -> Result<VaultAccount, CliError> {
+    let data = backend
+        .account_data(address)
+        .await?
+        .ok_or(CliError::AccountNotFound(*address))?;
+    let account = VaultAccount::try_from_data(&data).map_err(|reason| CliError::Decode { account: *address, reason })?;
+    if account.account_type() != expected {
+        return Err(CliError::Decode { account: *address, reason: "Unexpected account type" });
+    }
+    Ok(account)
+}
+
+/// Report a failed transaction as a vault error when the failing instruction targets
+/// the vault program; other programs' custom codes are left as they are.
+fn decode_vault_error(instructions: &[Instruction], program_id: &Pubkey, error: TransactionError) -> CliError {
+    match VaultError::from_transaction_error(&error) {
+        Some((index, vault_error))
+            if instructions.get(index as usize).map(|ix| &ix.program_id) == Some(program_id) =>
+        {
+            CliError::Vault { index, error: vault_error }
+        }
+        _ => CliError::Transaction(error),
+    }
+}
+
+/// Execute `command`, signing with `signer`
+pub async fn run<B: VaultBackend>(
+    backend: &mut B,
+    config: &Config,
+    signer: &Keypair,
+    command: Command,
+) -> Result<Output, CliError> {
+    let client = |vault: &VaultArgs| {
+        VaultClient::with_program_id(
+            config.program_id,
+            vault.owner.unwrap_or_else(|| signer.pubkey()),
+            vault.mint,
+            vault.vault_id,
+        )
+        .map(|client| client.create_token_accounts(true))
+    };
+
+    let (command_name, client, instructions) = match command {
+        Command::Init(vault) => {
+            let client = client(&vault)?;
+            let instructions = client.initialize(None)?;
+            ("init", client, instructions)
+        }
+        Command::Deposit { vault, amount } => {
+            let client = client(&vault)?;
+            let instructions = client.deposit(&signer.pubkey(), amount)?;
+            ("deposit", client, instructions)
+        }
+        Command::Withdraw { vault, amount } => {
+            let client = client(&vault)?;
+            let instructions = client.withdraw(&signer.pubkey(), amount)?;
+            ("withdraw", client, instructions)
+        }
+        Command::WithdrawAll(vault) => {
+            let client = client(&vault)?;
+            let instructions = client.withdraw_all();
+            ("withdraw-all", client, instructions)
+        }
+        Command::Close(vault) => {
+            let client = client(&vault)?;
+            let instructions = client.close();
+            ("close", client, instructions)
+        }
+        Command::ShowVault(vault) => {
+            let address = client(&vault)?.vault_state();
+            let account = load_account(backend, &address, "vault").await?;
+            return Ok(Output::Account { address, account });
+        }
+        Command::ShowBalance { vault, user } => {
+            let address = client(&vault)?.user_balance(&user.unwrap_or_else(|| signer.pubkey()))?;
+            let account = load_account(backend, &address, "user_balance").await?;
+            return Ok(Output::Account { address, account });
+        }
+    };
+
+    let signature = backend.send(&instructions, signer).await.map_err(|e| match e {
+        CliError::Transaction(error) => decode_vault_error(&instructions, &config.program_id, error),
+        e => e,
+    })?;
+    Ok(Output::Transaction {
+        command: command_name,
+        vault: client.vault_state(),
+        signature,
+    })
+}
@@ -0,0 +1,365 @@
+//! Off-chain instruction builders that resolve every account themselves.
+//!
+//! The builders on [`VaultInstruction`] take each account explicitly. [`VaultClient`]
+//! only needs the vault owner, the token mint and the vault id. It derives the vault
+//! state and user balance PDAs with [`crate::utils`] and uses associated token accounts
+//! for the vault and its users. With [`VaultClient::create_token_accounts`] enabled,
+//! instructions that pay tokens out are preceded by an idempotent ATA creation, so
+//! the receiving account does not need to exist beforehand.
+//!
+//! Depositors of a vault are listed with `getProgramAccounts`: [`depositor_filters`]
+//! selects the vault's `UserBalance` accounts and [`VaultClient::depositor_table`]
+//! aggregates the result and cross-checks it against the vault state.
+
+use solana_program::{instruction::Instruction, program_error::ProgramError, pubkey::Pubkey};
+use solana_rpc_client_api::{
+    config::RpcProgramAccountsConfig,
+    filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::account::ReadableAccount;
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+
+use crate::{
+    account::VaultAccount,
+    instruction::{VaultInstruction, VaultMetadataArgs},
+    state::{ProgramAccount, UserBalance, VaultState, USER_BALANCE_VAULT_OFFSET},
+    utils::{derive_metadata_pda, derive_user_balance_pda, derive_vault_state_pda},
+};
+
+/// `getProgramAccounts` filters matching the `UserBalance` accounts of `vault_state`
+pub fn depositor_filters(vault_state: &Pubkey) -> Vec<RpcFilterType> {
+    vec![
+        RpcFilterType::DataSize(UserBalance::SIZE as u64),
+        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(0, UserBalance::DISCRIMINATOR.to_vec())),
+        RpcFilterType::Memcmp(Memcmp::new_raw_bytes(USER_BALANCE_VAULT_OFFSET, vault_state.to_bytes().to_vec())),
+    ]
+}
+
+/// One depositor of a vault
+#[derive(Debug, Clone, PartialEq)]
+pub struct Depositor {
+    /// User balance account
+    pub address: Pubkey,
+    pub user: Pubkey,
+    pub balance: u64,
+    pub pending_withdrawal: u64,
+}
+
+/// Depositors of a vault, largest balance first, with the totals they add up to
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepositorTable {
+    pub depositors: Vec<Depositor>,
+    /// Sum of the depositors' balances
+    pub total_balance: u64,
+    /// Sum of the depositors' queued withdrawals, still counted in `total_deposited` until claimed
+    pub total_pending: u64,
+    /// `VaultState::total_deposited` at the time of the query
+    pub total_deposited: u64,
+}
+
+impl DepositorTable {
+    /// Whether balances plus queued withdrawals add up to the vault's total deposited
+    pub fn is_consistent(&self) -> bool {
+        self.total_balance.checked_add(self.total_pending) == Some(self.total_deposited)
+    }
+
+    /// `total_deposited` minus what the depositors hold; zero when consistent
+    pub fn discrepancy(&self) -> i128 {
+        self.total_deposited as i128 - self.total_balance as i128 - self.total_pending as i128
+    }
+}
+
+/// Account resolver and instruction builder for one vault
+#[derive(Debug, Clone, PartialEq)]
+pub struct VaultClient {
+    program_id: Pubkey,
+    owner: Pubkey,
+    mint: Pubkey,
+    vault_id: u64,
+    vault_state: Pubkey,
+    create_token_accounts: bool,
+}
+
+impl VaultClient {
+    /// Client for the vault `vault_id` of `owner` and `mint`, deployed at the crate's program id
+    pub fn new(owner: Pubkey, mint: Pubkey, vault_id: u64) -> Result<Self, ProgramError> {
+        Self::with_program_id(crate::id(), owner, mint, vault_id)
+    }
+
+    /// Client for a vault program deployed at `program_id`
+    pub fn with_program_id(
+        program_id: Pubkey,
+        owner: Pubkey,
+        mint: Pubkey,
+        vault_id: u64,
+    ) -> Result<Self, ProgramError> {
+        let (vault_state, _) = derive_vault_state_pda(&program_id, &owner, &mint, vault_id)?;
+        Ok(Self {
+            program_id,
+            owner,
+            mint,
+            vault_id,
+            vault_state,
+            create_token_accounts: false,
+        })
+    }
+
+    /// Prepend idempotent ATA creation to instructions that pay tokens out
+    pub fn create_token_accounts(mut self, enabled: bool) -> Self {
+        self.create_token_accounts = enabled;
+        self
+    }
+
+    /// Program id the instructions are addressed to
+    pub fn program_id(&self) -> Pubkey {
+        self.program_id
+    }
+
+    /// Vault owner
+    pub fn owner(&self) -> Pubkey {
+        self.owner
+    }
+
+    /// Token mint of the vault
+    pub fn mint(&self) -> Pubkey {
+        self.mint
+    }
+
+    /// Vault id distinguishing vaults with the same owner and mint
+    pub fn vault_id(&self) -> u64 {
+        self.vault_id
+    }
+
+    /// Vault state PDA
+    pub fn vault_state(&self) -> Pubkey {
+        self.vault_state
+    }
+
+    /// Associated token account of the vault state PDA, holding the deposited tokens
+    pub fn vault_token_account(&self) -> Pubkey {
+        get_associated_token_address(&self.vault_state, &self.mint)
+    }
+
+    /// Associated token account of `wallet` for the vault's mint
+    pub fn token_account(&self, wallet: &Pubkey) -> Pubkey {
+        get_associated_token_address(wallet, &self.mint)
+    }
+
+    /// User balance PDA of `user` in this vault
+    pub fn user_balance(&self, user: &Pubkey) -> Result<Pubkey, ProgramError> {
+        Ok(derive_user_balance_pda(&self.program_id, user, &self.vault_state)?.0)
+    }
+
+    /// Metadata PDA of this vault
+    pub fn metadata(&self) -> Result<Pubkey, ProgramError> {
+        Ok(derive_metadata_pda(&self.program_id, &self.vault_state)?.0)
+    }
+
+    /// `getProgramAccounts` config listing the depositors of this vault
+    pub fn depositors_config(&self) -> RpcProgramAccountsConfig {
+        RpcProgramAccountsConfig {
+            filters: Some(depositor_filters(&self.vault_state)),
+            ..RpcProgramAccountsConfig::default()
+        }
+    }
+
+    /// Aggregate `getProgramAccounts` results into a depositor table, checking each account
+    /// is a user balance of this vault
+    pub fn depositor_table<A: ReadableAccount>(
+        &self,
+        vault_state: &VaultState,
+        accounts: &[(Pubkey, A)],
+    ) -> Result<DepositorTable, &'static str> {
+        let mut depositors = Vec::with_capacity(accounts.len());
+        let (mut total_balance, mut total_pending) = (0u64, 0u64);
+        for (address, account) in accounts {
+            let decoded = VaultAccount::try_from_account_with_program_id(account, &self.program_id)?;
+            let balance = decoded.as_user_balance().ok_or("Account is not a user balance")?;
+            if balance.vault != self.vault_state {
+                return Err("User balance belongs to another vault");
+            }
+            total_balance = total_balance.checked_add(balance.balance).ok_or("Arithmetic overflow in total balance")?;
+            total_pending = total_pending
+                .checked_add(balance.pending_withdrawal)
+                .ok_or("Arithmetic overflow in total pending")?;
+            depositors.push(Depositor {
+                address: *address,
+                user: balance.user,
+                balance: balance.balance,
+                pending_withdrawal: balance.pending_withdrawal,
+            });
+        }
+        depositors.sort_by(|a, b| b.balance.cmp(&a.balance).then(a.user.cmp(&b.user)));
+
+        Ok(DepositorTable {
+            depositors,
+            total_balance,
+            total_pending,
+            total_deposited: vault_state.total_deposited,
+        })
+    }
+
+    /// Idempotently create the associated token account of `wallet`, paid by `payer`
+    pub fn create_token_account(&self, payer: &Pubkey, wallet: &Pubkey) -> Instruction {
+        create_associated_token_account_idempotent(payer, wallet, &self.mint, &spl_token::id())
+    }
+
+    /// Create the vault token account and initialize the vault. The owner pays for both.
+    pub fn initialize(&self, metadata: Option<VaultMetadataArgs>) -> Result<Vec<Instruction>, ProgramError> {
+        let create_vault_token_account = self.create_token_account(&self.owner, &self.vault_state);
+        let initialize = match metadata {
+            Some(metadata) => VaultInstruction::initialize_with_metadata(
+                &self.program_id,
+                &self.owner,
+                &self.vault_state,
+                &self.vault_token_account(),
+                &self.mint,
+                &self.metadata()?,
+                self.vault_id,
+                metadata,
+            ),
+            None => VaultInstruction::initialize(
+                &self.program_id,
+                &self.owner,
+                &self.vault_state,
+                &self.vault_token_account(),
+                &self.mint,
+                self.vault_id,
+            ),
+        };
+        Ok(vec![create_vault_token_account, initialize])
+    }
+
+    /// Deposit `amount` from the user's associated token account
+    pub fn deposit(&self, user: &Pubkey, amount: u64) -> Result<Vec<Instruction>, ProgramError> {
+        Ok(vec![VaultInstruction::deposit(
+            &self.program_id,
+            user,
+            &self.token_account(user),
+            &self.vault_token_account(),
+            &self.vault_state,
+            &self.user_balance(user)?,
+            amount,
+        )])
+    }
+
+    /// Withdraw `amount` to the user's associated token account
+    pub fn withdraw(&self, user: &Pubkey, amount: u64) -> Result<Vec<Instruction>, ProgramError> {
+        let withdraw = VaultInstruction::withdraw(
+            &self.program_id,
+            user,
+            &self.token_account(user),
+            &self.vault_token_account(),
+            &self.vault_state,
+            &self.user_balance(user)?,
+            amount,
+        );
+        Ok(self.with_token_account(user, user, withdraw))
+    }
+
+    /// Claim the user's queued withdrawal to their associated token account
+    pub fn claim_withdrawal(&self, user: &Pubkey) -> Result<Vec<Instruction>, ProgramError> {
+        let claim = VaultInstruction::claim_withdrawal(
+            &self.program_id,
+            user,
+            &self.token_account(user),
+            &self.vault_token_account(),
+            &self.vault_state,
+            &self.user_balance(user)?,
+        );
+        Ok(self.with_token_account(user, user, claim))
+    }
+
+    /// Withdraw every token in the vault to the owner's associated token account
+    pub fn withdraw_all(&self) -> Vec<Instruction> {
+        let withdraw_all = VaultInstruction::withdraw_all(
+            &self.program_id,
+            &self.owner,
+            &self.token_account(&self.owner),
+            &self.vault_token_account(),
+            &self.vault_state,
+        );
+        self.with_token_account(&self.owner, &self.owner, withdraw_all)
+    }
+
+    /// Close the vault, sending the remaining tokens to the owner's associated token account
+    pub fn close(&self) -> Vec<Instruction> {
+        let close = VaultInstruction::close(
+            &self.program_id,
+            &self.owner,
+            &self.token_account(&self.owner),
+            &self.vault_token_account(),
+            &self.vault_state,
+        );
+        self.with_token_account(&self.owner, &self.owner, close)
+    }
+
+    /// Move `amount` of the sender's balance to `to` without moving tokens
+    pub fn transfer_balance(&self, sender: &Pubkey, to: &Pubkey, amount: u64) -> Result<Instruction, ProgramError> {
+        Ok(VaultInstruction::transfer_balance(
+            &self.program_id,
+            sender,
+            &self.vault_state,
+            &self.user_balance(sender)?,
+            &self.user_balance(to)?,
+            to,
+            amount,
+        ))
+    }
+
+    /// Replace the vault's display metadata
+    pub fn update_metadata(&self, metadata: VaultMetadataArgs) -> Result<Instruction, ProgramError> {
+        Ok(VaultInstruction::update_metadata(
+            &self.program_id,
+            &self.owner,
+            &self.vault_state,
+            &self.metadata()?,
+            &self.mint,
+            metadata,
+        ))
+    }
+
+    /// Prefix `instruction` with the creation of `wallet`'s token account when enabled
+    fn with_token_account(&self, payer: &Pubkey, wallet: &Pubkey, instruction: Instruction) -> Vec<Instruction> {
+        if self.create_token_accounts {
+            vec![self.create_token_account(payer, wallet), instruction]
+        } else {
+            vec![instruction]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolves_accounts() {
+        let owner = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let user = Pubkey::new_unique();
+        let client = VaultClient::new(owner, mint, 3).unwrap();
+
+        let (vault_state, _) = derive_vault_state_pda(&crate::id(), &owner, &mint, 3).unwrap();
+        assert_eq!(client.vault_state(), vault_state);
+
+        let deposit = client.deposit(&user, 10).unwrap();
+        assert_eq!(deposit.len(), 1);
+        let keys: Vec<Pubkey> = deposit[0].accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(keys[0], user);
+        assert_eq!(keys[1], get_associated_token_address(&user, &mint));
+        assert_eq!(keys[2], get_associated_token_address(&vault_state, &mint));
+        assert_eq!(keys[3], vault_state);
+        assert_eq!(keys[4], derive_user_balance_pda(&crate::id(), &user, &vault_state).unwrap().0);
+
+        // Payouts get an ATA pre-instruction only when enabled
+        assert_eq!(client.withdraw(&user, 10).unwrap().len(), 1);
+        let client = client.create_token_accounts(true);
+        let withdraw = client.withdraw(&user, 10).unwrap();
+        assert_eq!(withdraw.len(), 2);
+        assert_eq!(withdraw[0].program_id, spl_associated_token_account::id());
+        assert_eq!(withdraw[1].program_id, crate::id());
+    }
+}
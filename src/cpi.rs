@@ -0,0 +1,119 @@
+//! Cross-program invocation helpers for programs that call the vault.
+//!
+//! Each helper takes the accounts of one instruction, builds it with the matching
+//! [`VaultInstruction`] builder so the account order always matches the processor,
+//! and invokes the vault program. Pass `signer_seeds` when the user is a PDA of the
+//! calling program (e.g. a DAO treasury); pass `&[]` when the user signed the
+//! outer transaction.
+//!
+//! Depositing creates the user balance PDA on first use, paid by the user, so a
+//! PDA user must be a system account holding enough lamports for its rent.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::Instruction,
+    program::{invoke, invoke_signed},
+};
+
+use crate::instruction::VaultInstruction;
+
+/// Accounts of `Deposit`
+pub struct DepositAccounts<'info> {
+    pub vault_program: AccountInfo<'info>,
+    pub user: AccountInfo<'info>,
+    pub user_token_account: AccountInfo<'info>,
+    pub vault_token_account: AccountInfo<'info>,
+    pub vault_state: AccountInfo<'info>,
+    pub user_balance: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub system_program: AccountInfo<'info>,
+}
+
+/// Accounts of `Withdraw` and `ClaimWithdrawal`
+pub struct WithdrawAccounts<'info> {
+    pub vault_program: AccountInfo<'info>,
+    pub user: AccountInfo<'info>,
+    pub user_token_account: AccountInfo<'info>,
+    pub vault_token_account: AccountInfo<'info>,
+    pub vault_state: AccountInfo<'info>,
+    pub user_balance: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+}
+
+impl<'info> WithdrawAccounts<'info> {
+    fn to_account_infos(&self) -> Vec<AccountInfo<'info>> {
+        vec![
+            self.user.clone(),
+            self.user_token_account.clone(),
+            self.vault_token_account.clone(),
+            self.vault_state.clone(),
+            self.user_balance.clone(),
+            self.token_program.clone(),
+            self.vault_program.clone(),
+        ]
+    }
+}
+
+/// Deposit `amount` from `user_token_account` into the vault, crediting `user`
+pub fn deposit(accounts: DepositAccounts, amount: u64, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    let instruction = VaultInstruction::deposit(
+        accounts.vault_program.key,
+        accounts.user.key,
+        accounts.user_token_account.key,
+        accounts.vault_token_account.key,
+        accounts.vault_state.key,
+        accounts.user_balance.key,
+        amount,
+    );
+    invoke_vault(
+        &instruction,
+        &[
+            accounts.user,
+            accounts.user_token_account,
+            accounts.vault_token_account,
+            accounts.vault_state,
+            accounts.user_balance,
+            accounts.token_program,
+            accounts.system_program,
+            accounts.vault_program,
+        ],
+        signer_seeds,
+    )
+}
+
+/// Withdraw `amount` of `user`'s balance to `user_token_account`. The vault queues the
+/// withdrawal instead when it lacks idle liquidity; see [`claim_withdrawal`].
+pub fn withdraw(accounts: WithdrawAccounts, amount: u64, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    let instruction = VaultInstruction::withdraw(
+        accounts.vault_program.key,
+        accounts.user.key,
+        accounts.user_token_account.key,
+        accounts.vault_token_account.key,
+        accounts.vault_state.key,
+        accounts.user_balance.key,
+        amount,
+    );
+    invoke_vault(&instruction, &accounts.to_account_infos(), signer_seeds)
+}
+
+/// Pay out `user`'s queued withdrawal to `user_token_account`
+pub fn claim_withdrawal(accounts: WithdrawAccounts, signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    let instruction = VaultInstruction::claim_withdrawal(
+        accounts.vault_program.key,
+        accounts.user.key,
+        accounts.user_token_account.key,
+        accounts.vault_token_account.key,
+        accounts.vault_state.key,
+        accounts.user_balance.key,
+    );
+    invoke_vault(&instruction, &accounts.to_account_infos(), signer_seeds)
+}
+
+fn invoke_vault(instruction: &Instruction, account_infos: &[AccountInfo], signer_seeds: &[&[&[u8]]]) -> ProgramResult {
+    if signer_seeds.is_empty() {
+        invoke(instruction, account_infos)
+    } else {
+        invoke_signed(instruction, account_infos, signer_seeds)
+    }
+}
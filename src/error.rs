@@ -1,49 +1,269 @@
 use solana_program::{
+    account_info::AccountInfo,
     decode_error::DecodeError,
     msg,
     program_error::{PrintProgramError, ProgramError},
+    pubkey::Pubkey,
 };
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
 use thiserror::Error;
 
-/// Custom error types for the vault program
-#[derive(Error, Debug, Copy, Clone)]
+/// Custom error types for the vault program.
+///
+/// Each variant is returned as `ProgramError::Custom(code)` with the explicit
+/// discriminant as its code. Codes are part of the program's interface: never
+/// renumber or reuse one, only append new variants.
+#[derive(Error, Debug, Copy, Clone, PartialEq, Eq, FromPrimitive)]
 pub enum VaultError {
     /// Insufficient funds for withdrawal
     #[error("Insufficient funds")]
-    InsufficientFunds,
+    InsufficientFunds = 0,
     
     /// Unauthorized access attempt
     #[error("Unauthorized access")]
-    UnauthorizedAccess,
+    UnauthorizedAccess = 1,
     
     /// Invalid input parameters
     #[error("Invalid input")]
-    InvalidInput,
+    InvalidInput = 2,
     
     /// Vault is closed
     #[error("Vault is closed")]
-    VaultClosed,
+    VaultClosed = 3,
     
     /// Invalid token account
     #[error("Invalid token account")]
-    InvalidTokenAccount,
+    InvalidTokenAccount = 4,
     
     /// Invalid mint
     #[error("Invalid mint")]
-    InvalidMint,
+    InvalidMint = 5,
     
     /// Arithmetic overflow
     #[error("Arithmetic overflow")]
-    ArithmeticOverflow,
+    ArithmeticOverflow = 6,
     
     /// Account not initialized
     #[error("Account not initialized")]
-    AccountNotInitialized,
+    AccountNotInitialized = 7,
+
+    /// Delegate allowance too small for the requested withdrawal
+    #[error("Insufficient delegate allowance")]
+    InsufficientAllowance = 8,
+
+    /// Withdrawal permit signature, message or nonce is invalid
+    #[error("Invalid permit")]
+    InvalidPermit = 9,
+
+    /// Withdrawal permit is past its expiry
+    #[error("Permit expired")]
+    PermitExpired = 10,
+
+    /// Flash loan was not repaid with the fee by the end of the callback
+    #[error("Flash loan not repaid")]
+    FlashLoanNotRepaid = 11,
+
+    /// Vault is locked by an in-flight flash loan
+    #[error("Vault is locked")]
+    VaultLocked = 12,
+
+    /// Strategy is not registered, already registered, or the registry is full
+    #[error("Invalid strategy")]
+    InvalidStrategy = 13,
+
+    /// Allocation would exceed the strategy's cap
+    #[error("Strategy cap exceeded")]
+    StrategyCapExceeded = 14,
+
+    /// Allocation would leave less idle liquidity than the vault requires
+    #[error("Liquidity buffer breached")]
+    LiquidityBufferBreached = 15,
+
+    /// Account data does not carry the discriminator of the expected account type
+    #[error("Invalid account discriminator")]
+    InvalidAccountDiscriminator = 16,
+
+    /// Account layout version is not supported by this program
+    #[error("Unsupported account version")]
+    UnsupportedAccountVersion = 17,
+
+    /// Metadata name or URI is empty, too long or not printable UTF-8
+    #[error("Invalid metadata")]
+    InvalidMetadata = 18,
+
+    /// Fewer accounts were passed than the instruction requires
+    #[error("Not enough account keys")]
+    NotEnoughAccounts = 19,
+
+    /// Account is not owned by the expected program
+    #[error("Invalid account owner")]
+    InvalidAccountOwner = 20,
+
+    /// Account must be writable
+    #[error("Account not writable")]
+    AccountNotWritable = 21,
+
+    /// Account address does not match the expected program derived address
+    #[error("Invalid PDA")]
+    InvalidPda = 22,
+
+    /// Amount must be greater than zero
+    #[error("Amount must be greater than zero")]
+    ZeroAmount = 23,
+
+    /// System, token or callback program account is not the expected program
+    #[error("Invalid program id")]
+    InvalidProgramId = 24,
+
+    /// Sysvar account is not the expected sysvar
+    #[error("Invalid sysvar")]
+    InvalidSysvar = 25,
+
+    /// Account data failed validation or does not fit the account
+    #[error("Invalid account data")]
+    InvalidAccountData = 26,
+
+    /// Account is already initialized
+    #[error("Account already initialized")]
+    AccountAlreadyInitialized = 27,
+
+    /// Account does not hold enough lamports to be rent exempt
+    #[error("Not rent exempt")]
+    NotRentExempt = 28,
+
+    /// There is no queued withdrawal to claim
+    #[error("No pending withdrawal")]
+    NoPendingWithdrawal = 29,
+
+    /// Instruction data is empty or cannot be decoded
+    #[error("Invalid instruction data")]
+    InvalidInstructionData = 30,
+
+    /// Basket still holds deposits of at least one mint
+    #[error("Basket not empty")]
+    BasketNotEmpty = 31,
+
+    /// Idle liquidity not reserved for queued withdrawals cannot cover the amount
+    #[error("Insufficient liquidity")]
+    InsufficientLiquidity = 32,
+}
+
+impl VaultError {
+    /// Stable error code, as carried by `ProgramError::Custom`
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    /// Error for a custom error code, if it is one of ours
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::from_u32(code)
+    }
+
+    /// Vault error carried by a program error, if any
+    pub fn from_program_error(error: &ProgramError) -> Option<Self> {
+        match error {
+            ProgramError::Custom(code) => Self::from_code(*code),
+            _ => None,
+        }
+    }
+
+    /// Index of the failed instruction and the vault error it returned, if a
+    /// transaction failed with a custom error code of ours.
+    ///
+    /// Custom codes are not namespaced by program: only trust the result when the
+    /// instruction at the returned index targets the vault program.
+    #[cfg(feature = "client")]
+    pub fn from_transaction_error(error: &solana_sdk::transaction::TransactionError) -> Option<(u8, Self)> {
+        use solana_program::instruction::InstructionError;
+        use solana_sdk::transaction::TransactionError;
+
+        match error {
+            TransactionError::InstructionError(index, InstructionError::Custom(code)) => {
+                Self::from_code(*code).map(|error| (*index, error))
+            }
+            _ => None,
+        }
+    }
 }
 
 impl From<VaultError> for ProgramError {
     fn from(e: VaultError) -> Self {
-        ProgramError::Custom(e as u32)
+        ProgramError::Custom(e.code())
+    }
+}
+
+/// Error returned by the instruction processors: the program error, plus the
+/// instruction account it concerns when the failure is about one account.
+///
+/// The entrypoint logs both through [`ProcessError::log`], resolving the account
+/// to its index in the instruction, and returns the bare program error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessError {
+    pub error: ProgramError,
+    pub account: Option<Pubkey>,
+}
+
+/// Result of an instruction processor
+pub type ProcessResult<T = ()> = Result<T, ProcessError>;
+
+impl ProcessError {
+    /// Attribute the error to `account`, keeping an account set closer to the failure
+    pub fn with_account(self, account: &Pubkey) -> Self {
+        Self {
+            account: self.account.or(Some(*account)),
+            ..self
+        }
+    }
+
+    /// Log the decoded error and the failing account, as `#index` within `accounts`
+    pub fn log(&self, accounts: &[AccountInfo]) {
+        if !crate::logging::ERROR_ENABLED {
+            return;
+        }
+        self.error.print::<VaultError>();
+        if let Some(account) = &self.account {
+            match accounts.iter().position(|info| info.key == account) {
+                Some(index) => msg!("Error: Failed account #{}: {}", index, account),
+                None => msg!("Error: Failed account: {}", account),
+            }
+        }
+    }
+}
+
+impl VaultError {
+    /// This error, attributed to `account`
+    pub fn with_account(self, account: &Pubkey) -> ProcessError {
+        ProcessError::from(self).with_account(account)
+    }
+}
+
+/// Attribute the error of a result to an account, e.g. a failed load of its data
+pub trait AccountContext<T> {
+    fn with_account(self, account: &Pubkey) -> ProcessResult<T>;
+}
+
+impl<T, E: Into<ProcessError>> AccountContext<T> for Result<T, E> {
+    fn with_account(self, account: &Pubkey) -> ProcessResult<T> {
+        self.map_err(|error| error.into().with_account(account))
+    }
+}
+
+impl From<ProgramError> for ProcessError {
+    fn from(error: ProgramError) -> Self {
+        Self { error, account: None }
+    }
+}
+
+impl From<VaultError> for ProcessError {
+    fn from(error: VaultError) -> Self {
+        ProgramError::from(error).into()
+    }
+}
+
+impl From<ProcessError> for ProgramError {
+    fn from(error: ProcessError) -> Self {
+        error.error
     }
 }
 
@@ -64,6 +284,87 @@ impl PrintProgramError for VaultError {
             VaultError::InvalidMint => msg!("Error: Invalid mint provided"),
             VaultError::ArithmeticOverflow => msg!("Error: Arithmetic overflow occurred"),
             VaultError::AccountNotInitialized => msg!("Error: Account not properly initialized"),
+            VaultError::InsufficientAllowance => msg!("Error: Delegate allowance exceeded"),
+            VaultError::InvalidPermit => msg!("Error: Withdrawal permit signature, message or nonce is invalid"),
+            VaultError::PermitExpired => msg!("Error: Withdrawal permit has expired"),
+            VaultError::FlashLoanNotRepaid => msg!("Error: Flash loan was not repaid with its fee"),
+            VaultError::VaultLocked => msg!("Error: Vault is locked by an in-flight flash loan"),
+            VaultError::InvalidStrategy => msg!("Error: Strategy is not registered or cannot be registered"),
+            VaultError::StrategyCapExceeded => msg!("Error: Allocation exceeds the strategy cap"),
+            VaultError::LiquidityBufferBreached => msg!("Error: Allocation would breach the minimum idle liquidity"),
+            VaultError::InvalidAccountDiscriminator => msg!("Error: Account is not of the expected type"),
+            VaultError::UnsupportedAccountVersion => msg!("Error: Account layout version is not supported"),
+            VaultError::InvalidMetadata => msg!("Error: Metadata name or URI is invalid"),
+            VaultError::NotEnoughAccounts => msg!("Error: Not enough account keys provided"),
+            VaultError::InvalidAccountOwner => msg!("Error: Account is not owned by the expected program"),
+            VaultError::AccountNotWritable => msg!("Error: Account must be writable"),
+            VaultError::InvalidPda => msg!("Error: Account does not match the expected program derived address"),
+            VaultError::ZeroAmount => msg!("Error: Amount must be greater than zero"),
+            VaultError::InvalidProgramId => msg!("Error: Program account is not the expected program"),
+            VaultError::InvalidSysvar => msg!("Error: Sysvar account is not the expected sysvar"),
+            VaultError::InvalidAccountData => msg!("Error: Account data is invalid or does not fit the account"),
+            VaultError::AccountAlreadyInitialized => msg!("Error: Account is already initialized"),
+            VaultError::NotRentExempt => msg!("Error: Account is not rent exempt"),
+            VaultError::NoPendingWithdrawal => msg!("Error: No pending withdrawal to claim"),
+            VaultError::InvalidInstructionData => msg!("Error: Instruction data is empty or malformed"),
+            VaultError::BasketNotEmpty => msg!("Error: Basket still holds deposits"),
+            VaultError::InsufficientLiquidity => msg!("Error: Not enough idle liquidity to pay out now"),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_are_stable() {
+        assert_eq!(VaultError::InsufficientFunds.code(), 0);
+        assert_eq!(VaultError::InvalidInput.code(), 2);
+        assert_eq!(VaultError::InvalidMetadata.code(), 18);
+        assert_eq!(VaultError::InvalidInstructionData.code(), 30);
+        assert_eq!(VaultError::BasketNotEmpty.code(), 31);
+        assert_eq!(VaultError::InsufficientLiquidity.code(), 32);
+        assert_eq!(ProgramError::from(VaultError::ZeroAmount), ProgramError::Custom(23));
+
+        for code in 0..=VaultError::InsufficientLiquidity.code() {
+            let error = VaultError::from_code(code).unwrap();
+            assert_eq!(error.code(), code);
+        }
+        assert_eq!(VaultError::from_code(VaultError::InsufficientLiquidity.code() + 1), None);
+        assert_eq!(
+            VaultError::from_program_error(&ProgramError::Custom(4)),
+            Some(VaultError::InvalidTokenAccount)
+        );
+        assert_eq!(VaultError::from_program_error(&ProgramError::InvalidArgument), None);
+    }
+
+    #[test]
+    fn test_process_error_keeps_innermost_account() {
+        let inner = Pubkey::new_unique();
+        let outer = Pubkey::new_unique();
+        let result: ProcessResult = Err(VaultError::InvalidAccountData.with_account(&inner));
+        let error = result.with_account(&outer).unwrap_err();
+        assert_eq!(error.account, Some(inner));
+        assert_eq!(ProgramError::from(error), ProgramError::Custom(26));
+
+        let unattributed: Result<(), ProgramError> = Err(ProgramError::InvalidArgument);
+        assert_eq!(unattributed.with_account(&outer).unwrap_err().account, Some(outer));
+    }
+
+    #[cfg(feature = "client")]
+    #[test]
+    fn test_from_transaction_error() {
+        use solana_program::instruction::InstructionError;
+        use solana_sdk::transaction::TransactionError;
+
+        let error = TransactionError::InstructionError(1, InstructionError::Custom(29));
+        let (index, vault_error) = VaultError::from_transaction_error(&error).unwrap();
+        assert_eq!((index, vault_error), (1, VaultError::NoPendingWithdrawal));
+        assert_eq!(vault_error.to_string(), "No pending withdrawal");
+
+        let not_custom = TransactionError::InstructionError(0, InstructionError::InvalidArgument);
+        assert_eq!(VaultError::from_transaction_error(&not_custom), None);
+        assert_eq!(VaultError::from_transaction_error(&TransactionError::AccountNotFound), None);
+    }
+}
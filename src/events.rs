@@ -0,0 +1,163 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{log::sol_log_data, pubkey::Pubkey};
+
+/// Discriminator prefixed to every serialized vault event
+pub const EVENT_DISCRIMINATOR: [u8; 8] = *b"vaultevt";
+
+/// Prefix the runtime puts in front of `sol_log_data` output in transaction logs
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+/// Structured events emitted by the vault program via `sol_log_data`.
+/// New variants must be appended at the end to keep existing tags stable.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum VaultEvent {
+    /// A vault was created
+    Initialized { vault: Pubkey, owner: Pubkey, mint: Pubkey, vault_id: u64 },
+    /// Tokens were deposited and credited to `user` (the depositor differs for DepositFor)
+    Deposited { vault: Pubkey, user: Pubkey, depositor: Pubkey, amount: u64, balance: u64 },
+    /// A user withdrew tokens
+    Withdrawn { vault: Pubkey, user: Pubkey, amount: u64, balance: u64 },
+    /// The owner withdrew every token in the vault
+    WithdrewAll { vault: Pubkey, owner: Pubkey, amount: u64 },
+    /// The owner closed the vault
+    Closed { vault: Pubkey, owner: Pubkey, remaining: u64 },
+    /// A user approved a delegate
+    DelegateApproved { vault: Pubkey, user: Pubkey, delegate: Pubkey, amount: u64 },
+    /// A user revoked their delegate
+    DelegateRevoked { vault: Pubkey, user: Pubkey },
+    /// A delegate withdrew from a user's balance
+    WithdrewAsDelegate { vault: Pubkey, user: Pubkey, delegate: Pubkey, amount: u64, remaining_allowance: u64 },
+    /// Balance moved between two users of the same vault
+    BalanceTransferred { vault: Pubkey, from: Pubkey, to: Pubkey, amount: u64 },
+    /// A relayer executed a signed withdrawal permit
+    WithdrewWithPermit { vault: Pubkey, user: Pubkey, relayer: Pubkey, amount: u64, nonce: u64 },
+    /// A basket was created
+    BasketInitialized { basket: Pubkey, owner: Pubkey, basket_id: u64 },
+    /// A mint was added to a basket
+    BasketMintAdded { basket: Pubkey, mint: Pubkey },
+    /// Tokens of one basket mint were deposited
+    BasketDeposited { basket: Pubkey, user: Pubkey, mint: Pubkey, amount: u64, balance: u64 },
+    /// Tokens of one basket mint were withdrawn
+    BasketWithdrawn { basket: Pubkey, user: Pubkey, mint: Pubkey, amount: u64, balance: u64 },
+    /// A flash loan was taken and repaid
+    FlashLoan { vault: Pubkey, borrower_program: Pubkey, amount: u64, fee: u64 },
+    /// A strategy adapter was registered
+    StrategyAdded { vault: Pubkey, strategy: Pubkey, index: u8, cap: u64 },
+    /// Tokens were allocated to a strategy
+    Allocated { vault: Pubkey, strategy: Pubkey, amount: u64, total_assets: u64 },
+    /// Tokens were pulled back from a strategy
+    Deallocated { vault: Pubkey, strategy: Pubkey, amount: u64, total_assets: u64 },
+    /// A strategy reported its balance; `distributed` is the gain credited to depositors
+    Harvested { vault: Pubkey, strategy: Pubkey, previous_balance: u64, reported_balance: u64, distributed: u64 },
+    /// The owner changed the minimum idle ratio
+    MinIdleBpsSet { vault: Pubkey, min_idle_bps: u16 },
+    /// A withdrawal was queued for lack of idle liquidity
+    WithdrawalQueued { vault: Pubkey, user: Pubkey, amount: u64, pending: u64 },
+    /// A queued withdrawal was paid out
+    WithdrawalClaimed { vault: Pubkey, user: Pubkey, amount: u64 },
+    /// A program account was upgraded to the current layout
+    AccountMigrated { account: Pubkey, from_version: u8, to_version: u8 },
+    /// The owner set the vault's display metadata
+    MetadataUpdated { vault: Pubkey, name: String, uri: String },
+    /// The owner closed an empty basket
+    BasketClosed { basket: Pubkey, owner: Pubkey },
+}
+
+impl VaultEvent {
+    /// Serialize the event with its discriminator
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = EVENT_DISCRIMINATOR.to_vec();
+        data.extend_from_slice(&self.try_to_vec().unwrap());
+        data
+    }
+
+    /// Parse bytes produced by `to_bytes`, returning `None` for non-vault data
+    pub fn try_from_bytes(data: &[u8]) -> Option<Self> {
+        let payload = data.strip_prefix(&EVENT_DISCRIMINATOR[..])?;
+        Self::try_from_slice(payload).ok()
+    }
+
+    /// Emit the event to the transaction log
+    pub fn emit(&self) {
+        sol_log_data(&[&self.to_bytes()]);
+    }
+
+    /// Decode a single `Program data: ...` log line
+    pub fn from_log_line(line: &str) -> Option<Self> {
+        let encoded = line.strip_prefix(PROGRAM_DATA_PREFIX)?.split_whitespace().next()?;
+        let data = STANDARD.decode(encoded).ok()?;
+        Self::try_from_bytes(&data)
+    }
+}
+
+/// Decode every vault event emitted by `program_id` in a transaction's log messages.
+/// Program invoke/success/failed lines are tracked so data logged by other
+/// programs (including CPI callees) is ignored.
+pub fn decode_logs<S: AsRef<str>>(program_id: &Pubkey, logs: &[S]) -> Vec<VaultEvent> {
+    let program_id = program_id.to_string();
+    let mut invocation_stack: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs {
+        let line = line.as_ref();
+        if line.starts_with(PROGRAM_DATA_PREFIX) {
+            if invocation_stack.last() == Some(&program_id.as_str()) {
+                events.extend(VaultEvent::from_log_line(line));
+            }
+        } else if let Some(rest) = line.strip_prefix("Program ") {
+            let mut parts = rest.split_whitespace();
+            match (parts.next(), parts.next()) {
+                (Some(program), Some("invoke")) => invocation_stack.push(program),
+                (Some(_), Some("success")) | (Some(_), Some("failed:")) => {
+                    invocation_stack.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_log_line_roundtrip() {
+        let event = VaultEvent::Deposited {
+            vault: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            depositor: Pubkey::new_unique(),
+            amount: 100,
+            balance: 250,
+        };
+        let line = format!("{}{}", PROGRAM_DATA_PREFIX, STANDARD.encode(event.to_bytes()));
+        assert_eq!(VaultEvent::from_log_line(&line), Some(event));
+        assert_eq!(VaultEvent::from_log_line("Program log: Deposit successful"), None);
+        assert_eq!(VaultEvent::try_from_bytes(b"not an event"), None);
+    }
+
+    #[test]
+    fn test_decode_logs_ignores_other_programs() {
+        let program_id = Pubkey::new_unique();
+        let other_program = Pubkey::new_unique();
+        let vault = Pubkey::new_unique();
+        let ours = VaultEvent::DelegateRevoked { vault, user: Pubkey::new_unique() };
+        let theirs = VaultEvent::Closed { vault, owner: Pubkey::new_unique(), remaining: 0 };
+        let data_line = |event: &VaultEvent| format!("{}{}", PROGRAM_DATA_PREFIX, STANDARD.encode(event.to_bytes()));
+
+        let logs = vec![
+            format!("Program {} invoke [1]", program_id),
+            format!("Program {} invoke [2]", other_program),
+            data_line(&theirs),
+            format!("Program {} success", other_program),
+            data_line(&ours),
+            format!("Program {} consumed 1000 of 200000 compute units", program_id),
+            format!("Program {} success", program_id),
+        ];
+
+        assert_eq!(decode_logs(&program_id, &logs), vec![ours]);
+    }
+}
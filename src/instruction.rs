@@ -1,5 +1,6 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_program::{
+    ed25519_program,
     instruction::{AccountMeta, Instruction},
     program_error::ProgramError,
     pubkey::Pubkey,
@@ -7,12 +8,21 @@ use solana_program::{
     sysvar,
 };
 
-use crate::error::VaultError;
+use crate::{error::VaultError, utils::ED25519_OFFSETS_HEADER_LEN};
+
+/// Display metadata set at `Initialize` or with `UpdateMetadata`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct VaultMetadataArgs {
+    /// Display name, 1 to 32 bytes
+    pub name: String,
+    /// URI of off-chain details, up to 200 bytes
+    pub uri: String,
+}
 
 /// Instructions supported by the vault program
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
 pub enum VaultInstruction {
-    /// Initialize a new vault
+    /// Initialize a new vault, optionally with its display metadata
     /// 
     /// Accounts expected:
     /// 0. [signer, writable] Vault owner
@@ -22,7 +32,8 @@ pub enum VaultInstruction {
     /// 4. [] SPL Token program
     /// 5. [] System program
     /// 6. [] Rent sysvar
-    Initialize,
+    /// 7. [writable] Vault metadata account (PDA), only when `metadata` is set
+    Initialize { vault_id: u64, metadata: Option<VaultMetadataArgs> },
 
     /// Deposit SPL tokens into the vault
     /// 
@@ -66,6 +77,285 @@ pub enum VaultInstruction {
     /// 3. [writable] Vault state account
     /// 4. [] SPL Token program
     Close,
+
+    /// Approve a delegate to withdraw up to `amount` from the user's balance
+    ///
+    /// Accounts expected:
+    /// 0. [signer] User account
+    /// 1. [] Vault state account
+    /// 2. [writable] User balance account (PDA)
+    ApproveDelegate { delegate: Pubkey, amount: u64 },
+
+    /// Revoke the delegate on the user's balance
+    ///
+    /// Accounts expected:
+    /// 0. [signer] User account
+    /// 1. [] Vault state account
+    /// 2. [writable] User balance account (PDA)
+    RevokeDelegate,
+
+    /// Withdraw SPL tokens from another user's balance as their approved delegate.
+    /// Fails with `InsufficientLiquidity`, leaving the allowance unspent, when idle
+    /// liquidity cannot pay the destination now; delegate withdrawals are never queued.
+    ///
+    /// Accounts expected:
+    /// 0. [signer] Delegate account
+    /// 1. [writable] Destination token account
+    /// 2. [writable] Vault token account
+    /// 3. [writable] Vault state account
+    /// 4. [writable] User balance account (PDA) of the delegating user
+    /// 5. [] SPL Token program
+    WithdrawAsDelegate { amount: u64 },
+
+    /// Move part of the signer's vault balance to another user
+    ///
+    /// Accounts expected:
+    /// 0. [signer, writable] Sender account (pays rent if the recipient balance is created)
+    /// 1. [writable] Vault state account
+    /// 2. [writable] Sender balance account (PDA)
+    /// 3. [writable] Recipient balance account (PDA)
+    /// 4. [] System program (for PDA creation if needed)
+    TransferBalance { to: Pubkey, amount: u64 },
+
+    /// Deposit SPL tokens into the vault, crediting another user's balance
+    ///
+    /// Accounts expected:
+    /// 0. [signer, writable] Depositor account (token source authority and rent payer)
+    /// 1. [writable] Depositor token account
+    /// 2. [writable] Vault token account
+    /// 3. [writable] Vault state account
+    /// 4. [writable] Beneficiary balance account (PDA)
+    /// 5. [] SPL Token program
+    /// 6. [] System program (for PDA creation if needed)
+    DepositFor { beneficiary: Pubkey, amount: u64 },
+
+    /// Withdraw on behalf of a user who signed an off-chain permit.
+    /// The preceding instruction in the transaction must be an ed25519 signature
+    /// verification of `utils::withdraw_permit_message` by the user.
+    /// Without enough idle liquidity the amount is queued for the user to claim.
+    ///
+    /// Accounts expected:
+    /// 0. [signer] Relayer account (pays transaction fees)
+    /// 1. [writable] Destination token account named in the permit
+    /// 2. [writable] Vault token account
+    /// 3. [writable] Vault state account
+    /// 4. [writable] User balance account (PDA) of the permit signer
+    /// 5. [] SPL Token program
+    /// 6. [] Instructions sysvar
+    WithdrawWithPermit { amount: u64, nonce: u64, expiry: i64 },
+
+    /// Initialize a new multi-mint basket vault
+    ///
+    /// Accounts expected:
+    /// 0. [signer, writable] Basket owner
+    /// 1. [writable] Basket state account (PDA)
+    /// 2. [] System program
+    InitializeBasket { basket_id: u64 },
+
+    /// Add a mint to the basket, creating the basket's PDA token account for it (owner only)
+    ///
+    /// Accounts expected:
+    /// 0. [signer, writable] Basket owner
+    /// 1. [writable] Basket state account (PDA)
+    /// 2. [] Token mint
+    /// 3. [writable] Basket token account (PDA) for the mint
+    /// 4. [] SPL Token program
+    /// 5. [] System program
+    AddBasketMint,
+
+    /// Deposit SPL tokens of any basket mint into the basket
+    ///
+    /// Accounts expected:
+    /// 0. [signer, writable] User account
+    /// 1. [writable] User token account
+    /// 2. [writable] Basket token account (PDA) for the mint
+    /// 3. [writable] Basket state account
+    /// 4. [writable] Basket balance account (PDA) for the user and mint
+    /// 5. [] SPL Token program
+    /// 6. [] System program (for PDA creation if needed)
+    BasketDeposit { amount: u64 },
+
+    /// Withdraw SPL tokens of one basket mint
+    ///
+    /// Accounts expected:
+    /// 0. [signer] User account
+    /// 1. [writable] User token account
+    /// 2. [writable] Basket token account (PDA) for the mint
+    /// 3. [writable] Basket state account
+    /// 4. [writable] Basket balance account (PDA) for the user and mint
+    /// 5. [] SPL Token program
+    BasketWithdraw { amount: u64 },
+
+    /// Lend vault liquidity for the duration of a borrower program callback.
+    /// The vault token account must hold at least `amount + fee` more than
+    /// before the loan once the callback returns.
+    ///
+    /// Accounts expected:
+    /// 0. [writable] Vault state account
+    /// 1. [writable] Vault token account
+    /// 2. [writable] Borrower token account (receives the loan)
+    /// 3. [] Borrower program (invoked with `FlashLoanCallback`)
+    /// 4. [] SPL Token program
+    /// 5. [] Any additional accounts, forwarded to the borrower program
+    FlashLoan { amount: u64 },
+
+    /// Register a strategy adapter program with an allocation cap (owner only)
+    ///
+    /// Accounts expected:
+    /// 0. [signer] Vault owner
+    /// 1. [writable] Vault state account
+    /// 2. [] Strategy adapter program
+    AddStrategy { cap: u64 },
+
+    /// Move idle vault tokens into a registered strategy (owner only)
+    ///
+    /// Accounts expected:
+    /// 0. [signer] Vault owner
+    /// 1. [writable] Vault state account
+    /// 2. [writable] Vault token account
+    /// 3. [writable] Strategy token account
+    /// 4. [] Strategy adapter program
+    /// 5. [] SPL Token program
+    /// 6. [] Any additional accounts, forwarded to the adapter
+    Allocate { strategy_index: u8, amount: u64 },
+
+    /// Pull tokens back from a registered strategy into the vault (owner only)
+    ///
+    /// Accounts expected: same as `Allocate`
+    Deallocate { strategy_index: u8, amount: u64 },
+
+    /// Refresh a strategy's allocation from the balance its adapter reports (owner only)
+    ///
+    /// Accounts expected: same as `Allocate`
+    Harvest { strategy_index: u8 },
+
+    /// Set the minimum idle liquidity, in basis points of total assets, that
+    /// allocations must leave in the vault (owner only)
+    ///
+    /// Accounts expected:
+    /// 0. [signer] Vault owner
+    /// 1. [writable] Vault state account
+    SetMinIdleBps { min_idle_bps: u16 },
+
+    /// Pay out the user's queued withdrawal once the vault has enough idle liquidity
+    ///
+    /// Accounts expected:
+    /// 0. [signer] User account
+    /// 1. [writable] User token account
+    /// 2. [writable] Vault token account
+    /// 3. [writable] Vault state account
+    /// 4. [writable] User balance account (PDA)
+    /// 5. [] SPL Token program
+    ClaimWithdrawal,
+
+    /// Upgrade a vault state account written with an older layout to the
+    /// current one, reallocating it in place. Anyone may migrate; the payer
+    /// covers any additional rent. A no-op on accounts already up to date.
+    ///
+    /// Accounts expected:
+    /// 0. [signer, writable] Payer account
+    /// 1. [writable] Vault state account
+    /// 2. [] System program
+    MigrateVault,
+
+    /// Upgrade a user balance account written with an older layout to the
+    /// current one. Same rules and accounts as `MigrateVault`.
+    ///
+    /// Accounts expected:
+    /// 0. [signer, writable] Payer account
+    /// 1. [writable] User balance account
+    /// 2. [] System program
+    MigrateUserBalance,
+
+    /// Replace the vault's display name and URI (owner only). Creates the
+    /// metadata account, caching the mint decimals, if the vault has none yet.
+    ///
+    /// Accounts expected:
+    /// 0. [signer, writable] Vault owner
+    /// 1. [] Vault state account
+    /// 2. [writable] Vault metadata account (PDA)
+    /// 3. [] Token mint
+    /// 4. [] System program
+    UpdateMetadata { metadata: VaultMetadataArgs },
+
+    /// Close an empty basket (owner only). Fails while any mint has deposits.
+    ///
+    /// Accounts expected:
+    /// 0. [signer] Basket owner
+    /// 1. [writable] Basket state account (PDA)
+    CloseBasket,
+}
+
+/// Interface every strategy adapter program implements. The vault invokes the
+/// adapter with the vault state PDA as signer and the accounts:
+/// 0. [signer] Vault state account
+/// 1. [writable] Vault token account
+/// 2. [writable] Strategy token account
+/// 3. [] SPL Token program
+/// 4. [] Any additional accounts passed to the vault instruction
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub enum StrategyAdapterInstruction {
+    /// `amount` tokens were just moved into the strategy token account
+    Deposit { amount: u64 },
+    /// Return `amount` tokens to the vault token account
+    Withdraw { amount: u64 },
+    /// Report the strategy's current balance as a little-endian u64 via return data
+    ReportBalance,
+}
+
+impl StrategyAdapterInstruction {
+    /// Tag prefixed to adapter instruction data so adapter programs can route it
+    pub const TAG: [u8; 8] = *b"vault_sa";
+
+    /// Serialize the adapter instruction with its tag
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = Self::TAG.to_vec();
+        data.extend_from_slice(&self.try_to_vec().unwrap());
+        data
+    }
+
+    /// Parse adapter instruction data produced by `pack`
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let payload = input
+            .strip_prefix(&Self::TAG[..])
+            .ok_or(VaultError::InvalidInstructionData)?;
+        Self::try_from_slice(payload).map_err(|_| VaultError::InvalidInstructionData.into())
+    }
+}
+
+/// Instruction data the vault passes to the borrower program during `FlashLoan`
+///
+/// The borrower program receives the accounts:
+/// 0. [writable] Borrower token account (holds the loaned tokens)
+/// 1. [writable] Vault token account (repay `amount + fee` here)
+/// 2. [] SPL Token program
+/// 3. [] Any additional accounts passed to `FlashLoan`
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct FlashLoanCallback {
+    /// Amount lent to the borrower
+    pub amount: u64,
+    /// Fee owed on top of `amount`
+    pub fee: u64,
+}
+
+impl FlashLoanCallback {
+    /// Tag prefixed to the callback data so borrower programs can route it
+    pub const TAG: [u8; 8] = *b"vault_fl";
+
+    /// Serialize the callback data with its tag
+    pub fn pack(&self) -> Vec<u8> {
+        let mut data = Self::TAG.to_vec();
+        data.extend_from_slice(&self.try_to_vec().unwrap());
+        data
+    }
+
+    /// Parse callback data produced by `pack`
+    pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
+        let payload = input
+            .strip_prefix(&Self::TAG[..])
+            .ok_or(VaultError::InvalidInstructionData)?;
+        Self::try_from_slice(payload).map_err(|_| VaultError::InvalidInstructionData.into())
+    }
 }
 
 impl VaultInstruction {
@@ -76,6 +366,7 @@ impl VaultInstruction {
         vault_state: &Pubkey,
         vault_token_account: &Pubkey,
         token_mint: &Pubkey,
+        vault_id: u64,
     ) -> Instruction {
         let accounts = vec![
             AccountMeta::new(*owner, true),
@@ -90,10 +381,28 @@ impl VaultInstruction {
         Instruction {
             program_id: *program_id,
             accounts,
-            data: VaultInstruction::Initialize.try_to_vec().unwrap(),
+            data: VaultInstruction::Initialize { vault_id, metadata: None }.try_to_vec().unwrap(),
         }
     }
 
+    /// Create an Initialize instruction that also sets the vault's display metadata
+    #[allow(clippy::too_many_arguments)]
+    pub fn initialize_with_metadata(
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        vault_state: &Pubkey,
+        vault_token_account: &Pubkey,
+        token_mint: &Pubkey,
+        vault_metadata: &Pubkey,
+        vault_id: u64,
+        metadata: VaultMetadataArgs,
+    ) -> Instruction {
+        let mut instruction = Self::initialize(program_id, owner, vault_state, vault_token_account, token_mint, vault_id);
+        instruction.accounts.push(AccountMeta::new(*vault_metadata, false));
+        instruction.data = VaultInstruction::Initialize { vault_id, metadata: Some(metadata) }.try_to_vec().unwrap();
+        instruction
+    }
+
     /// Create a Deposit instruction
     pub fn deposit(
         program_id: &Pubkey,
@@ -192,21 +501,586 @@ impl VaultInstruction {
             data: VaultInstruction::Close.try_to_vec().unwrap(),
         }
     }
+
+    /// Create an ApproveDelegate instruction
+    pub fn approve_delegate(
+        program_id: &Pubkey,
+        user: &Pubkey,
+        vault_state: &Pubkey,
+        user_balance_account: &Pubkey,
+        delegate: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(*user, true),
+            AccountMeta::new_readonly(*vault_state, false),
+            AccountMeta::new(*user_balance_account, false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::ApproveDelegate {
+                delegate: *delegate,
+                amount,
+            }
+            .try_to_vec()
+            .unwrap(),
+        }
+    }
+
+    /// Create a RevokeDelegate instruction
+    pub fn revoke_delegate(
+        program_id: &Pubkey,
+        user: &Pubkey,
+        vault_state: &Pubkey,
+        user_balance_account: &Pubkey,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(*user, true),
+            AccountMeta::new_readonly(*vault_state, false),
+            AccountMeta::new(*user_balance_account, false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::RevokeDelegate.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create a WithdrawAsDelegate instruction
+    pub fn withdraw_as_delegate(
+        program_id: &Pubkey,
+        delegate: &Pubkey,
+        destination_token_account: &Pubkey,
+        vault_token_account: &Pubkey,
+        vault_state: &Pubkey,
+        user_balance_account: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(*delegate, true),
+            AccountMeta::new(*destination_token_account, false),
+            AccountMeta::new(*vault_token_account, false),
+            AccountMeta::new(*vault_state, false),
+            AccountMeta::new(*user_balance_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::WithdrawAsDelegate { amount }.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create a TransferBalance instruction
+    pub fn transfer_balance(
+        program_id: &Pubkey,
+        sender: &Pubkey,
+        vault_state: &Pubkey,
+        sender_balance_account: &Pubkey,
+        recipient_balance_account: &Pubkey,
+        to: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new(*sender, true),
+            AccountMeta::new(*vault_state, false),
+            AccountMeta::new(*sender_balance_account, false),
+            AccountMeta::new(*recipient_balance_account, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::TransferBalance { to: *to, amount }
+                .try_to_vec()
+                .unwrap(),
+        }
+    }
+
+    /// Create a DepositFor instruction
+    #[allow(clippy::too_many_arguments)]
+    pub fn deposit_for(
+        program_id: &Pubkey,
+        depositor: &Pubkey,
+        depositor_token_account: &Pubkey,
+        vault_token_account: &Pubkey,
+        vault_state: &Pubkey,
+        beneficiary_balance_account: &Pubkey,
+        beneficiary: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new(*depositor, true),
+            AccountMeta::new(*depositor_token_account, false),
+            AccountMeta::new(*vault_token_account, false),
+            AccountMeta::new(*vault_state, false),
+            AccountMeta::new(*beneficiary_balance_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::DepositFor {
+                beneficiary: *beneficiary,
+                amount,
+            }
+            .try_to_vec()
+            .unwrap(),
+        }
+    }
+
+    /// Create a WithdrawWithPermit instruction
+    #[allow(clippy::too_many_arguments)]
+    pub fn withdraw_with_permit(
+        program_id: &Pubkey,
+        relayer: &Pubkey,
+        destination_token_account: &Pubkey,
+        vault_token_account: &Pubkey,
+        vault_state: &Pubkey,
+        user_balance_account: &Pubkey,
+        amount: u64,
+        nonce: u64,
+        expiry: i64,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(*relayer, true),
+            AccountMeta::new(*destination_token_account, false),
+            AccountMeta::new(*vault_token_account, false),
+            AccountMeta::new(*vault_state, false),
+            AccountMeta::new(*user_balance_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(sysvar::instructions::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::WithdrawWithPermit {
+                amount,
+                nonce,
+                expiry,
+            }
+            .try_to_vec()
+            .unwrap(),
+        }
+    }
+
+    /// Create an InitializeBasket instruction
+    pub fn initialize_basket(
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        basket: &Pubkey,
+        basket_id: u64,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(*basket, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::InitializeBasket { basket_id }.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create an AddBasketMint instruction
+    pub fn add_basket_mint(
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        basket: &Pubkey,
+        token_mint: &Pubkey,
+        basket_token_account: &Pubkey,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(*basket, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new(*basket_token_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::AddBasketMint.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create a BasketDeposit instruction
+    pub fn basket_deposit(
+        program_id: &Pubkey,
+        user: &Pubkey,
+        user_token_account: &Pubkey,
+        basket_token_account: &Pubkey,
+        basket: &Pubkey,
+        basket_balance_account: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new(*user, true),
+            AccountMeta::new(*user_token_account, false),
+            AccountMeta::new(*basket_token_account, false),
+            AccountMeta::new(*basket, false),
+            AccountMeta::new(*basket_balance_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::BasketDeposit { amount }.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create a BasketWithdraw instruction
+    pub fn basket_withdraw(
+        program_id: &Pubkey,
+        user: &Pubkey,
+        user_token_account: &Pubkey,
+        basket_token_account: &Pubkey,
+        basket: &Pubkey,
+        basket_balance_account: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(*user, true),
+            AccountMeta::new(*user_token_account, false),
+            AccountMeta::new(*basket_token_account, false),
+            AccountMeta::new(*basket, false),
+            AccountMeta::new(*basket_balance_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::BasketWithdraw { amount }.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create a CloseBasket instruction
+    pub fn close_basket(program_id: &Pubkey, owner: &Pubkey, basket: &Pubkey) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*basket, false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::CloseBasket.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create a FlashLoan instruction; `callback_accounts` are forwarded to the borrower program
+    pub fn flash_loan(
+        program_id: &Pubkey,
+        vault_state: &Pubkey,
+        vault_token_account: &Pubkey,
+        borrower_token_account: &Pubkey,
+        borrower_program: &Pubkey,
+        callback_accounts: &[AccountMeta],
+        amount: u64,
+    ) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new(*vault_state, false),
+            AccountMeta::new(*vault_token_account, false),
+            AccountMeta::new(*borrower_token_account, false),
+            AccountMeta::new_readonly(*borrower_program, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ];
+        accounts.extend_from_slice(callback_accounts);
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::FlashLoan { amount }.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create an AddStrategy instruction
+    pub fn add_strategy(
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        vault_state: &Pubkey,
+        strategy_program: &Pubkey,
+        cap: u64,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*vault_state, false),
+            AccountMeta::new_readonly(*strategy_program, false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::AddStrategy { cap }.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create an Allocate instruction; `adapter_accounts` are forwarded to the adapter
+    #[allow(clippy::too_many_arguments)]
+    pub fn allocate(
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        vault_state: &Pubkey,
+        vault_token_account: &Pubkey,
+        strategy_token_account: &Pubkey,
+        strategy_program: &Pubkey,
+        adapter_accounts: &[AccountMeta],
+        strategy_index: u8,
+        amount: u64,
+    ) -> Instruction {
+        Self::strategy_instruction(
+            program_id,
+            owner,
+            vault_state,
+            vault_token_account,
+            strategy_token_account,
+            strategy_program,
+            adapter_accounts,
+            VaultInstruction::Allocate { strategy_index, amount },
+        )
+    }
+
+    /// Create a Deallocate instruction; `adapter_accounts` are forwarded to the adapter
+    #[allow(clippy::too_many_arguments)]
+    pub fn deallocate(
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        vault_state: &Pubkey,
+        vault_token_account: &Pubkey,
+        strategy_token_account: &Pubkey,
+        strategy_program: &Pubkey,
+        adapter_accounts: &[AccountMeta],
+        strategy_index: u8,
+        amount: u64,
+    ) -> Instruction {
+        Self::strategy_instruction(
+            program_id,
+            owner,
+            vault_state,
+            vault_token_account,
+            strategy_token_account,
+            strategy_program,
+            adapter_accounts,
+            VaultInstruction::Deallocate { strategy_index, amount },
+        )
+    }
+
+    /// Create a Harvest instruction; `adapter_accounts` are forwarded to the adapter
+    #[allow(clippy::too_many_arguments)]
+    pub fn harvest(
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        vault_state: &Pubkey,
+        vault_token_account: &Pubkey,
+        strategy_token_account: &Pubkey,
+        strategy_program: &Pubkey,
+        adapter_accounts: &[AccountMeta],
+        strategy_index: u8,
+    ) -> Instruction {
+        Self::strategy_instruction(
+            program_id,
+            owner,
+            vault_state,
+            vault_token_account,
+            strategy_token_account,
+            strategy_program,
+            adapter_accounts,
+            VaultInstruction::Harvest { strategy_index },
+        )
+    }
+
+    /// Create a SetMinIdleBps instruction
+    pub fn set_min_idle_bps(
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        vault_state: &Pubkey,
+        min_idle_bps: u16,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*vault_state, false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::SetMinIdleBps { min_idle_bps }.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create a ClaimWithdrawal instruction
+    pub fn claim_withdrawal(
+        program_id: &Pubkey,
+        user: &Pubkey,
+        user_token_account: &Pubkey,
+        vault_token_account: &Pubkey,
+        vault_state: &Pubkey,
+        user_balance_account: &Pubkey,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new_readonly(*user, true),
+            AccountMeta::new(*user_token_account, false),
+            AccountMeta::new(*vault_token_account, false),
+            AccountMeta::new(*vault_state, false),
+            AccountMeta::new(*user_balance_account, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::ClaimWithdrawal.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Create a MigrateVault instruction
+    pub fn migrate_vault(program_id: &Pubkey, payer: &Pubkey, vault_state: &Pubkey) -> Instruction {
+        Self::migrate_instruction(program_id, payer, vault_state, VaultInstruction::MigrateVault)
+    }
+
+    /// Create a MigrateUserBalance instruction
+    pub fn migrate_user_balance(program_id: &Pubkey, payer: &Pubkey, user_balance_account: &Pubkey) -> Instruction {
+        Self::migrate_instruction(program_id, payer, user_balance_account, VaultInstruction::MigrateUserBalance)
+    }
+
+    /// Create an UpdateMetadata instruction
+    pub fn update_metadata(
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        vault_state: &Pubkey,
+        vault_metadata: &Pubkey,
+        token_mint: &Pubkey,
+        metadata: VaultMetadataArgs,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new(*owner, true),
+            AccountMeta::new_readonly(*vault_state, false),
+            AccountMeta::new(*vault_metadata, false),
+            AccountMeta::new_readonly(*token_mint, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: VaultInstruction::UpdateMetadata { metadata }.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Shared account layout of the migration instructions
+    fn migrate_instruction(
+        program_id: &Pubkey,
+        payer: &Pubkey,
+        account: &Pubkey,
+        instruction: VaultInstruction,
+    ) -> Instruction {
+        let accounts = vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new(*account, false),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ];
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: instruction.try_to_vec().unwrap(),
+        }
+    }
+
+    /// Shared account layout of the Allocate, Deallocate and Harvest instructions
+    #[allow(clippy::too_many_arguments)]
+    fn strategy_instruction(
+        program_id: &Pubkey,
+        owner: &Pubkey,
+        vault_state: &Pubkey,
+        vault_token_account: &Pubkey,
+        strategy_token_account: &Pubkey,
+        strategy_program: &Pubkey,
+        adapter_accounts: &[AccountMeta],
+        instruction: VaultInstruction,
+    ) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(*vault_state, false),
+            AccountMeta::new(*vault_token_account, false),
+            AccountMeta::new(*strategy_token_account, false),
+            AccountMeta::new_readonly(*strategy_program, false),
+            AccountMeta::new_readonly(spl_token::id(), false),
+        ];
+        accounts.extend_from_slice(adapter_accounts);
+
+        Instruction {
+            program_id: *program_id,
+            accounts,
+            data: instruction.try_to_vec().unwrap(),
+        }
+    }
+}
+
+/// Create the ed25519 signature verification instruction that must precede
+/// `WithdrawWithPermit`, from a signature the user produced off-chain
+pub fn ed25519_permit_instruction(
+    signer: &Pubkey,
+    signature: &[u8; 64],
+    message: &[u8],
+) -> Instruction {
+    let public_key_offset = ED25519_OFFSETS_HEADER_LEN;
+    let signature_offset = public_key_offset + 32;
+    let message_data_offset = signature_offset + 64;
+
+    let mut data = Vec::with_capacity(message_data_offset + message.len());
+    data.push(1); // number of signatures
+    data.push(0); // padding
+    for field in [
+        signature_offset as u16,
+        u16::MAX, // signature lives in this instruction
+        public_key_offset as u16,
+        u16::MAX, // public key lives in this instruction
+        message_data_offset as u16,
+        message.len() as u16,
+        u16::MAX, // message lives in this instruction
+    ] {
+        data.extend_from_slice(&field.to_le_bytes());
+    }
+    data.extend_from_slice(signer.as_ref());
+    data.extend_from_slice(signature);
+    data.extend_from_slice(message);
+
+    Instruction {
+        program_id: ed25519_program::id(),
+        accounts: vec![],
+        data,
+    }
 }
 
 /// Parse instruction data into VaultInstruction
 pub fn unpack(input: &[u8]) -> Result<VaultInstruction, ProgramError> {
     if input.is_empty() {
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidInstructionData.into());
     }
 
-    VaultInstruction::try_from_slice(input).map_err(|_| VaultError::InvalidInput.into())
+    VaultInstruction::try_from_slice(input).map_err(|_| VaultError::InvalidInstructionData.into())
 }
 
 /// Validate instruction data format and size
 pub fn validate_instruction_data(data: &[u8]) -> Result<(), ProgramError> {
     if data.is_empty() {
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidInstructionData.into());
     }
 
     // Try to deserialize to validate format
@@ -214,16 +1088,26 @@ pub fn validate_instruction_data(data: &[u8]) -> Result<(), ProgramError> {
         Ok(instruction) => {
             // Additional validation based on instruction type
             match instruction {
-                VaultInstruction::Deposit { amount } | VaultInstruction::Withdraw { amount } => {
-                    if amount == 0 {
-                        return Err(VaultError::InvalidInput.into());
-                    }
+                VaultInstruction::Deposit { amount }
+                | VaultInstruction::Withdraw { amount }
+                | VaultInstruction::WithdrawAsDelegate { amount }
+                | VaultInstruction::TransferBalance { amount, .. }
+                | VaultInstruction::DepositFor { amount, .. }
+                | VaultInstruction::WithdrawWithPermit { amount, .. }
+                | VaultInstruction::BasketDeposit { amount }
+                | VaultInstruction::BasketWithdraw { amount }
+                | VaultInstruction::FlashLoan { amount }
+                | VaultInstruction::Allocate { amount, .. }
+                | VaultInstruction::Deallocate { amount, .. }
+                    if amount == 0 =>
+                {
+                    return Err(VaultError::ZeroAmount.into());
                 }
                 _ => {}
             }
             Ok(())
         }
-        Err(_) => Err(VaultError::InvalidInput.into()),
+        Err(_) => Err(VaultError::InvalidInstructionData.into()),
     }
 }
 
@@ -239,9 +1123,37 @@ mod tests {
         assert_eq!(deposit, deserialized);
     }
 
+    #[test]
+    fn test_approve_delegate_serialization() {
+        let approve = VaultInstruction::ApproveDelegate {
+            delegate: Pubkey::new_unique(),
+            amount: 500,
+        };
+        let serialized = approve.try_to_vec().unwrap();
+        let deserialized = VaultInstruction::try_from_slice(&serialized).unwrap();
+        assert_eq!(approve, deserialized);
+    }
+
+    #[test]
+    fn test_strategy_adapter_instruction_roundtrip() {
+        let instruction = StrategyAdapterInstruction::Withdraw { amount: 42 };
+        let data = instruction.pack();
+        assert_eq!(StrategyAdapterInstruction::unpack(&data).unwrap(), instruction);
+        assert!(StrategyAdapterInstruction::unpack(&FlashLoanCallback { amount: 42, fee: 0 }.pack()).is_err());
+    }
+
+    #[test]
+    fn test_flash_loan_callback_roundtrip() {
+        let callback = FlashLoanCallback { amount: 1_000, fee: 1 };
+        let data = callback.pack();
+        assert_eq!(&data[..8], &FlashLoanCallback::TAG);
+        assert_eq!(FlashLoanCallback::unpack(&data).unwrap(), callback);
+        assert!(FlashLoanCallback::unpack(&data[8..]).is_err());
+    }
+
     #[test]
     fn test_unpack_valid_instruction() {
-        let instruction = VaultInstruction::Initialize;
+        let instruction = VaultInstruction::Initialize { vault_id: 7, metadata: None };
         let data = instruction.try_to_vec().unwrap();
         let unpacked = unpack(&data).unwrap();
         assert_eq!(instruction, unpacked);
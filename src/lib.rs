@@ -1,33 +1,50 @@
 use solana_program::{
     account_info::AccountInfo,
-    entrypoint,
     entrypoint::ProgramResult,
     pubkey::Pubkey,
 };
 
 // Program modules
+#[cfg(feature = "client")]
+pub mod account;
+#[cfg(feature = "cli")]
+pub mod cli;
+#[cfg(feature = "client")]
+pub mod client;
+#[cfg(feature = "cpi")]
+pub mod cpi;
 pub mod error;
+pub mod events;
 pub mod instruction;
+mod logging;
+pub mod pod;
 pub mod processor;
 pub mod state;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod utils;
 
 // Re-exports for external use (will be uncommented as modules are implemented)
 pub use error::VaultError;
+pub use events::VaultEvent;
 pub use instruction::VaultInstruction;
-pub use state::{UserBalance, VaultState};
+pub use state::{BasketBalance, BasketState, ProgramAccount, StrategyAllocation, UserBalance, VaultMetadata, VaultState};
 
 // Program entrypoint
 #[cfg(not(feature = "no-entrypoint"))]
-entrypoint!(process_instruction);
+solana_program::entrypoint!(process_instruction);
 
-/// Main program entry point
+/// Main program entry point. Failures are logged with their decoded message and
+/// the index of the failing account, if known, before the program error is returned.
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
-    processor::process_instruction(program_id, accounts, instruction_data)
+    processor::process_instruction(program_id, accounts, instruction_data).map_err(|error| {
+        error.log(accounts);
+        error.into()
+    })
 }
 
 // Declare program ID (this would be set after deployment)
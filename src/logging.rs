@@ -0,0 +1,49 @@
+//! Compile-time log verbosity.
+//!
+//! Every `msg!` costs compute units, so the processor logs through the macros
+//! below and the level is picked with cargo features:
+//!
+//! - no feature: error logs only (deployed builds)
+//! - `log-info`: errors plus one summary line per successful instruction
+//! - `log-debug`: everything, including buffer dumps and serialization traces
+//! - `log-off`: no `msg!` output at all
+//!
+//! Structured events emitted through `sol_log_data` are not affected.
+
+/// Error logs are kept unless `log-off` is enabled
+pub(crate) const ERROR_ENABLED: bool = !cfg!(feature = "log-off");
+
+/// Success summaries, enabled by `log-info` (implied by `log-debug`)
+pub(crate) const INFO_ENABLED: bool = cfg!(feature = "log-info") && !cfg!(feature = "log-off");
+
+/// Diagnostic traces, enabled by `log-debug`
+pub(crate) const DEBUG_ENABLED: bool = cfg!(feature = "log-debug") && !cfg!(feature = "log-off");
+
+/// Log a failure reason right before an error is returned
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        if $crate::logging::ERROR_ENABLED {
+            ::solana_program::msg!($($arg)*);
+        }
+    };
+}
+
+/// Log the summary of a successful instruction
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        if $crate::logging::INFO_ENABLED {
+            ::solana_program::msg!($($arg)*);
+        }
+    };
+}
+
+/// Log serialization and buffer diagnostics
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        if $crate::logging::DEBUG_ENABLED {
+            ::solana_program::msg!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {log_debug, log_error, log_info};
@@ -0,0 +1,93 @@
+use bytemuck::{Pod, Zeroable};
+
+/// Little-endian `u64` with an alignment of 1, laid out exactly like its borsh encoding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+#[repr(transparent)]
+pub struct PodU64([u8; 8]);
+
+impl PodU64 {
+    /// Read the value
+    pub fn get(self) -> u64 {
+        u64::from_le_bytes(self.0)
+    }
+
+    /// Overwrite the value
+    pub fn set(&mut self, value: u64) {
+        self.0 = value.to_le_bytes();
+    }
+}
+
+impl From<u64> for PodU64 {
+    fn from(value: u64) -> Self {
+        Self(value.to_le_bytes())
+    }
+}
+
+/// Little-endian `u128` with an alignment of 1, laid out exactly like its borsh encoding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+#[repr(transparent)]
+pub struct PodU128([u8; 16]);
+
+impl PodU128 {
+    /// Read the value
+    pub fn get(self) -> u128 {
+        u128::from_le_bytes(self.0)
+    }
+
+    /// Overwrite the value
+    pub fn set(&mut self, value: u128) {
+        self.0 = value.to_le_bytes();
+    }
+}
+
+impl From<u128> for PodU128 {
+    fn from(value: u128) -> Self {
+        Self(value.to_le_bytes())
+    }
+}
+
+/// Little-endian `u16` with an alignment of 1, laid out exactly like its borsh encoding
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+#[repr(transparent)]
+pub struct PodU16([u8; 2]);
+
+impl PodU16 {
+    /// Read the value
+    pub fn get(self) -> u16 {
+        u16::from_le_bytes(self.0)
+    }
+
+    /// Overwrite the value
+    pub fn set(&mut self, value: u16) {
+        self.0 = value.to_le_bytes();
+    }
+}
+
+impl From<u16> for PodU16 {
+    fn from(value: u16) -> Self {
+        Self(value.to_le_bytes())
+    }
+}
+
+/// Single-byte boolean laid out like its borsh encoding (0 or 1)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+#[repr(transparent)]
+pub struct PodBool(u8);
+
+impl PodBool {
+    /// Read the value; any non-zero byte is true
+    pub fn get(self) -> bool {
+        self.0 != 0
+    }
+
+    /// Overwrite the value
+    pub fn set(&mut self, value: bool) {
+        self.0 = value as u8;
+    }
+}
+
+impl From<bool> for PodBool {
+    fn from(value: bool) -> Self {
+        Self(value as u8)
+    }
+}
@@ -1,49 +1,77 @@
-use borsh::{BorshDeserialize, BorshSerialize};
+use borsh::BorshDeserialize;
 use solana_program::{
     account_info::{next_account_info, AccountInfo},
-    entrypoint::ProgramResult,
-    msg,
-    program::invoke_signed,
-    program_error::ProgramError,
+    instruction::{AccountMeta, Instruction},
+    program::{get_return_data, invoke_signed},
     program_pack::Pack,
     pubkey::Pubkey,
+    clock::Clock,
     rent::Rent,
     system_instruction,
     sysvar::Sysvar,
 };
 
 use crate::{
-    error::VaultError,
-    instruction::{unpack, VaultInstruction},
-    state::{VaultState, UserBalance},
-    utils::{derive_vault_state_pda, derive_user_balance_pda, verify_signer, verify_token_mint},
+    error::{AccountContext, ProcessResult, VaultError},
+    events::VaultEvent,
+    logging::{log_debug, log_error, log_info},
+    instruction::{unpack, FlashLoanCallback, StrategyAdapterInstruction, VaultInstruction, VaultMetadataArgs},
+    state::{
+        BasketBalance, BasketState, PodUserBalance, PodVaultState, ProgramAccount, UserBalance, UserBalanceV1,
+        VaultMetadata, VaultState, VaultStateV1, ZeroCopyAccount, ACCOUNT_HEADER_LEN,
+    },
+    utils::{
+        derive_basket_balance_pda, derive_basket_pda, derive_basket_token_account_pda, derive_metadata_pda,
+        derive_vault_state_pda, derive_user_balance_pda, verify_ed25519_permit,
+        withdraw_permit_message, BASKET_BALANCE_SEED, BASKET_SEED, BASKET_TOKEN_SEED, METADATA_SEED,
+    },
 };
 
 /// Helper function for logging buffer state for debugging
 fn log_buffer_state(data: &[u8], operation: &str) {
-    msg!("{}: Buffer length: {}", operation, data.len());
-    msg!("{}: Expected VaultState size: {}", operation, VaultState::SIZE);
+    log_debug!("{}: Buffer length: {}", operation, data.len());
+    log_debug!("{}: Expected VaultState size: {}", operation, VaultState::SIZE);
     if !data.is_empty() {
         let preview_len = 20.min(data.len());
-        msg!("{}: First {} bytes: {:?}", operation, preview_len, &data[..preview_len]);
+        log_debug!("{}: First {} bytes: {:?}", operation, preview_len, &data[..preview_len]);
         if data.len() > 20 {
             let tail_start = data.len().saturating_sub(20);
-            msg!("{}: Last 20 bytes: {:?}", operation, &data[tail_start..]);
+            log_debug!("{}: Last 20 bytes: {:?}", operation, &data[tail_start..]);
         }
     }
 }
 
+/// Verify the discriminator and layout version at the start of a program account
+fn check_account_header<T: ProgramAccount>(
+    account_data: &[u8],
+    account_name: &str,
+    operation: &str,
+) -> ProcessResult {
+    match T::stored_version(account_data) {
+        None => {
+            log_error!("{}: {} discriminator mismatch", operation, account_name);
+            Err(VaultError::InvalidAccountDiscriminator.into())
+        }
+        Some(version) if version != T::VERSION => {
+            log_error!("{}: Unsupported {} version - expected: {}, actual: {}",
+                 operation, account_name, T::VERSION, version);
+            Err(VaultError::UnsupportedAccountVersion.into())
+        }
+        Some(_) => Ok(()),
+    }
+}
+
 /// Validates account data buffer for vault state operations
 fn validate_vault_buffer(
     account_data: &[u8],
     expected_size: usize,
     operation: &str,
-) -> Result<(), ProgramError> {
+) -> ProcessResult {
     if account_data.len() != expected_size {
-        msg!("{}: Buffer size mismatch - expected: {}, actual: {}", 
+        log_error!("{}: Buffer size mismatch - expected: {}, actual: {}", 
              operation, expected_size, account_data.len());
         log_buffer_state(account_data, operation);
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidAccountData.into());
     }
     Ok(())
 }
@@ -53,44 +81,44 @@ fn serialize_vault_state_safe(
     vault_state: &VaultState,
     vault_state_data: &mut [u8],
     operation: &str,
-) -> Result<(), ProgramError> {
-    msg!("{}: Starting serialization", operation);
-    msg!("{}: Buffer length: {}, Expected size: {}", 
+) -> ProcessResult {
+    log_debug!("{}: Starting serialization", operation);
+    log_debug!("{}: Buffer length: {}, Expected size: {}", 
          operation, vault_state_data.len(), VaultState::SIZE);
     
     // Validate vault state before serialization
     vault_state.validate().map_err(|err| {
-        msg!("{}: Vault state validation failed: {}", operation, err);
-        VaultError::InvalidInput
+        log_error!("{}: Vault state validation failed: {}", operation, err);
+        VaultError::InvalidAccountData
     })?;
     
     // Serialize the vault state
-    let serialized_data = vault_state.try_to_vec()
+    let serialized_data = vault_state.to_account_bytes()
         .map_err(|e| {
-            msg!("{}: Failed to serialize vault state: {}", operation, e);
-            VaultError::InvalidInput
+            log_error!("{}: Failed to serialize vault state: {}", operation, e);
+            VaultError::InvalidAccountData
         })?;
     
-    msg!("{}: Serialized data length: {}", operation, serialized_data.len());
+    log_debug!("{}: Serialized data length: {}", operation, serialized_data.len());
     
     // Validate serialized data size
     if serialized_data.len() != VaultState::SIZE {
-        msg!("{}: Serialization size mismatch - expected: {}, got: {}", 
+        log_error!("{}: Serialization size mismatch - expected: {}, got: {}", 
              operation, VaultState::SIZE, serialized_data.len());
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidAccountData.into());
     }
     
     // Validate buffer size
     if vault_state_data.len() < serialized_data.len() {
-        msg!("{}: Account data buffer too small - required: {}, available: {}", 
+        log_error!("{}: Account data buffer too small - required: {}, available: {}", 
              operation, serialized_data.len(), vault_state_data.len());
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidAccountData.into());
     }
     
     // Copy the serialized data to the exact required space
     vault_state_data[..serialized_data.len()].copy_from_slice(&serialized_data);
     
-    msg!("{}: Successfully serialized vault state", operation);
+    log_debug!("{}: Successfully serialized vault state", operation);
     Ok(())
 }
 
@@ -98,31 +126,38 @@ fn serialize_vault_state_safe(
 fn deserialize_vault_state_safe(
     vault_state_data: &[u8],
     operation: &str,
-) -> Result<VaultState, ProgramError> {
-    msg!("{}: Starting deserialization", operation);
+) -> ProcessResult<VaultState> {
+    log_debug!("{}: Starting deserialization", operation);
     
     // Log buffer state for debugging
     log_buffer_state(vault_state_data, operation);
     
     // Validate buffer size before deserialization
     validate_vault_buffer(vault_state_data, VaultState::SIZE, operation)?;
+    check_account_header::<VaultState>(vault_state_data, "vault state", operation)?;
     
     // Attempt deserialization
-    let vault_state = VaultState::try_from_slice(vault_state_data)
+    let vault_state = VaultState::try_from_slice(&vault_state_data[ACCOUNT_HEADER_LEN..])
         .map_err(|e| {
-            msg!("{}: Failed to deserialize vault state: {}", operation, e);
-            msg!("{}: This may indicate data corruption or format mismatch", operation);
+            log_error!("{}: Failed to deserialize vault state: {}", operation, e);
+            log_error!("{}: This may indicate data corruption or format mismatch", operation);
             log_buffer_state(vault_state_data, operation);
             VaultError::AccountNotInitialized
         })?;
     
     // Validate deserialized state
     vault_state.validate().map_err(|err| {
-        msg!("{}: Deserialized vault state validation failed: {}", operation, err);
-        VaultError::InvalidInput
+        log_error!("{}: Deserialized vault state validation failed: {}", operation, err);
+        VaultError::InvalidAccountData
     })?;
+
+    // Reject any vault operation while a flash loan is in flight
+    if vault_state.is_locked {
+        log_error!("{}: Vault is locked by an in-flight flash loan", operation);
+        return Err(VaultError::VaultLocked.into());
+    }
     
-    msg!("{}: Successfully deserialized vault state", operation);
+    log_debug!("{}: Successfully deserialized vault state", operation);
     Ok(vault_state)
 }
 
@@ -130,40 +165,41 @@ fn deserialize_vault_state_safe(
 fn deserialize_user_balance_safe(
     user_balance_data: &[u8],
     operation: &str,
-) -> Result<UserBalance, ProgramError> {
-    msg!("{}: Starting user balance deserialization", operation);
+) -> ProcessResult<UserBalance> {
+    log_debug!("{}: Starting user balance deserialization", operation);
     
     // Log buffer state for debugging
-    msg!("{}: User balance buffer length: {}", operation, user_balance_data.len());
-    msg!("{}: Expected UserBalance size: {}", operation, UserBalance::SIZE);
+    log_debug!("{}: User balance buffer length: {}", operation, user_balance_data.len());
+    log_debug!("{}: Expected UserBalance size: {}", operation, UserBalance::SIZE);
     
     if !user_balance_data.is_empty() {
         let preview_len = 20.min(user_balance_data.len());
-        msg!("{}: First {} bytes: {:?}", operation, preview_len, &user_balance_data[..preview_len]);
+        log_debug!("{}: First {} bytes: {:?}", operation, preview_len, &user_balance_data[..preview_len]);
     }
     
     // Validate buffer size before deserialization
     if user_balance_data.len() != UserBalance::SIZE {
-        msg!("{}: User balance buffer size mismatch - expected: {}, actual: {}", 
+        log_error!("{}: User balance buffer size mismatch - expected: {}, actual: {}", 
              operation, UserBalance::SIZE, user_balance_data.len());
         return Err(VaultError::AccountNotInitialized.into());
     }
+    check_account_header::<UserBalance>(user_balance_data, "user balance", operation)?;
     
     // Attempt deserialization
-    let user_balance = UserBalance::try_from_slice(user_balance_data)
+    let user_balance = UserBalance::try_from_slice(&user_balance_data[ACCOUNT_HEADER_LEN..])
         .map_err(|e| {
-            msg!("{}: Failed to deserialize user balance: {}", operation, e);
-            msg!("{}: This may indicate data corruption or format mismatch", operation);
+            log_error!("{}: Failed to deserialize user balance: {}", operation, e);
+            log_error!("{}: This may indicate data corruption or format mismatch", operation);
             VaultError::AccountNotInitialized
         })?;
     
     // Validate deserialized state
     user_balance.validate().map_err(|err| {
-        msg!("{}: Deserialized user balance validation failed: {}", operation, err);
-        VaultError::InvalidInput
+        log_error!("{}: Deserialized user balance validation failed: {}", operation, err);
+        VaultError::InvalidAccountData
     })?;
     
-    msg!("{}: Successfully deserialized user balance", operation);
+    log_debug!("{}: Successfully deserialized user balance", operation);
     Ok(user_balance)
 }
 
@@ -172,69 +208,593 @@ fn serialize_user_balance_safe(
     user_balance: &UserBalance,
     user_balance_data: &mut [u8],
     operation: &str,
-) -> Result<(), ProgramError> {
-    msg!("{}: Starting user balance serialization", operation);
-    msg!("{}: Buffer length: {}, Expected size: {}", 
+) -> ProcessResult {
+    log_debug!("{}: Starting user balance serialization", operation);
+    log_debug!("{}: Buffer length: {}, Expected size: {}", 
          operation, user_balance_data.len(), UserBalance::SIZE);
     
     // Validate user balance before serialization
     user_balance.validate().map_err(|err| {
-        msg!("{}: User balance validation failed: {}", operation, err);
-        VaultError::InvalidInput
+        log_error!("{}: User balance validation failed: {}", operation, err);
+        VaultError::InvalidAccountData
     })?;
     
     // Serialize the user balance
-    let serialized_data = user_balance.try_to_vec()
+    let serialized_data = user_balance.to_account_bytes()
         .map_err(|e| {
-            msg!("{}: Failed to serialize user balance: {}", operation, e);
-            VaultError::InvalidInput
+            log_error!("{}: Failed to serialize user balance: {}", operation, e);
+            VaultError::InvalidAccountData
         })?;
     
-    msg!("{}: Serialized user balance data length: {}", operation, serialized_data.len());
+    log_debug!("{}: Serialized user balance data length: {}", operation, serialized_data.len());
     
     // Validate serialized data size
     if serialized_data.len() != UserBalance::SIZE {
-        msg!("{}: User balance serialization size mismatch - expected: {}, got: {}", 
+        log_error!("{}: User balance serialization size mismatch - expected: {}, got: {}", 
              operation, UserBalance::SIZE, serialized_data.len());
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidAccountData.into());
     }
     
     // Validate buffer size
     if user_balance_data.len() < serialized_data.len() {
-        msg!("{}: User balance account data buffer too small - required: {}, available: {}", 
+        log_error!("{}: User balance account data buffer too small - required: {}, available: {}", 
              operation, serialized_data.len(), user_balance_data.len());
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidAccountData.into());
     }
     
     // Copy the serialized data to the exact required space
     user_balance_data[..serialized_data.len()].copy_from_slice(&serialized_data);
     
-    msg!("{}: Successfully serialized user balance", operation);
+    log_debug!("{}: Successfully serialized user balance", operation);
+    Ok(())
+}
+
+/// Derive and verify a user balance PDA, creating and initializing the account if it does not
+/// exist yet. Rent for a newly created account is paid by `payer_info`.
+fn create_user_balance_if_missing<'a>(
+    program_id: &Pubkey,
+    payer_info: &AccountInfo<'a>,
+    user_balance_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    user: &Pubkey,
+    vault_state: &Pubkey,
+    operation: &str,
+) -> ProcessResult {
+    let (user_balance_pda, user_balance_bump) = derive_user_balance_pda(
+        program_id,
+        user,
+        vault_state,
+    )?;
+
+    if user_balance_pda != *user_balance_info.key {
+        log_error!("{}: User balance PDA mismatch. Expected: {}, Got: {}", 
+             operation, user_balance_pda, user_balance_info.key);
+        return Err(VaultError::InvalidPda.with_account(user_balance_info.key));
+    }
+
+    if user_balance_info.owner == program_id {
+        return Ok(());
+    }
+    if user_balance_info.owner != &solana_program::system_program::id() {
+        log_error!("{}: User balance account has invalid owner", operation);
+        return Err(VaultError::InvalidAccountOwner.with_account(user_balance_info.key));
+    }
+
+    // Account doesn't exist, create it
+    let rent = Rent::get()?;
+    let user_balance_space = UserBalance::SIZE;
+    let user_balance_lamports = rent.minimum_balance(user_balance_space);
+
+    let create_user_balance_ix = system_instruction::create_account(
+        payer_info.key,
+        user_balance_info.key,
+        user_balance_lamports,
+        user_balance_space as u64,
+        program_id,
+    );
+
+    let user_balance_seeds = &[
+        crate::utils::USER_BALANCE_SEED,
+        user.as_ref(),
+        vault_state.as_ref(),
+        &[user_balance_bump],
+    ];
+
+    invoke_signed(
+        &create_user_balance_ix,
+        &[
+            payer_info.clone(),
+            user_balance_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[user_balance_seeds],
+    ).map_err(|e| {
+        log_error!("{}: Failed to create user balance account: {}", operation, e);
+        e
+    })?;
+
+    // Initialize new user balance
+    let user_balance = UserBalance::new(*user, *vault_state, user_balance_bump);
+    let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
+    serialize_user_balance_safe(&user_balance, &mut user_balance_data, operation)
+}
+
+/// Derive and verify a user balance PDA, then load it or create it if it does not exist yet.
+/// Rent for a newly created account is paid by `payer_info`.
+fn load_or_create_user_balance<'a>(
+    program_id: &Pubkey,
+    payer_info: &AccountInfo<'a>,
+    user_balance_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    user: &Pubkey,
+    vault_state: &Pubkey,
+    operation: &str,
+) -> ProcessResult<UserBalance> {
+    create_user_balance_if_missing(
+        program_id,
+        payer_info,
+        user_balance_info,
+        system_program_info,
+        user,
+        vault_state,
+        operation,
+    )?;
+
+    let user_balance_data = user_balance_info.try_borrow_data()?;
+    deserialize_user_balance_safe(&user_balance_data, operation).with_account(user_balance_info.key)
+}
+
+/// Zero-copy view of a program account, checking its size and header
+fn load_zero_copy<'a, T: ZeroCopyAccount>(
+    account_data: &'a [u8],
+    account_name: &str,
+    operation: &str,
+) -> ProcessResult<&'a T> {
+    if account_data.len() != T::Account::SIZE {
+        log_error!("{}: {} buffer size mismatch - expected: {}, actual: {}",
+             operation, account_name, T::Account::SIZE, account_data.len());
+        return Err(VaultError::AccountNotInitialized.into());
+    }
+    check_account_header::<T::Account>(account_data, account_name, operation)?;
+
+    T::load(account_data).map_err(|err| {
+        log_error!("{}: {}", operation, err);
+        VaultError::AccountNotInitialized.into()
+    })
+}
+
+/// Mutable zero-copy view of a program account, checking its size and header
+fn load_zero_copy_mut<'a, T: ZeroCopyAccount>(
+    account_data: &'a mut [u8],
+    account_name: &str,
+    operation: &str,
+) -> ProcessResult<&'a mut T> {
+    load_zero_copy::<T>(account_data, account_name, operation)?;
+
+    T::load_mut(account_data).map_err(|err| {
+        log_error!("{}: {}", operation, err);
+        VaultError::AccountNotInitialized.into()
+    })
+}
+
+/// Zero-copy view of a vault state account, rejecting vaults locked by an in-flight flash loan
+fn load_vault_state_zero_copy_mut<'a>(
+    vault_state_data: &'a mut [u8],
+    operation: &str,
+) -> ProcessResult<&'a mut PodVaultState> {
+    let vault_state = load_zero_copy_mut::<PodVaultState>(vault_state_data, "vault state", operation)?;
+    if vault_state.is_locked.get() {
+        log_error!("{}: Vault is locked by an in-flight flash loan", operation);
+        return Err(VaultError::VaultLocked.into());
+    }
+    Ok(vault_state)
+}
+
+/// Generic helper for deserializing program accounts, checking size and header
+fn deserialize_account_safe<T: ProgramAccount>(
+    account_data: &[u8],
+    account_name: &str,
+    operation: &str,
+) -> ProcessResult<T> {
+    if account_data.len() != T::SIZE {
+        log_error!("{}: {} buffer size mismatch - expected: {}, actual: {}",
+             operation, account_name, T::SIZE, account_data.len());
+        return Err(VaultError::AccountNotInitialized.into());
+    }
+    check_account_header::<T>(account_data, account_name, operation)?;
+
+    T::try_from_slice(&account_data[ACCOUNT_HEADER_LEN..]).map_err(|e| {
+        log_error!("{}: Failed to deserialize {}: {}", operation, account_name, e);
+        VaultError::AccountNotInitialized.into()
+    })
+}
+
+/// Generic helper for serializing program accounts with their header
+fn serialize_account_safe<T: ProgramAccount>(
+    value: &T,
+    account_data: &mut [u8],
+    account_name: &str,
+    operation: &str,
+) -> ProcessResult {
+    let serialized_data = value.to_account_bytes().map_err(|e| {
+        log_error!("{}: Failed to serialize {}: {}", operation, account_name, e);
+        VaultError::InvalidAccountData
+    })?;
+
+    if account_data.len() < T::SIZE {
+        log_error!("{}: {} account data buffer too small - required: {}, available: {}",
+             operation, account_name, T::SIZE, account_data.len());
+        return Err(VaultError::InvalidAccountData.into());
+    }
+
+    account_data[..T::SIZE].copy_from_slice(&serialized_data);
+    Ok(())
+}
+
+/// Helper for safe basket state deserialization with validation
+fn deserialize_basket_state_safe(
+    basket_data: &[u8],
+    operation: &str,
+) -> ProcessResult<BasketState> {
+    log_debug!("{}: Starting basket state deserialization", operation);
+    log_debug!("{}: Basket state buffer length: {}, Expected size: {}",
+         operation, basket_data.len(), BasketState::SIZE);
+
+    // Validate buffer size before deserialization
+    if basket_data.len() != BasketState::SIZE {
+        log_error!("{}: Basket state buffer size mismatch - expected: {}, actual: {}",
+             operation, BasketState::SIZE, basket_data.len());
+        return Err(VaultError::AccountNotInitialized.into());
+    }
+    check_account_header::<BasketState>(basket_data, "basket state", operation)?;
+
+    // Attempt deserialization
+    let basket = BasketState::try_from_slice(&basket_data[ACCOUNT_HEADER_LEN..])
+        .map_err(|e| {
+            log_error!("{}: Failed to deserialize basket state: {}", operation, e);
+            VaultError::AccountNotInitialized
+        })?;
+
+    // Validate deserialized state
+    basket.validate().map_err(|err| {
+        log_error!("{}: Deserialized basket state validation failed: {}", operation, err);
+        VaultError::InvalidAccountData
+    })?;
+
+    log_debug!("{}: Successfully deserialized basket state", operation);
+    Ok(basket)
+}
+
+/// Helper for basket state serialization with validation
+fn serialize_basket_state_safe(
+    basket: &BasketState,
+    basket_data: &mut [u8],
+    operation: &str,
+) -> ProcessResult {
+    log_debug!("{}: Starting basket state serialization", operation);
+
+    // Validate basket state before serialization
+    basket.validate().map_err(|err| {
+        log_error!("{}: Basket state validation failed: {}", operation, err);
+        VaultError::InvalidAccountData
+    })?;
+
+    let serialized_data = basket.to_account_bytes()
+        .map_err(|e| {
+            log_error!("{}: Failed to serialize basket state: {}", operation, e);
+            VaultError::InvalidAccountData
+        })?;
+
+    // Validate buffer size
+    if basket_data.len() < serialized_data.len() {
+        log_error!("{}: Basket state account data buffer too small - required: {}, available: {}",
+             operation, serialized_data.len(), basket_data.len());
+        return Err(VaultError::InvalidAccountData.into());
+    }
+
+    basket_data[..serialized_data.len()].copy_from_slice(&serialized_data);
+
+    log_debug!("{}: Successfully serialized basket state", operation);
+    Ok(())
+}
+
+/// Helper for safe basket balance deserialization with validation
+fn deserialize_basket_balance_safe(
+    basket_balance_data: &[u8],
+    operation: &str,
+) -> ProcessResult<BasketBalance> {
+    log_debug!("{}: Starting basket balance deserialization", operation);
+    log_debug!("{}: Basket balance buffer length: {}, Expected size: {}",
+         operation, basket_balance_data.len(), BasketBalance::SIZE);
+
+    // Validate buffer size before deserialization
+    if basket_balance_data.len() != BasketBalance::SIZE {
+        log_error!("{}: Basket balance buffer size mismatch - expected: {}, actual: {}",
+             operation, BasketBalance::SIZE, basket_balance_data.len());
+        return Err(VaultError::AccountNotInitialized.into());
+    }
+    check_account_header::<BasketBalance>(basket_balance_data, "basket balance", operation)?;
+
+    // Attempt deserialization
+    let basket_balance = BasketBalance::try_from_slice(&basket_balance_data[ACCOUNT_HEADER_LEN..])
+        .map_err(|e| {
+            log_error!("{}: Failed to deserialize basket balance: {}", operation, e);
+            VaultError::AccountNotInitialized
+        })?;
+
+    // Validate deserialized state
+    basket_balance.validate().map_err(|err| {
+        log_error!("{}: Deserialized basket balance validation failed: {}", operation, err);
+        VaultError::InvalidAccountData
+    })?;
+
+    log_debug!("{}: Successfully deserialized basket balance", operation);
+    Ok(basket_balance)
+}
+
+/// Helper for basket balance serialization with validation
+fn serialize_basket_balance_safe(
+    basket_balance: &BasketBalance,
+    basket_balance_data: &mut [u8],
+    operation: &str,
+) -> ProcessResult {
+    log_debug!("{}: Starting basket balance serialization", operation);
+
+    // Validate basket balance before serialization
+    basket_balance.validate().map_err(|err| {
+        log_error!("{}: Basket balance validation failed: {}", operation, err);
+        VaultError::InvalidAccountData
+    })?;
+
+    let serialized_data = basket_balance.to_account_bytes()
+        .map_err(|e| {
+            log_error!("{}: Failed to serialize basket balance: {}", operation, e);
+            VaultError::InvalidAccountData
+        })?;
+
+    // Validate buffer size
+    if basket_balance_data.len() < serialized_data.len() {
+        log_error!("{}: Basket balance account data buffer too small - required: {}, available: {}",
+             operation, serialized_data.len(), basket_balance_data.len());
+        return Err(VaultError::InvalidAccountData.into());
+    }
+
+    basket_balance_data[..serialized_data.len()].copy_from_slice(&serialized_data);
+
+    log_debug!("{}: Successfully serialized basket balance", operation);
     Ok(())
 }
 
+/// Load and validate a basket state account owned by this program
+fn load_basket_state(
+    program_id: &Pubkey,
+    basket_info: &AccountInfo,
+    operation: &str,
+) -> ProcessResult<BasketState> {
+    if basket_info.owner != program_id {
+        log_error!("{}: Basket state account not owned by program", operation);
+        return Err(VaultError::InvalidAccountOwner.with_account(basket_info.key));
+    }
+
+    let basket_data = basket_info.try_borrow_data()?;
+    let basket = deserialize_basket_state_safe(&basket_data, operation).with_account(basket_info.key)?;
+
+    if !basket.is_operational() {
+        log_error!("{}: Basket is closed", operation);
+        return Err(VaultError::VaultClosed.with_account(basket_info.key));
+    }
+
+    Ok(basket)
+}
+
 /// Legacy helper function for backward compatibility - delegates to safe version
 fn serialize_vault_state(
     vault_state: &VaultState,
     vault_state_data: &mut [u8],
     operation: &str,
-) -> Result<(), ProgramError> {
+) -> ProcessResult {
     serialize_vault_state_safe(vault_state, vault_state_data, operation)
 }
 
+/// Credit a user's accrued yield to their balance and to the vault total
+fn settle_user_yield(
+    vault_state: &mut VaultState,
+    user_balance: &mut UserBalance,
+    operation: &str,
+) -> ProcessResult {
+    user_balance
+        .settle_yield(vault_state.yield_per_token)
+        .and_then(|credited| vault_state.add_deposit(credited))
+        .map_err(|err| {
+            log_error!("{}: Failed to settle yield: {}", operation, err);
+            VaultError::ArithmeticOverflow.into()
+        })
+}
+
+/// Verify the destination and vault token accounts for a withdrawal of `amount`
+fn verify_withdrawal_token_accounts(
+    vault_state: &VaultState,
+    destination_token_account_info: &AccountInfo,
+    vault_token_account_info: &AccountInfo,
+    amount: u64,
+    operation: &str,
+) -> ProcessResult {
+    let vault_token_amount = verify_payout_token_accounts(
+        vault_state,
+        destination_token_account_info,
+        vault_token_account_info,
+        operation,
+    )?;
+
+    // Verify vault has sufficient tokens
+    if vault_token_amount < amount {
+        log_error!("{}: Insufficient vault token balance. Required: {}, Available: {}",
+             operation, amount, vault_token_amount);
+        return Err(VaultError::InsufficientFunds.into());
+    }
+
+    Ok(())
+}
+
+/// Verify the destination and vault token accounts for a payout, returning the vault's token amount
+fn verify_payout_token_accounts(
+    vault_state: &VaultState,
+    destination_token_account_info: &AccountInfo,
+    vault_token_account_info: &AccountInfo,
+    operation: &str,
+) -> ProcessResult<u64> {
+    // Verify token accounts are owned by SPL Token program
+    if destination_token_account_info.owner != &spl_token::id() {
+        log_error!("{}: Destination token account must be owned by SPL Token program", operation);
+        return Err(VaultError::InvalidTokenAccount.with_account(destination_token_account_info.key));
+    }
+    if vault_token_account_info.owner != &spl_token::id() {
+        log_error!("{}: Vault token account must be owned by SPL Token program", operation);
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
+    }
+
+    // Verify token accounts match the vault's mint
+    let destination_token_data = destination_token_account_info.try_borrow_data()?;
+    let destination_token_account = spl_token::state::Account::unpack(&destination_token_data)
+        .map_err(|_| {
+            log_error!("{}: Failed to unpack destination token account", operation);
+            VaultError::InvalidTokenAccount
+        })?;
+
+    if destination_token_account.mint != vault_state.token_mint {
+        log_error!("{}: Destination token account mint mismatch", operation);
+        return Err(VaultError::InvalidMint.with_account(destination_token_account_info.key));
+    }
+    drop(destination_token_data);
+
+    if *vault_token_account_info.key != vault_state.token_account {
+        log_error!("{}: Vault token account mismatch. Expected: {}, Got: {}",
+             operation, vault_state.token_account, vault_token_account_info.key);
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
+    }
+    let vault_token_data = vault_token_account_info.try_borrow_data()?;
+    let vault_token_account = spl_token::state::Account::unpack(&vault_token_data)
+        .map_err(|_| {
+            log_error!("{}: Failed to unpack vault token account", operation);
+            VaultError::InvalidTokenAccount
+        })?;
+
+    if vault_token_account.mint != vault_state.token_mint {
+        log_error!("{}: Vault token account mint mismatch", operation);
+        return Err(VaultError::InvalidMint.with_account(vault_token_account_info.key));
+    }
+
+    Ok(vault_token_account.amount)
+}
+
+/// Move `amount` of a user's balance into the withdrawal queue, returning the new pending amount
+fn queue_user_withdrawal(
+    vault_state: &mut VaultState,
+    user_balance: &mut UserBalance,
+    amount: u64,
+    operation: &str,
+) -> ProcessResult<u64> {
+    user_balance.queue_withdrawal(amount).map_err(|err| {
+        log_error!("{}: Failed to queue withdrawal: {}", operation, err);
+        VaultError::ArithmeticOverflow
+    })?;
+    vault_state.add_queued(amount).map_err(|err| {
+        log_error!("{}: Failed to update queued total: {}", operation, err);
+        VaultError::ArithmeticOverflow
+    })?;
+    Ok(user_balance.pending_withdrawal)
+}
+
+/// PDA seed components of a vault state account, needed to sign for it
+struct VaultSeeds {
+    owner: Pubkey,
+    token_mint: Pubkey,
+    vault_id: u64,
+    bump: u8,
+}
+
+impl From<&VaultState> for VaultSeeds {
+    fn from(vault_state: &VaultState) -> Self {
+        Self {
+            owner: vault_state.owner,
+            token_mint: vault_state.token_mint,
+            vault_id: vault_state.vault_id,
+            bump: vault_state.bump,
+        }
+    }
+}
+
+impl From<&PodVaultState> for VaultSeeds {
+    fn from(vault_state: &PodVaultState) -> Self {
+        Self {
+            owner: vault_state.owner,
+            token_mint: vault_state.token_mint,
+            vault_id: vault_state.vault_id.get(),
+            bump: vault_state.bump,
+        }
+    }
+}
+
+/// Transfer tokens out of the vault token account, signing with the vault state PDA
+fn transfer_from_vault<'a>(
+    vault_state: impl Into<VaultSeeds>,
+    vault_state_info: &AccountInfo<'a>,
+    vault_token_account_info: &AccountInfo<'a>,
+    destination_info: &AccountInfo<'a>,
+    token_program_info: &AccountInfo<'a>,
+    amount: u64,
+    operation: &str,
+) -> ProcessResult {
+    let transfer_ix = spl_token::instruction::transfer(
+        &spl_token::id(),
+        vault_token_account_info.key,
+        destination_info.key,
+        vault_state_info.key, // Vault state account is the authority
+        &[],
+        amount,
+    )?;
+
+    let vault_seeds = vault_state.into();
+    let vault_state_seeds = &[
+        crate::utils::VAULT_SEED,
+        vault_seeds.owner.as_ref(),
+        vault_seeds.token_mint.as_ref(),
+        &vault_seeds.vault_id.to_le_bytes(),
+        &[vault_seeds.bump],
+    ];
+
+    invoke_signed(
+        &transfer_ix,
+        &[
+            vault_token_account_info.clone(),
+            destination_info.clone(),
+            vault_state_info.clone(),
+            token_program_info.clone(),
+        ],
+        &[vault_state_seeds],
+    ).map_err(|e| {
+        log_error!("{}: Token transfer failed: {}", operation, e);
+        e.into()
+    })
+}
+
 pub fn process_instruction(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     instruction_data: &[u8],
-) -> ProgramResult {
+) -> ProcessResult {
     let instruction = unpack(instruction_data)?;
     
     match instruction {
-        VaultInstruction::Initialize => process_initialize(program_id, accounts),
+        VaultInstruction::Initialize { vault_id, metadata } => {
+            process_initialize(program_id, accounts, vault_id, metadata)
+        }
         VaultInstruction::Deposit { amount } => {
             process_deposit(program_id, accounts, amount)
         }
         VaultInstruction::Withdraw { amount } => {
+            #[cfg(feature = "borsh-baseline")]
+            let process_withdraw = borsh_baseline::process_withdraw;
             process_withdraw(program_id, accounts, amount)
         }
         VaultInstruction::WithdrawAll => {
@@ -243,28 +803,97 @@ pub fn process_instruction(
         VaultInstruction::Close => {
             process_close(program_id, accounts)
         }
-    }
-}
-
-/// Process Initialize instruction
-/// Creates a new vault with the specified owner and token mint
-pub fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
-    let account_info_iter = &mut accounts.iter();
-    
-    // Validate minimum number of accounts
-    if accounts.len() < 7 {
-        msg!("Initialize: Insufficient accounts provided");
-        return Err(VaultError::InvalidInput.into());
-    }
-    
-    // Expected accounts:
-    // 0. [signer, writable] Vault owner
-    // 1. [writable] Vault state account (PDA)
-    // 2. [writable] Vault token account
-    // 3. [] Token mint
-    // 4. [] SPL Token program
-    // 5. [] System program
-    // 6. [] Rent sysvar
+        VaultInstruction::ApproveDelegate { delegate, amount } => {
+            process_approve_delegate(program_id, accounts, delegate, amount)
+        }
+        VaultInstruction::RevokeDelegate => {
+            process_revoke_delegate(program_id, accounts)
+        }
+        VaultInstruction::WithdrawAsDelegate { amount } => {
+            process_withdraw_as_delegate(program_id, accounts, amount)
+        }
+        VaultInstruction::TransferBalance { to, amount } => {
+            process_transfer_balance(program_id, accounts, to, amount)
+        }
+        VaultInstruction::DepositFor { beneficiary, amount } => {
+            process_deposit_for(program_id, accounts, beneficiary, amount)
+        }
+        VaultInstruction::WithdrawWithPermit { amount, nonce, expiry } => {
+            process_withdraw_with_permit(program_id, accounts, amount, nonce, expiry)
+        }
+        VaultInstruction::InitializeBasket { basket_id } => {
+            process_initialize_basket(program_id, accounts, basket_id)
+        }
+        VaultInstruction::AddBasketMint => {
+            process_add_basket_mint(program_id, accounts)
+        }
+        VaultInstruction::BasketDeposit { amount } => {
+            process_basket_deposit(program_id, accounts, amount)
+        }
+        VaultInstruction::BasketWithdraw { amount } => {
+            process_basket_withdraw(program_id, accounts, amount)
+        }
+        VaultInstruction::FlashLoan { amount } => {
+            process_flash_loan(program_id, accounts, amount)
+        }
+        VaultInstruction::AddStrategy { cap } => {
+            process_add_strategy(program_id, accounts, cap)
+        }
+        VaultInstruction::Allocate { strategy_index, amount } => {
+            process_allocate(program_id, accounts, strategy_index, amount)
+        }
+        VaultInstruction::Deallocate { strategy_index, amount } => {
+            process_deallocate(program_id, accounts, strategy_index, amount)
+        }
+        VaultInstruction::Harvest { strategy_index } => {
+            process_harvest(program_id, accounts, strategy_index)
+        }
+        VaultInstruction::SetMinIdleBps { min_idle_bps } => {
+            process_set_min_idle_bps(program_id, accounts, min_idle_bps)
+        }
+        VaultInstruction::ClaimWithdrawal => {
+            process_claim_withdrawal(program_id, accounts)
+        }
+        VaultInstruction::MigrateVault => {
+            process_migrate_account::<VaultStateV1, VaultState>(program_id, accounts, "vault state", "MigrateVault")
+        }
+        VaultInstruction::MigrateUserBalance => {
+            process_migrate_account::<UserBalanceV1, UserBalance>(program_id, accounts, "user balance", "MigrateUserBalance")
+        }
+        VaultInstruction::UpdateMetadata { metadata } => {
+            process_update_metadata(program_id, accounts, metadata)
+        }
+        VaultInstruction::CloseBasket => {
+            process_close_basket(program_id, accounts)
+        }
+    }
+}
+
+/// Process Initialize instruction
+/// Creates a new vault with the specified owner and token mint
+pub fn process_initialize(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    vault_id: u64,
+    metadata: Option<VaultMetadataArgs>,
+) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+    
+    // Validate minimum number of accounts
+    if accounts.len() < 7 {
+        log_error!("Initialize: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+    
+    // Expected accounts:
+    // 0. [signer, writable] Vault owner
+    // 1. [writable] Vault state account (PDA)
+    // 2. [writable] Vault token account
+    // 3. [] Token mint
+    // 4. [] SPL Token program
+    // 5. [] System program
+    // 6. [] Rent sysvar
+    // 7. [writable] Vault metadata account (PDA), only when metadata is provided
     let owner_info = next_account_info(account_info_iter)?;
     let vault_state_info = next_account_info(account_info_iter)?;
     let vault_token_account_info = next_account_info(account_info_iter)?;
@@ -272,86 +901,91 @@ pub fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> Prog
     let token_program_info = next_account_info(account_info_iter)?;
     let system_program_info = next_account_info(account_info_iter)?;
     let rent_info = next_account_info(account_info_iter)?;
+    let metadata_info = match metadata {
+        Some(_) => Some(next_account_info(account_info_iter)?),
+        None => None,
+    };
 
     // Comprehensive account validation
     
     // Verify owner is signer and writable
     if !owner_info.is_signer {
-        msg!("Initialize: Owner must be signer");
-        return Err(VaultError::UnauthorizedAccess.into());
+        log_error!("Initialize: Owner must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
     }
     if !owner_info.is_writable {
-        msg!("Initialize: Owner account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Initialize: Owner account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(owner_info.key));
     }
     
     // Verify vault state account is writable
     if !vault_state_info.is_writable {
-        msg!("Initialize: Vault state account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Initialize: Vault state account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
     }
     
     // Verify vault token account is writable and owned by token program
     if !vault_token_account_info.is_writable {
-        msg!("Initialize: Vault token account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Initialize: Vault token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_token_account_info.key));
     }
     if vault_token_account_info.owner != &spl_token::id() {
-        msg!("Initialize: Vault token account must be owned by SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("Initialize: Vault token account must be owned by SPL Token program");
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
     }
     
     // Verify token mint is valid and owned by token program
     if token_mint_info.owner != &spl_token::id() {
-        msg!("Initialize: Token mint must be owned by SPL Token program");
-        return Err(VaultError::InvalidMint.into());
+        log_error!("Initialize: Token mint must be owned by SPL Token program");
+        return Err(VaultError::InvalidMint.with_account(token_mint_info.key));
     }
     
     // Verify token mint structure
     let mint_data = token_mint_info.try_borrow_data()?;
     if mint_data.len() != spl_token::state::Mint::LEN {
-        msg!("Initialize: Invalid token mint data length");
+        log_error!("Initialize: Invalid token mint data length");
         return Err(VaultError::InvalidMint.into());
     }
-    let mint = spl_token::state::Mint::unpack(&mint_data)
+    let decimals = spl_token::state::Mint::unpack(&mint_data)
         .map_err(|_| {
-            msg!("Initialize: Failed to unpack token mint");
+            log_error!("Initialize: Failed to unpack token mint");
             VaultError::InvalidMint
-        })?;
+        })?
+        .decimals;
     drop(mint_data);
     
     // Verify vault token account matches the mint
     let vault_token_data = vault_token_account_info.try_borrow_data()?;
     if vault_token_data.len() != spl_token::state::Account::LEN {
-        msg!("Initialize: Invalid vault token account data length");
+        log_error!("Initialize: Invalid vault token account data length");
         return Err(VaultError::InvalidTokenAccount.into());
     }
     let vault_token_account = spl_token::state::Account::unpack(&vault_token_data)
         .map_err(|_| {
-            msg!("Initialize: Failed to unpack vault token account");
+            log_error!("Initialize: Failed to unpack vault token account");
             VaultError::InvalidTokenAccount
         })?;
     
     if vault_token_account.mint != *token_mint_info.key {
-        msg!("Initialize: Vault token account mint mismatch");
-        return Err(VaultError::InvalidMint.into());
+        log_error!("Initialize: Vault token account mint mismatch");
+        return Err(VaultError::InvalidMint.with_account(token_mint_info.key));
     }
     drop(vault_token_data);
     
     // Verify program accounts
     if token_program_info.key != &spl_token::id() {
-        msg!("Initialize: Invalid SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("Initialize: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
     }
     
     if system_program_info.key != &solana_program::system_program::id() {
-        msg!("Initialize: Invalid System program");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Initialize: Invalid System program");
+        return Err(VaultError::InvalidProgramId.with_account(system_program_info.key));
     }
     
     if rent_info.key != &solana_program::sysvar::rent::id() {
-        msg!("Initialize: Invalid Rent sysvar");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Initialize: Invalid Rent sysvar");
+        return Err(VaultError::InvalidSysvar.with_account(rent_info.key));
     }
     
     // Derive and verify vault state PDA
@@ -359,23 +993,24 @@ pub fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> Prog
         program_id,
         owner_info.key,
         token_mint_info.key,
+        vault_id,
     )?;
     
     if vault_state_pda != *vault_state_info.key {
-        msg!("Initialize: Vault state PDA mismatch. Expected: {}, Got: {}", 
+        log_error!("Initialize: Vault state PDA mismatch. Expected: {}, Got: {}", 
              vault_state_pda, vault_state_info.key);
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidPda.with_account(vault_state_info.key));
     }
     
     // Verify vault state account is uninitialized
     if vault_state_info.owner != &solana_program::system_program::id() {
-        msg!("Initialize: Vault state account already initialized");
-        return Err(VaultError::AccountNotInitialized.into());
+        log_error!("Initialize: Vault state account already initialized");
+        return Err(VaultError::AccountNotInitialized.with_account(vault_state_info.key));
     }
     
     if vault_state_info.data_len() != 0 {
-        msg!("Initialize: Vault state account must be empty");
-        return Err(VaultError::AccountNotInitialized.into());
+        log_error!("Initialize: Vault state account must be empty");
+        return Err(VaultError::AccountNotInitialized.with_account(vault_state_info.key));
     }
     
     // Get rent and validate rent exemption
@@ -387,9 +1022,9 @@ pub fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> Prog
     
     // Verify owner has sufficient lamports
     if owner_info.lamports() < vault_state_lamports {
-        msg!("Initialize: Insufficient lamports for rent exemption. Required: {}, Available: {}", 
+        log_error!("Initialize: Insufficient lamports for rent exemption. Required: {}, Available: {}", 
              vault_state_lamports, owner_info.lamports());
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::NotRentExempt.with_account(owner_info.key));
     }
     
     // Create vault state account
@@ -405,6 +1040,7 @@ pub fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> Prog
         crate::utils::VAULT_SEED,
         owner_info.key.as_ref(),
         token_mint_info.key.as_ref(),
+        &vault_id.to_le_bytes(),
         &[vault_state_bump],
     ];
     
@@ -417,7 +1053,7 @@ pub fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> Prog
         ],
         &[vault_state_seeds],
     ).map_err(|e| {
-        msg!("Initialize: Failed to create vault state account: {}", e);
+        log_error!("Initialize: Failed to create vault state account: {}", e);
         e
     })?;
     
@@ -426,54 +1062,178 @@ pub fn process_initialize(program_id: &Pubkey, accounts: &[AccountInfo]) -> Prog
         *owner_info.key,
         *token_mint_info.key,
         *vault_token_account_info.key,
+        vault_id,
         vault_state_bump,
     );
     
     // Validate the vault state
     vault_state.validate().map_err(|err| {
-        msg!("Initialize: Vault state validation failed: {}", err);
-        VaultError::InvalidInput
+        log_error!("Initialize: Vault state validation failed: {}", err);
+        VaultError::InvalidAccountData
     })?;
     
     // Serialize and store vault state
     let mut vault_state_data = vault_state_info.try_borrow_mut_data()
         .map_err(|e| {
-            msg!("Initialize: Failed to borrow vault state data: {}", e);
-            VaultError::InvalidInput
+            log_error!("Initialize: Failed to borrow vault state data: {}", e);
+            VaultError::InvalidAccountData
         })?;
     
-    msg!("Initialize: Account data length before serialization: {}", vault_state_data.len());
+    log_debug!("Initialize: Account data length before serialization: {}", vault_state_data.len());
     
-    serialize_vault_state(&vault_state, &mut *vault_state_data, "Initialize")?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "Initialize")?;
     
-    msg!(
-        "Vault initialized successfully. Owner: {}, Mint: {}, Token Account: {}, Bump: {}",
+    log_info!(
+        "Vault initialized successfully. Owner: {}, Mint: {}, Vault ID: {}, Token Account: {}, Bump: {}",
         owner_info.key,
         token_mint_info.key,
+        vault_id,
         vault_token_account_info.key,
         vault_state_bump
     );
     
+    VaultEvent::Initialized {
+        vault: *vault_state_info.key,
+        owner: *owner_info.key,
+        mint: *token_mint_info.key,
+        vault_id,
+    }.emit();
+    drop(vault_state_data);
+
+    if let (Some(args), Some(metadata_info)) = (metadata, metadata_info) {
+        create_vault_metadata(
+            program_id,
+            owner_info,
+            metadata_info,
+            system_program_info,
+            vault_state_info.key,
+            &args,
+            decimals,
+            "Initialize",
+        )?;
+
+        VaultEvent::MetadataUpdated {
+            vault: *vault_state_info.key,
+            name: args.name,
+            uri: args.uri,
+        }.emit();
+    }
+
     Ok(())
 }
 
+/// Create the metadata PDA of `vault_state` holding `args`. Rent is paid by `payer_info`.
+#[allow(clippy::too_many_arguments)]
+fn create_vault_metadata<'a>(
+    program_id: &Pubkey,
+    payer_info: &AccountInfo<'a>,
+    metadata_info: &AccountInfo<'a>,
+    system_program_info: &AccountInfo<'a>,
+    vault_state: &Pubkey,
+    args: &VaultMetadataArgs,
+    decimals: u8,
+    operation: &str,
+) -> ProcessResult {
+    let (metadata_pda, metadata_bump) = derive_metadata_pda(program_id, vault_state)?;
+    if metadata_pda != *metadata_info.key {
+        log_error!("{}: Metadata PDA mismatch. Expected: {}, Got: {}",
+             operation, metadata_pda, metadata_info.key);
+        return Err(VaultError::InvalidPda.with_account(metadata_info.key));
+    }
+    if !metadata_info.is_writable {
+        log_error!("{}: Metadata account must be writable", operation);
+        return Err(VaultError::AccountNotWritable.with_account(metadata_info.key));
+    }
+    if metadata_info.owner != &solana_program::system_program::id() || metadata_info.data_len() != 0 {
+        log_error!("{}: Metadata account already initialized", operation);
+        return Err(VaultError::AccountAlreadyInitialized.with_account(metadata_info.key));
+    }
+
+    let metadata = VaultMetadata::new(
+        *vault_state,
+        &args.name,
+        &args.uri,
+        decimals,
+        Clock::get()?.unix_timestamp,
+        metadata_bump,
+    ).map_err(|err| {
+        log_error!("{}: {}", operation, err);
+        VaultError::InvalidMetadata
+    })?;
+
+    let rent = Rent::get()?;
+    let create_metadata_ix = system_instruction::create_account(
+        payer_info.key,
+        metadata_info.key,
+        rent.minimum_balance(VaultMetadata::SIZE),
+        VaultMetadata::SIZE as u64,
+        program_id,
+    );
+    let metadata_seeds = &[METADATA_SEED, vault_state.as_ref(), &[metadata_bump]];
+
+    invoke_signed(
+        &create_metadata_ix,
+        &[payer_info.clone(), metadata_info.clone(), system_program_info.clone()],
+        &[metadata_seeds],
+    ).map_err(|e| {
+        log_error!("{}: Failed to create metadata account: {}", operation, e);
+        e
+    })?;
+
+    let mut metadata_data = metadata_info.try_borrow_mut_data()?;
+    serialize_account_safe(&metadata, &mut metadata_data, "vault metadata", operation)
+}
+
 /// Process Deposit instruction
 /// Allows users to deposit SPL tokens into the vault
-pub fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+pub fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProcessResult {
+    #[cfg(feature = "borsh-baseline")]
+    let deposit_into_balance = borsh_baseline::deposit_into_balance;
+    deposit_into_balance(program_id, accounts, None, amount, "Deposit")
+}
+
+/// Process DepositFor instruction
+/// Allows a depositor to fund another user's vault balance
+pub fn process_deposit_for(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    beneficiary: Pubkey,
+    amount: u64,
+) -> ProcessResult {
+    if beneficiary == Pubkey::default() {
+        log_error!("DepositFor: Invalid beneficiary pubkey");
+        return Err(VaultError::InvalidInput.into());
+    }
+
+    #[cfg(feature = "borsh-baseline")]
+    let deposit_into_balance = borsh_baseline::deposit_into_balance;
+    deposit_into_balance(program_id, accounts, Some(beneficiary), amount, "DepositFor")
+}
+
+/// Shared deposit logic. Tokens and rent come from the signing depositor and are credited
+/// to `beneficiary`, or to the depositor themselves when no beneficiary is given.
+#[cfg_attr(feature = "borsh-baseline", allow(dead_code))]
+fn deposit_into_balance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    beneficiary: Option<Pubkey>,
+    amount: u64,
+    operation: &str,
+) -> ProcessResult {
     let account_info_iter = &mut accounts.iter();
     
     // Validate minimum number of accounts
     if accounts.len() < 7 {
-        msg!("Deposit: Insufficient accounts provided");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("{}: Insufficient accounts provided", operation);
+        return Err(VaultError::NotEnoughAccounts.into());
     }
     
     // Expected accounts:
-    // 0. [signer, writable] User account
+    // 0. [signer, writable] User account (depositor)
     // 1. [writable] User token account
     // 2. [writable] Vault token account
     // 3. [writable] Vault state account
-    // 4. [writable] User balance account (PDA)
+    // 4. [writable] User balance account (PDA) of the beneficiary
     // 5. [] SPL Token program
     // 6. [] System program (for PDA creation if needed)
     let user_info = next_account_info(account_info_iter)?;
@@ -483,174 +1243,134 @@ pub fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], amount: u6
     let user_balance_info = next_account_info(account_info_iter)?;
     let token_program_info = next_account_info(account_info_iter)?;
     let system_program_info = next_account_info(account_info_iter)?;
+    let beneficiary = beneficiary.unwrap_or(*user_info.key);
 
     // Validate deposit amount
     if amount == 0 {
-        msg!("Deposit: Amount must be greater than zero");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("{}: Amount must be greater than zero", operation);
+        return Err(VaultError::ZeroAmount.into());
     }
 
     // Verify user is signer
     if !user_info.is_signer {
-        msg!("Deposit: User must be signer");
-        return Err(VaultError::UnauthorizedAccess.into());
+        log_error!("{}: User must be signer", operation);
+        return Err(VaultError::UnauthorizedAccess.with_account(user_info.key));
     }
 
     // Verify accounts are writable
     if !user_token_account_info.is_writable {
-        msg!("Deposit: User token account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("{}: User token account must be writable", operation);
+        return Err(VaultError::AccountNotWritable.with_account(user_token_account_info.key));
     }
     if !vault_token_account_info.is_writable {
-        msg!("Deposit: Vault token account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("{}: Vault token account must be writable", operation);
+        return Err(VaultError::AccountNotWritable.with_account(vault_token_account_info.key));
     }
     if !vault_state_info.is_writable {
-        msg!("Deposit: Vault state account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("{}: Vault state account must be writable", operation);
+        return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
     }
     if !user_balance_info.is_writable {
-        msg!("Deposit: User balance account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("{}: User balance account must be writable", operation);
+        return Err(VaultError::AccountNotWritable.with_account(user_balance_info.key));
     }
 
     // Verify program accounts
     if token_program_info.key != &spl_token::id() {
-        msg!("Deposit: Invalid SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("{}: Invalid SPL Token program", operation);
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
     }
     if system_program_info.key != &solana_program::system_program::id() {
-        msg!("Deposit: Invalid System program");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("{}: Invalid System program", operation);
+        return Err(VaultError::InvalidProgramId.with_account(system_program_info.key));
     }
 
-    // Load and validate vault state
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("{}: Vault state account not owned by program", operation);
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    // Read the vault in place
     let vault_state_data = vault_state_info.try_borrow_data()?;
-    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "Deposit")?;
-    drop(vault_state_data); // Drop the read borrow early
+    let vault_state = load_zero_copy::<PodVaultState>(&vault_state_data, "vault state", operation)
+        .with_account(vault_state_info.key)?;
+    if vault_state.is_locked.get() {
+        log_error!("{}: Vault is locked by an in-flight flash loan", operation);
+        return Err(VaultError::VaultLocked.into());
+    }
 
     // Check if vault is operational
     if !vault_state.is_operational() {
-        msg!("Deposit: Vault is closed");
+        log_error!("{}: Vault is closed", operation);
         return Err(VaultError::VaultClosed.into());
     }
-
-    // Verify vault state account ownership
-    if vault_state_info.owner != program_id {
-        msg!("Deposit: Vault state account not owned by program");
-        return Err(VaultError::InvalidInput.into());
-    }
+    let token_mint = vault_state.token_mint;
+    let vault_token_account_key = vault_state.token_account;
+    let yield_per_token = vault_state.yield_per_token.get();
+    drop(vault_state_data); // Drop the read borrow early
 
     // Verify token accounts are owned by SPL Token program
     if user_token_account_info.owner != &spl_token::id() {
-        msg!("Deposit: User token account must be owned by SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("{}: User token account must be owned by SPL Token program", operation);
+        return Err(VaultError::InvalidTokenAccount.with_account(user_token_account_info.key));
     }
     if vault_token_account_info.owner != &spl_token::id() {
-        msg!("Deposit: Vault token account must be owned by SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("{}: Vault token account must be owned by SPL Token program", operation);
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
     }
 
     // Verify token accounts match the vault's mint
     let user_token_data = user_token_account_info.try_borrow_data()?;
     let user_token_account = spl_token::state::Account::unpack(&user_token_data)
         .map_err(|_| {
-            msg!("Deposit: Failed to unpack user token account");
+            log_error!("{}: Failed to unpack user token account", operation);
             VaultError::InvalidTokenAccount
         })?;
     
-    if user_token_account.mint != vault_state.token_mint {
-        msg!("Deposit: User token account mint mismatch");
-        return Err(VaultError::InvalidMint.into());
+    if user_token_account.mint != token_mint {
+        log_error!("{}: User token account mint mismatch", operation);
+        return Err(VaultError::InvalidMint.with_account(user_token_account_info.key));
     }
 
     // Verify user has sufficient balance
     if user_token_account.amount < amount {
-        msg!("Deposit: Insufficient user token balance. Required: {}, Available: {}", 
-             amount, user_token_account.amount);
+        log_error!("{}: Insufficient user token balance. Required: {}, Available: {}", 
+             operation, amount, user_token_account.amount);
         return Err(VaultError::InsufficientFunds.into());
     }
     drop(user_token_data);
 
     // Verify vault token account
+    if *vault_token_account_info.key != vault_token_account_key {
+        log_error!("{}: Vault token account mismatch. Expected: {}, Got: {}",
+             operation, vault_token_account_key, vault_token_account_info.key);
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
+    }
     let vault_token_data = vault_token_account_info.try_borrow_data()?;
     let vault_token_account = spl_token::state::Account::unpack(&vault_token_data)
         .map_err(|_| {
-            msg!("Deposit: Failed to unpack vault token account");
+            log_error!("{}: Failed to unpack vault token account", operation);
             VaultError::InvalidTokenAccount
         })?;
     
-    if vault_token_account.mint != vault_state.token_mint {
-        msg!("Deposit: Vault token account mint mismatch");
-        return Err(VaultError::InvalidMint.into());
+    if vault_token_account.mint != token_mint {
+        log_error!("{}: Vault token account mint mismatch", operation);
+        return Err(VaultError::InvalidMint.with_account(vault_token_account_info.key));
     }
     drop(vault_token_data);
 
-    // Derive and verify user balance PDA
-    let (user_balance_pda, user_balance_bump) = derive_user_balance_pda(
+    // Derive and verify the beneficiary's balance PDA, creating the account on first deposit
+    create_user_balance_if_missing(
         program_id,
-        user_info.key,
+        user_info,
+        user_balance_info,
+        system_program_info,
+        &beneficiary,
         vault_state_info.key,
+        operation,
     )?;
 
-    if user_balance_pda != *user_balance_info.key {
-        msg!("Deposit: User balance PDA mismatch. Expected: {}, Got: {}", 
-             user_balance_pda, user_balance_info.key);
-        return Err(VaultError::InvalidInput.into());
-    }
-
-    // Handle user balance account creation or loading
-    let mut user_balance = if user_balance_info.owner == &solana_program::system_program::id() {
-        // Account doesn't exist, create it
-        let rent = Rent::get()?;
-        let user_balance_space = UserBalance::SIZE;
-        let user_balance_lamports = rent.minimum_balance(user_balance_space);
-
-        let create_user_balance_ix = system_instruction::create_account(
-            user_info.key,
-            user_balance_info.key,
-            user_balance_lamports,
-            user_balance_space as u64,
-            program_id,
-        );
-
-        let user_balance_seeds = &[
-            crate::utils::USER_BALANCE_SEED,
-            user_info.key.as_ref(),
-            vault_state_info.key.as_ref(),
-            &[user_balance_bump],
-        ];
-
-        invoke_signed(
-            &create_user_balance_ix,
-            &[
-                user_info.clone(),
-                user_balance_info.clone(),
-                system_program_info.clone(),
-            ],
-            &[user_balance_seeds],
-        ).map_err(|e| {
-            msg!("Deposit: Failed to create user balance account: {}", e);
-            e
-        })?;
-
-        // Initialize new user balance
-        UserBalance::new(*user_info.key, *vault_state_info.key, user_balance_bump)
-    } else if user_balance_info.owner == program_id {
-        // Account exists, load it
-        let user_balance_data = user_balance_info.try_borrow_data()?;
-        deserialize_user_balance_safe(&user_balance_data, "Deposit")?
-    } else {
-        msg!("Deposit: User balance account has invalid owner");
-        return Err(VaultError::InvalidInput.into());
-    };
-
-    // Validate user balance account
-    user_balance.validate().map_err(|err| {
-        msg!("Deposit: User balance validation failed: {}", err);
-        VaultError::InvalidInput
-    })?;
-
     // Transfer tokens from user to vault
     let transfer_ix = spl_token::instruction::transfer(
         &spl_token::id(),
@@ -670,52 +1390,68 @@ pub fn process_deposit(program_id: &Pubkey, accounts: &[AccountInfo], amount: u6
             token_program_info.clone(),
         ],
     ).map_err(|e| {
-        msg!("Deposit: Token transfer failed: {}", e);
+        log_error!("{}: Token transfer failed: {}", operation, e);
         e
     })?;
 
-    // Update user balance with overflow protection
-    user_balance.add_balance(amount).map_err(|err| {
-        msg!("Deposit: Failed to update user balance: {}", err);
+    // Update user balance in place with overflow protection
+    let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
+    let user_balance = load_zero_copy_mut::<PodUserBalance>(&mut user_balance_data, "user balance", operation)
+        .with_account(user_balance_info.key)?;
+    user_balance.validate().map_err(|err| {
+        log_error!("{}: User balance validation failed: {}", operation, err);
+        VaultError::InvalidAccountData
+    })?;
+    let credited = user_balance.settle_yield(yield_per_token).map_err(|err| {
+        log_error!("{}: Failed to settle yield: {}", operation, err);
         VaultError::ArithmeticOverflow
     })?;
-
-    // Update vault total deposited with overflow protection
-    vault_state.add_deposit(amount).map_err(|err| {
-        msg!("Deposit: Failed to update vault total: {}", err);
+    user_balance.add_balance(amount).map_err(|err| {
+        log_error!("{}: Failed to update user balance: {}", operation, err);
         VaultError::ArithmeticOverflow
     })?;
-
-    // Save updated user balance
-    let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
-    serialize_user_balance_safe(&user_balance, &mut *user_balance_data, "Deposit")?;
+    let new_balance = user_balance.balance.get();
     drop(user_balance_data);
 
-    // Save updated vault state
-    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?; // Borrow for writing
-    
-    serialize_vault_state(&vault_state, &mut *vault_state_data, "Deposit")?;
+    // Update vault total deposited in place with overflow protection
+    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+    let vault_state = load_vault_state_zero_copy_mut(&mut vault_state_data, operation)
+        .with_account(vault_state_info.key)?;
+    vault_state.add_deposit(amount).and_then(|()| vault_state.add_deposit(credited)).map_err(|err| {
+        log_error!("{}: Failed to update vault total: {}", operation, err);
+        VaultError::ArithmeticOverflow
+    })?;
 
-    msg!(
-        "Deposit successful. User: {}, Amount: {}, New Balance: {}, Vault Total: {}",
+    log_info!(
+        "{} successful. Depositor: {}, Beneficiary: {}, Amount: {}, New Balance: {}, Vault Total: {}",
+        operation,
         user_info.key,
+        beneficiary,
         amount,
-        user_balance.balance,
-        vault_state.total_deposited
+        new_balance,
+        vault_state.total_deposited.get()
     );
 
+    VaultEvent::Deposited {
+        vault: *vault_state_info.key,
+        user: beneficiary,
+        depositor: *user_info.key,
+        amount,
+        balance: new_balance,
+    }.emit();
+
     Ok(())
 }
 
 /// Process Withdraw instruction
 /// Allows users to withdraw SPL tokens from the vault
-pub fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProgramResult {
+pub fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProcessResult {
     let account_info_iter = &mut accounts.iter();
     
     // Validate minimum number of accounts
     if accounts.len() < 6 {
-        msg!("Withdraw: Insufficient accounts provided");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Withdraw: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
     }
     
     // Expected accounts:
@@ -734,210 +1470,244 @@ pub fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u
 
     // Validate withdrawal amount
     if amount == 0 {
-        msg!("Withdraw: Amount must be greater than zero");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Withdraw: Amount must be greater than zero");
+        return Err(VaultError::ZeroAmount.into());
     }
 
     // Verify user is signer
     if !user_info.is_signer {
-        msg!("Withdraw: User must be signer");
-        return Err(VaultError::UnauthorizedAccess.into());
+        log_error!("Withdraw: User must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(user_info.key));
     }
 
     // Verify accounts are writable
     if !user_token_account_info.is_writable {
-        msg!("Withdraw: User token account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Withdraw: User token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(user_token_account_info.key));
     }
     if !vault_token_account_info.is_writable {
-        msg!("Withdraw: Vault token account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Withdraw: Vault token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_token_account_info.key));
     }
     if !vault_state_info.is_writable {
-        msg!("Withdraw: Vault state account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Withdraw: Vault state account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
     }
     if !user_balance_info.is_writable {
-        msg!("Withdraw: User balance account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Withdraw: User balance account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(user_balance_info.key));
     }
 
     // Verify program accounts
     if token_program_info.key != &spl_token::id() {
-        msg!("Withdraw: Invalid SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("Withdraw: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
     }
 
-    // Load and validate vault state
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("Withdraw: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    // Read the vault in place
     let vault_state_data = vault_state_info.try_borrow_data()?;
-    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "Withdraw")?;
-    drop(vault_state_data); // Drop the read borrow early
+    let vault_state = load_zero_copy::<PodVaultState>(&vault_state_data, "vault state", "Withdraw")
+        .with_account(vault_state_info.key)?;
+    if vault_state.is_locked.get() {
+        log_error!("Withdraw: Vault is locked by an in-flight flash loan");
+        return Err(VaultError::VaultLocked.into());
+    }
 
     // Check if vault is operational
     if !vault_state.is_operational() {
-        msg!("Withdraw: Vault is closed");
+        log_error!("Withdraw: Vault is closed");
         return Err(VaultError::VaultClosed.into());
     }
-
-    // Verify vault state account ownership
-    if vault_state_info.owner != program_id {
-        msg!("Withdraw: Vault state account not owned by program");
-        return Err(VaultError::InvalidInput.into());
-    }
+    let token_mint = vault_state.token_mint;
+    let yield_per_token = vault_state.yield_per_token.get();
+    let vault_seeds = VaultSeeds::from(vault_state);
 
     // Verify token accounts are owned by SPL Token program
     if user_token_account_info.owner != &spl_token::id() {
-        msg!("Withdraw: User token account must be owned by SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("Withdraw: User token account must be owned by SPL Token program");
+        return Err(VaultError::InvalidTokenAccount.with_account(user_token_account_info.key));
     }
     if vault_token_account_info.owner != &spl_token::id() {
-        msg!("Withdraw: Vault token account must be owned by SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("Withdraw: Vault token account must be owned by SPL Token program");
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
     }
 
     // Verify token accounts match the vault's mint
     let user_token_data = user_token_account_info.try_borrow_data()?;
     let user_token_account = spl_token::state::Account::unpack(&user_token_data)
         .map_err(|_| {
-            msg!("Withdraw: Failed to unpack user token account");
+            log_error!("Withdraw: Failed to unpack user token account");
             VaultError::InvalidTokenAccount
         })?;
     
-    if user_token_account.mint != vault_state.token_mint {
-        msg!("Withdraw: User token account mint mismatch");
-        return Err(VaultError::InvalidMint.into());
+    if user_token_account.mint != token_mint {
+        log_error!("Withdraw: User token account mint mismatch");
+        return Err(VaultError::InvalidMint.with_account(user_token_account_info.key));
     }
     drop(user_token_data);
 
     // Verify vault token account
+    if *vault_token_account_info.key != vault_state.token_account {
+        log_error!("Withdraw: Vault token account mismatch. Expected: {}, Got: {}",
+             vault_state.token_account, vault_token_account_info.key);
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
+    }
     let vault_token_data = vault_token_account_info.try_borrow_data()?;
     let vault_token_account = spl_token::state::Account::unpack(&vault_token_data)
         .map_err(|_| {
-            msg!("Withdraw: Failed to unpack vault token account");
+            log_error!("Withdraw: Failed to unpack vault token account");
             VaultError::InvalidTokenAccount
         })?;
     
-    if vault_token_account.mint != vault_state.token_mint {
-        msg!("Withdraw: Vault token account mint mismatch");
-        return Err(VaultError::InvalidMint.into());
+    if vault_token_account.mint != token_mint {
+        log_error!("Withdraw: Vault token account mint mismatch");
+        return Err(VaultError::InvalidMint.with_account(vault_token_account_info.key));
     }
 
-    // Verify vault has sufficient tokens
-    if vault_token_account.amount < amount {
-        msg!("Withdraw: Insufficient vault token balance. Required: {}, Available: {}", 
-             amount, vault_token_account.amount);
-        return Err(VaultError::InsufficientFunds.into());
-    }
+    // Idle liquidity not reserved for queued withdrawals
+    let available_liquidity = vault_state.available_liquidity(vault_token_account.amount);
     drop(vault_token_data);
+    drop(vault_state_data); // Drop the read borrow before the transfer
 
     // Derive and verify user balance PDA
-    let (user_balance_pda, user_balance_bump) = derive_user_balance_pda(
+    let (user_balance_pda, _) = derive_user_balance_pda(
         program_id,
         user_info.key,
         vault_state_info.key,
     )?;
 
     if user_balance_pda != *user_balance_info.key {
-        msg!("Withdraw: User balance PDA mismatch. Expected: {}, Got: {}", 
+        log_error!("Withdraw: User balance PDA mismatch. Expected: {}, Got: {}", 
              user_balance_pda, user_balance_info.key);
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidPda.with_account(user_balance_info.key));
     }
 
     // Load user balance account
     if user_balance_info.owner != program_id {
-        msg!("Withdraw: User balance account not owned by program");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Withdraw: User balance account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(user_balance_info.key));
     }
 
     let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
-    let mut user_balance = deserialize_user_balance_safe(&user_balance_data, "Withdraw")?;
+    let user_balance = load_zero_copy_mut::<PodUserBalance>(&mut user_balance_data, "user balance", "Withdraw")
+        .with_account(user_balance_info.key)?;
 
     // Validate user balance account
     user_balance.validate().map_err(|err| {
-        msg!("Withdraw: User balance validation failed: {}", err);
-        VaultError::InvalidInput
+        log_error!("Withdraw: User balance validation failed: {}", err);
+        VaultError::InvalidAccountData
+    })?;
+
+    // Credit accrued yield so it can be withdrawn
+    let credited = user_balance.settle_yield(yield_per_token).map_err(|err| {
+        log_error!("Withdraw: Failed to settle yield: {}", err);
+        VaultError::ArithmeticOverflow
     })?;
 
     // Check if user has sufficient balance
     if !user_balance.has_sufficient_balance(amount) {
-        msg!("Withdraw: Insufficient user balance. Required: {}, Available: {}", 
-             amount, user_balance.balance);
+        log_error!("Withdraw: Insufficient user balance. Required: {}, Available: {}", 
+             amount, user_balance.balance.get());
         return Err(VaultError::InsufficientFunds.into());
     }
 
-    // Create transfer instruction from vault to user
-    let transfer_ix = spl_token::instruction::transfer(
-        &spl_token::id(),
-        vault_token_account_info.key,
-        user_token_account_info.key,
-        vault_state_info.key, // Vault state account is the authority
-        &[],
-        amount,
-    )?;
+    // Queue the withdrawal when idle liquidity cannot cover it
+    if amount > available_liquidity {
+        user_balance.queue_withdrawal(amount).map_err(|err| {
+            log_error!("Withdraw: Failed to queue withdrawal: {}", err);
+            VaultError::ArithmeticOverflow
+        })?;
+        let pending = user_balance.pending_withdrawal.get();
+        drop(user_balance_data);
 
-    // Create vault state seeds for signing
-    let vault_state_seeds = &[
-        crate::utils::VAULT_SEED,
-        vault_state.owner.as_ref(),
-        vault_state.token_mint.as_ref(),
-        &[vault_state.bump],
-    ];
+        let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+        let vault_state = load_vault_state_zero_copy_mut(&mut vault_state_data, "Withdraw")
+            .with_account(vault_state_info.key)?;
+        vault_state.add_queued(amount).and_then(|()| vault_state.add_deposit(credited)).map_err(|err| {
+            log_error!("Withdraw: Failed to update queued total: {}", err);
+            VaultError::ArithmeticOverflow
+        })?;
 
-    // Execute the transfer with vault state as signer
-    invoke_signed(
-        &transfer_ix,
-        &[
-            vault_token_account_info.clone(),
-            user_token_account_info.clone(),
-            vault_state_info.clone(),
-            token_program_info.clone(),
-        ],
-        &[vault_state_seeds],
-    ).map_err(|e| {
-        msg!("Withdraw: Token transfer failed: {}", e);
-        e
-    })?;
+        log_info!(
+            "Withdraw queued. User: {}, Amount: {}, Available Liquidity: {}, Pending: {}, Vault Queued: {}",
+            user_info.key,
+            amount,
+            available_liquidity,
+            pending,
+            vault_state.total_queued.get()
+        );
+
+        VaultEvent::WithdrawalQueued {
+            vault: *vault_state_info.key,
+            user: *user_info.key,
+            amount,
+            pending,
+        }.emit();
+
+        return Ok(());
+    }
 
-    // Update user balance with underflow protection
+    // Update user balance in place with underflow protection
     user_balance.subtract_balance(amount).map_err(|err| {
-        msg!("Withdraw: Failed to update user balance: {}", err);
+        log_error!("Withdraw: Failed to update user balance: {}", err);
         VaultError::ArithmeticOverflow
     })?;
+    let new_balance = user_balance.balance.get();
+    drop(user_balance_data);
 
-    // Update vault total deposited with underflow protection
-    vault_state.subtract_withdrawal(amount).map_err(|err| {
-        msg!("Withdraw: Failed to update vault total: {}", err);
+    // Transfer from vault to user with vault state as signer
+    transfer_from_vault(
+        vault_seeds,
+        vault_state_info,
+        vault_token_account_info,
+        user_token_account_info,
+        token_program_info,
+        amount,
+        "Withdraw",
+    )?;
+
+    // Update vault total deposited in place with underflow protection
+    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+    let vault_state = load_vault_state_zero_copy_mut(&mut vault_state_data, "Withdraw")
+        .with_account(vault_state_info.key)?;
+    vault_state.add_deposit(credited).and_then(|()| vault_state.subtract_withdrawal(amount)).map_err(|err| {
+        log_error!("Withdraw: Failed to update vault total: {}", err);
         VaultError::ArithmeticOverflow
     })?;
 
-    // Save updated user balance
-    serialize_user_balance_safe(&user_balance, &mut *user_balance_data, "Withdraw")?;
-    drop(user_balance_data);
-
-    // Save updated vault state
-    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
-    serialize_vault_state(&vault_state, &mut *vault_state_data, "Withdraw")?;
-
-    msg!(
+    log_info!(
         "Withdraw successful. User: {}, Amount: {}, New Balance: {}, Vault Total: {}",
         user_info.key,
         amount,
-        user_balance.balance,
-        vault_state.total_deposited
+        new_balance,
+        vault_state.total_deposited.get()
     );
 
+    VaultEvent::Withdrawn {
+        vault: *vault_state_info.key,
+        user: *user_info.key,
+        amount,
+        balance: new_balance,
+    }.emit();
+
     Ok(())
 }
 
 /// Process WithdrawAll instruction
 /// Allows vault owner to withdraw all funds from the vault
-pub fn process_withdraw_all(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+pub fn process_withdraw_all(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProcessResult {
     let account_info_iter = &mut accounts.iter();
     
     // Validate minimum number of accounts
     if accounts.len() < 5 {
-        msg!("WithdrawAll: Insufficient accounts provided");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("WithdrawAll: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
     }
     
     // Expected accounts:
@@ -954,75 +1724,76 @@ pub fn process_withdraw_all(program_id: &Pubkey, accounts: &[AccountInfo]) -> Pr
 
     // Verify owner is signer
     if !owner_info.is_signer {
-        msg!("WithdrawAll: Owner must be signer");
-        return Err(VaultError::UnauthorizedAccess.into());
+        log_error!("WithdrawAll: Owner must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
     }
 
     // Verify accounts are writable
     if !owner_token_account_info.is_writable {
-        msg!("WithdrawAll: Owner token account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("WithdrawAll: Owner token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(owner_token_account_info.key));
     }
     if !vault_token_account_info.is_writable {
-        msg!("WithdrawAll: Vault token account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("WithdrawAll: Vault token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_token_account_info.key));
     }
     if !vault_state_info.is_writable {
-        msg!("WithdrawAll: Vault state account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("WithdrawAll: Vault state account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
     }
 
     // Verify program accounts
     if token_program_info.key != &spl_token::id() {
-        msg!("WithdrawAll: Invalid SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("WithdrawAll: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
     }
 
     // Load and validate vault state
     let vault_state_data = vault_state_info.try_borrow_data()?;
-    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "WithdrawAll")?;
+    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "WithdrawAll")
+        .with_account(vault_state_info.key)?;
     drop(vault_state_data); // Drop the read borrow early
 
     // Check if vault is operational
     if !vault_state.is_operational() {
-        msg!("WithdrawAll: Vault is closed");
+        log_error!("WithdrawAll: Vault is closed");
         return Err(VaultError::VaultClosed.into());
     }
 
     // Verify vault state account ownership
     if vault_state_info.owner != program_id {
-        msg!("WithdrawAll: Vault state account not owned by program");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("WithdrawAll: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
     }
 
     // Verify caller is the vault owner
     if *owner_info.key != vault_state.owner {
-        msg!("WithdrawAll: Caller is not the vault owner. Expected: {}, Got: {}", 
+        log_error!("WithdrawAll: Caller is not the vault owner. Expected: {}, Got: {}", 
              vault_state.owner, owner_info.key);
-        return Err(VaultError::UnauthorizedAccess.into());
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
     }
 
     // Verify token accounts are owned by SPL Token program
     if owner_token_account_info.owner != &spl_token::id() {
-        msg!("WithdrawAll: Owner token account must be owned by SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("WithdrawAll: Owner token account must be owned by SPL Token program");
+        return Err(VaultError::InvalidTokenAccount.with_account(owner_token_account_info.key));
     }
     if vault_token_account_info.owner != &spl_token::id() {
-        msg!("WithdrawAll: Vault token account must be owned by SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("WithdrawAll: Vault token account must be owned by SPL Token program");
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
     }
 
     // Verify token accounts match the vault's mint
     let owner_token_data = owner_token_account_info.try_borrow_data()?;
     let owner_token_account = spl_token::state::Account::unpack(&owner_token_data)
         .map_err(|_| {
-            msg!("WithdrawAll: Failed to unpack owner token account");
+            log_error!("WithdrawAll: Failed to unpack owner token account");
             VaultError::InvalidTokenAccount
         })?;
     
     if owner_token_account.mint != vault_state.token_mint {
-        msg!("WithdrawAll: Owner token account mint mismatch");
-        return Err(VaultError::InvalidMint.into());
+        log_error!("WithdrawAll: Owner token account mint mismatch");
+        return Err(VaultError::InvalidMint.with_account(owner_token_account_info.key));
     }
     drop(owner_token_data);
 
@@ -1030,13 +1801,13 @@ pub fn process_withdraw_all(program_id: &Pubkey, accounts: &[AccountInfo]) -> Pr
     let vault_token_data = vault_token_account_info.try_borrow_data()?;
     let vault_token_account = spl_token::state::Account::unpack(&vault_token_data)
         .map_err(|_| {
-            msg!("WithdrawAll: Failed to unpack vault token account");
+            log_error!("WithdrawAll: Failed to unpack vault token account");
             VaultError::InvalidTokenAccount
         })?;
     
     if vault_token_account.mint != vault_state.token_mint {
-        msg!("WithdrawAll: Vault token account mint mismatch");
-        return Err(VaultError::InvalidMint.into());
+        log_error!("WithdrawAll: Vault token account mint mismatch");
+        return Err(VaultError::InvalidMint.with_account(vault_token_account_info.key));
     }
 
     let total_amount = vault_token_account.amount;
@@ -1044,69 +1815,53 @@ pub fn process_withdraw_all(program_id: &Pubkey, accounts: &[AccountInfo]) -> Pr
 
     // Check if there are any tokens to withdraw
     if total_amount == 0 {
-        msg!("WithdrawAll: No tokens to withdraw");
+        log_error!("WithdrawAll: No tokens to withdraw");
         return Ok(());
     }
 
-    // Create transfer instruction from vault to owner
-    let transfer_ix = spl_token::instruction::transfer(
-        &spl_token::id(),
-        vault_token_account_info.key,
-        owner_token_account_info.key,
-        vault_state_info.key, // Vault state account is the authority
-        &[],
+    // Transfer from vault to owner with vault state as signer
+    transfer_from_vault(
+        &vault_state,
+        vault_state_info,
+        vault_token_account_info,
+        owner_token_account_info,
+        token_program_info,
         total_amount,
+        "WithdrawAll",
     )?;
 
-    // Create vault state seeds for signing
-    let vault_state_seeds = &[
-        crate::utils::VAULT_SEED,
-        vault_state.owner.as_ref(),
-        vault_state.token_mint.as_ref(),
-        &[vault_state.bump],
-    ];
-
-    // Execute the transfer with vault state as signer
-    invoke_signed(
-        &transfer_ix,
-        &[
-            vault_token_account_info.clone(),
-            owner_token_account_info.clone(),
-            vault_state_info.clone(),
-            token_program_info.clone(),
-        ],
-        &[vault_state_seeds],
-    ).map_err(|e| {
-        msg!("WithdrawAll: Token transfer failed: {}", e);
-        e
-    })?;
-
     // Reset vault total deposited to zero
     vault_state.reset_total_deposited();
 
     // Save updated vault state
     let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
-    serialize_vault_state(&vault_state, &mut *vault_state_data, "WithdrawAll")?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "WithdrawAll")?;
 
-    msg!(
+    log_info!(
         "WithdrawAll successful. Owner: {}, Amount: {}, Vault Total Reset: {}",
         owner_info.key,
         total_amount,
         vault_state.total_deposited
     );
 
+    VaultEvent::WithdrewAll {
+        vault: *vault_state_info.key,
+        owner: *owner_info.key,
+        amount: total_amount,
+    }.emit();
+
     Ok(())
 }
 
 /// Process Close instruction
 /// Allows vault owner to close the vault and transfer any remaining tokens
-pub fn process_close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+pub fn process_close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProcessResult {
     let account_info_iter = &mut accounts.iter();
     
     // Validate minimum number of accounts
     if accounts.len() < 5 {
-        msg!("Close: Insufficient accounts provided");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Close: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
     }
     
     // Expected accounts:
@@ -1123,75 +1878,76 @@ pub fn process_close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramRe
 
     // Verify owner is signer
     if !owner_info.is_signer {
-        msg!("Close: Owner must be signer");
-        return Err(VaultError::UnauthorizedAccess.into());
+        log_error!("Close: Owner must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
     }
 
     // Verify accounts are writable
     if !owner_token_account_info.is_writable {
-        msg!("Close: Owner token account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Close: Owner token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(owner_token_account_info.key));
     }
     if !vault_token_account_info.is_writable {
-        msg!("Close: Vault token account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Close: Vault token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_token_account_info.key));
     }
     if !vault_state_info.is_writable {
-        msg!("Close: Vault state account must be writable");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Close: Vault state account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
     }
 
     // Verify program accounts
     if token_program_info.key != &spl_token::id() {
-        msg!("Close: Invalid SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("Close: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
     }
 
     // Load and validate vault state
     let vault_state_data = vault_state_info.try_borrow_data()?;
-    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "Close")?;
+    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "Close")
+        .with_account(vault_state_info.key)?;
     drop(vault_state_data); // Drop the read borrow early
 
     // Check if vault is already closed
     if !vault_state.is_operational() {
-        msg!("Close: Vault is already closed");
+        log_error!("Close: Vault is already closed");
         return Err(VaultError::VaultClosed.into());
     }
 
     // Verify vault state account ownership
     if vault_state_info.owner != program_id {
-        msg!("Close: Vault state account not owned by program");
-        return Err(VaultError::InvalidInput.into());
+        log_error!("Close: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
     }
 
     // Verify caller is the vault owner
     if *owner_info.key != vault_state.owner {
-        msg!("Close: Caller is not the vault owner. Expected: {}, Got: {}", 
+        log_error!("Close: Caller is not the vault owner. Expected: {}, Got: {}", 
              vault_state.owner, owner_info.key);
-        return Err(VaultError::UnauthorizedAccess.into());
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
     }
 
     // Verify token accounts are owned by SPL Token program
     if owner_token_account_info.owner != &spl_token::id() {
-        msg!("Close: Owner token account must be owned by SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("Close: Owner token account must be owned by SPL Token program");
+        return Err(VaultError::InvalidTokenAccount.with_account(owner_token_account_info.key));
     }
     if vault_token_account_info.owner != &spl_token::id() {
-        msg!("Close: Vault token account must be owned by SPL Token program");
-        return Err(VaultError::InvalidTokenAccount.into());
+        log_error!("Close: Vault token account must be owned by SPL Token program");
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
     }
 
     // Verify token accounts match the vault's mint
     let owner_token_data = owner_token_account_info.try_borrow_data()?;
     let owner_token_account = spl_token::state::Account::unpack(&owner_token_data)
         .map_err(|_| {
-            msg!("Close: Failed to unpack owner token account");
+            log_error!("Close: Failed to unpack owner token account");
             VaultError::InvalidTokenAccount
         })?;
     
     if owner_token_account.mint != vault_state.token_mint {
-        msg!("Close: Owner token account mint mismatch");
-        return Err(VaultError::InvalidMint.into());
+        log_error!("Close: Owner token account mint mismatch");
+        return Err(VaultError::InvalidMint.with_account(owner_token_account_info.key));
     }
     drop(owner_token_data);
 
@@ -1199,13 +1955,13 @@ pub fn process_close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramRe
     let vault_token_data = vault_token_account_info.try_borrow_data()?;
     let vault_token_account = spl_token::state::Account::unpack(&vault_token_data)
         .map_err(|_| {
-            msg!("Close: Failed to unpack vault token account");
+            log_error!("Close: Failed to unpack vault token account");
             VaultError::InvalidTokenAccount
         })?;
     
     if vault_token_account.mint != vault_state.token_mint {
-        msg!("Close: Vault token account mint mismatch");
-        return Err(VaultError::InvalidMint.into());
+        log_error!("Close: Vault token account mint mismatch");
+        return Err(VaultError::InvalidMint.with_account(vault_token_account_info.key));
     }
 
     let remaining_amount = vault_token_account.amount;
@@ -1213,37 +1969,15 @@ pub fn process_close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramRe
 
     // Transfer any remaining tokens to owner before closing
     if remaining_amount > 0 {
-        let transfer_ix = spl_token::instruction::transfer(
-            &spl_token::id(),
-            vault_token_account_info.key,
-            owner_token_account_info.key,
-            vault_state_info.key, // Vault state account is the authority
-            &[],
+        transfer_from_vault(
+            &vault_state,
+            vault_state_info,
+            vault_token_account_info,
+            owner_token_account_info,
+            token_program_info,
             remaining_amount,
+            "Close",
         )?;
-
-        // Create vault state seeds for signing
-        let vault_state_seeds = &[
-            crate::utils::VAULT_SEED,
-            vault_state.owner.as_ref(),
-            vault_state.token_mint.as_ref(),
-            &[vault_state.bump],
-        ];
-
-        // Execute the transfer with vault state as signer
-        invoke_signed(
-            &transfer_ix,
-            &[
-                vault_token_account_info.clone(),
-                owner_token_account_info.clone(),
-                vault_state_info.clone(),
-                token_program_info.clone(),
-            ],
-            &[vault_state_seeds],
-        ).map_err(|e| {
-            msg!("Close: Token transfer failed: {}", e);
-            e
-        })?;
     }
 
     // Mark vault as closed
@@ -1251,13 +1985,3058 @@ pub fn process_close(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramRe
 
     // Save updated vault state
     let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
-    serialize_vault_state(&vault_state, &mut *vault_state_data, "Close")?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "Close")?;
 
-    msg!(
+    log_info!(
         "Vault closed successfully. Owner: {}, Remaining tokens transferred: {}, Vault is now closed",
         owner_info.key,
         remaining_amount
     );
 
+    VaultEvent::Closed {
+        vault: *vault_state_info.key,
+        owner: *owner_info.key,
+        remaining: remaining_amount,
+    }.emit();
+
+    Ok(())
+}
+
+/// Load the signer's vault state and user balance for delegate management
+fn load_delegate_accounts<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'b [AccountInfo<'a>],
+    operation: &str,
+) -> ProcessResult<(&'b AccountInfo<'a>, UserBalance)> {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 3 {
+        log_error!("{}: Insufficient accounts provided", operation);
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer] User account
+    // 1. [] Vault state account
+    // 2. [writable] User balance account (PDA)
+    let user_info = next_account_info(account_info_iter)?;
+    let vault_state_info = next_account_info(account_info_iter)?;
+    let user_balance_info = next_account_info(account_info_iter)?;
+
+    // Verify user is signer
+    if !user_info.is_signer {
+        log_error!("{}: User must be signer", operation);
+        return Err(VaultError::UnauthorizedAccess.with_account(user_info.key));
+    }
+    if !user_balance_info.is_writable {
+        log_error!("{}: User balance account must be writable", operation);
+        return Err(VaultError::AccountNotWritable.with_account(user_balance_info.key));
+    }
+
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("{}: Vault state account not owned by program", operation);
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    // Load and validate vault state
+    let vault_state_data = vault_state_info.try_borrow_data()?;
+    let vault_state = deserialize_vault_state_safe(&vault_state_data, operation)
+        .with_account(vault_state_info.key)?;
+    drop(vault_state_data);
+
+    if !vault_state.is_operational() {
+        log_error!("{}: Vault is closed", operation);
+        return Err(VaultError::VaultClosed.into());
+    }
+
+    // Derive and verify user balance PDA
+    let (user_balance_pda, _) = derive_user_balance_pda(
+        program_id,
+        user_info.key,
+        vault_state_info.key,
+    )?;
+
+    if user_balance_pda != *user_balance_info.key {
+        log_error!("{}: User balance PDA mismatch. Expected: {}, Got: {}",
+             operation, user_balance_pda, user_balance_info.key);
+        return Err(VaultError::InvalidPda.with_account(user_balance_info.key));
+    }
+
+    if user_balance_info.owner != program_id {
+        log_error!("{}: User balance account not owned by program", operation);
+        return Err(VaultError::InvalidAccountOwner.with_account(user_balance_info.key));
+    }
+
+    let user_balance_data = user_balance_info.try_borrow_data()?;
+    let user_balance = deserialize_user_balance_safe(&user_balance_data, operation)
+        .with_account(user_balance_info.key)?;
+    drop(user_balance_data);
+
+    Ok((user_balance_info, user_balance))
+}
+
+/// Process ApproveDelegate instruction
+/// Allows a user to let a third party withdraw up to `amount` from their balance
+pub fn process_approve_delegate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    delegate: Pubkey,
+    amount: u64,
+) -> ProcessResult {
+    if delegate == Pubkey::default() {
+        log_error!("ApproveDelegate: Invalid delegate pubkey");
+        return Err(VaultError::InvalidInput.into());
+    }
+
+    let (user_balance_info, mut user_balance) =
+        load_delegate_accounts(program_id, accounts, "ApproveDelegate")?;
+
+    user_balance.approve_delegate(delegate, amount);
+
+    let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
+    serialize_user_balance_safe(&user_balance, &mut user_balance_data, "ApproveDelegate")?;
+
+    log_info!(
+        "Delegate approved. User: {}, Delegate: {}, Allowance: {}",
+        user_balance.user,
+        delegate,
+        amount
+    );
+
+    VaultEvent::DelegateApproved {
+        vault: user_balance.vault,
+        user: user_balance.user,
+        delegate,
+        amount,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process RevokeDelegate instruction
+/// Clears the delegate and any remaining allowance on the user's balance
+pub fn process_revoke_delegate(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProcessResult {
+    let (user_balance_info, mut user_balance) =
+        load_delegate_accounts(program_id, accounts, "RevokeDelegate")?;
+
+    user_balance.revoke_delegate();
+
+    let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
+    serialize_user_balance_safe(&user_balance, &mut user_balance_data, "RevokeDelegate")?;
+
+    log_info!("Delegate revoked. User: {}", user_balance.user);
+
+    VaultEvent::DelegateRevoked {
+        vault: user_balance.vault,
+        user: user_balance.user,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process WithdrawAsDelegate instruction
+/// Allows an approved delegate to withdraw from another user's balance
+pub fn process_withdraw_as_delegate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 6 {
+        log_error!("WithdrawAsDelegate: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer] Delegate account
+    // 1. [writable] Destination token account
+    // 2. [writable] Vault token account
+    // 3. [writable] Vault state account
+    // 4. [writable] User balance account (PDA) of the delegating user
+    // 5. [] SPL Token program
+    let delegate_info = next_account_info(account_info_iter)?;
+    let destination_token_account_info = next_account_info(account_info_iter)?;
+    let vault_token_account_info = next_account_info(account_info_iter)?;
+    let vault_state_info = next_account_info(account_info_iter)?;
+    let user_balance_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    // Validate withdrawal amount
+    if amount == 0 {
+        log_error!("WithdrawAsDelegate: Amount must be greater than zero");
+        return Err(VaultError::ZeroAmount.into());
+    }
+
+    // Verify delegate is signer
+    if !delegate_info.is_signer {
+        log_error!("WithdrawAsDelegate: Delegate must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(delegate_info.key));
+    }
+
+    // Verify accounts are writable
+    if !destination_token_account_info.is_writable {
+        log_error!("WithdrawAsDelegate: Destination token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(destination_token_account_info.key));
+    }
+    if !vault_token_account_info.is_writable {
+        log_error!("WithdrawAsDelegate: Vault token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_token_account_info.key));
+    }
+    if !vault_state_info.is_writable {
+        log_error!("WithdrawAsDelegate: Vault state account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
+    }
+    if !user_balance_info.is_writable {
+        log_error!("WithdrawAsDelegate: User balance account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(user_balance_info.key));
+    }
+
+    // Verify program accounts
+    if token_program_info.key != &spl_token::id() {
+        log_error!("WithdrawAsDelegate: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
+    }
+
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("WithdrawAsDelegate: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    // Load and validate vault state
+    let vault_state_data = vault_state_info.try_borrow_data()?;
+    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "WithdrawAsDelegate")
+        .with_account(vault_state_info.key)?;
+    drop(vault_state_data);
+
+    if !vault_state.is_operational() {
+        log_error!("WithdrawAsDelegate: Vault is closed");
+        return Err(VaultError::VaultClosed.into());
+    }
+
+    // Idle liquidity not reserved for queued withdrawals
+    let vault_token_amount = verify_payout_token_accounts(
+        &vault_state,
+        destination_token_account_info,
+        vault_token_account_info,
+        "WithdrawAsDelegate",
+    )?;
+    let available_liquidity = vault_state.available_liquidity(vault_token_amount);
+
+    // Load user balance account
+    if user_balance_info.owner != program_id {
+        log_error!("WithdrawAsDelegate: User balance account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(user_balance_info.key));
+    }
+
+    let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
+    let mut user_balance = deserialize_user_balance_safe(&user_balance_data, "WithdrawAsDelegate")
+        .with_account(user_balance_info.key)?;
+
+    // The balance account must be the PDA of its recorded user within this vault
+    let (user_balance_pda, _) = derive_user_balance_pda(
+        program_id,
+        &user_balance.user,
+        vault_state_info.key,
+    )?;
+
+    if user_balance_pda != *user_balance_info.key || user_balance.vault != *vault_state_info.key {
+        log_error!("WithdrawAsDelegate: User balance PDA mismatch. Expected: {}, Got: {}",
+             user_balance_pda, user_balance_info.key);
+        return Err(VaultError::InvalidPda.into());
+    }
+
+    // Verify the signer is the approved delegate
+    if !user_balance.is_delegate(delegate_info.key) {
+        log_error!("WithdrawAsDelegate: Signer is not the approved delegate. Expected: {}, Got: {}",
+             user_balance.delegate, delegate_info.key);
+        return Err(VaultError::UnauthorizedAccess.with_account(delegate_info.key));
+    }
+
+    if user_balance.delegated_amount < amount {
+        log_error!("WithdrawAsDelegate: Insufficient allowance. Required: {}, Available: {}",
+             amount, user_balance.delegated_amount);
+        return Err(VaultError::InsufficientAllowance.into());
+    }
+
+    // Credit accrued yield so it can be withdrawn
+    settle_user_yield(&mut vault_state, &mut user_balance, "WithdrawAsDelegate")?;
+
+    // Check if user has sufficient balance
+    if !user_balance.has_sufficient_balance(amount) {
+        log_error!("WithdrawAsDelegate: Insufficient user balance. Required: {}, Available: {}",
+             amount, user_balance.balance);
+        return Err(VaultError::InsufficientFunds.into());
+    }
+
+    // A queued withdrawal is claimed to the user's own account, so the delegate's
+    // destination must be paid now or not at all
+    if amount > available_liquidity {
+        log_error!("WithdrawAsDelegate: Insufficient liquidity. Required: {}, Available: {}",
+             amount, available_liquidity);
+        return Err(VaultError::InsufficientLiquidity.into());
+    }
+
+    user_balance.spend_allowance(amount).map_err(|err| {
+        log_error!("WithdrawAsDelegate: Failed to update allowance: {}", err);
+        VaultError::InsufficientAllowance
+    })?;
+
+    transfer_from_vault(
+        &vault_state,
+        vault_state_info,
+        vault_token_account_info,
+        destination_token_account_info,
+        token_program_info,
+        amount,
+        "WithdrawAsDelegate",
+    )?;
+
+    // Update user balance and vault total
+    user_balance.subtract_balance(amount).map_err(|err| {
+        log_error!("WithdrawAsDelegate: Failed to update user balance: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+    vault_state.subtract_withdrawal(amount).map_err(|err| {
+        log_error!("WithdrawAsDelegate: Failed to update vault total: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+
+    // Save updated user balance
+    serialize_user_balance_safe(&user_balance, &mut user_balance_data, "WithdrawAsDelegate")?;
+    drop(user_balance_data);
+
+    // Save updated vault state
+    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "WithdrawAsDelegate")?;
+
+    log_info!(
+        "Delegated withdraw successful. User: {}, Delegate: {}, Amount: {}, Remaining Allowance: {}",
+        user_balance.user,
+        delegate_info.key,
+        amount,
+        user_balance.delegated_amount
+    );
+
+    VaultEvent::WithdrewAsDelegate {
+        vault: *vault_state_info.key,
+        user: user_balance.user,
+        delegate: *delegate_info.key,
+        amount,
+        remaining_allowance: user_balance.delegated_amount,
+    }.emit();
+
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Process TransferBalance instruction
+/// Moves part of the signer's vault balance to another user without touching token accounts
+pub fn process_transfer_balance(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    to: Pubkey,
+    amount: u64,
+) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 5 {
+        log_error!("TransferBalance: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer, writable] Sender account (pays rent if the recipient balance is created)
+    // 1. [writable] Vault state account
+    // 2. [writable] Sender balance account (PDA)
+    // 3. [writable] Recipient balance account (PDA)
+    // 4. [] System program (for PDA creation if needed)
+    let sender_info = next_account_info(account_info_iter)?;
+    let vault_state_info = next_account_info(account_info_iter)?;
+    let sender_balance_info = next_account_info(account_info_iter)?;
+    let recipient_balance_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // Validate transfer amount and recipient
+    if amount == 0 {
+        log_error!("TransferBalance: Amount must be greater than zero");
+        return Err(VaultError::ZeroAmount.into());
+    }
+    if to == Pubkey::default() || to == *sender_info.key {
+        log_error!("TransferBalance: Invalid recipient {}", to);
+        return Err(VaultError::InvalidInput.with_account(sender_info.key));
+    }
+
+    // Verify sender is signer
+    if !sender_info.is_signer {
+        log_error!("TransferBalance: Sender must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(sender_info.key));
+    }
+
+    // Verify accounts are writable
+    if !vault_state_info.is_writable {
+        log_error!("TransferBalance: Vault state account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
+    }
+    if !sender_balance_info.is_writable {
+        log_error!("TransferBalance: Sender balance account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(sender_balance_info.key));
+    }
+    if !recipient_balance_info.is_writable {
+        log_error!("TransferBalance: Recipient balance account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(recipient_balance_info.key));
+    }
+
+    // Verify program accounts
+    if system_program_info.key != &solana_program::system_program::id() {
+        log_error!("TransferBalance: Invalid System program");
+        return Err(VaultError::InvalidProgramId.with_account(system_program_info.key));
+    }
+
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("TransferBalance: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    // Load and validate vault state
+    let vault_state_data = vault_state_info.try_borrow_data()?;
+    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "TransferBalance")
+        .with_account(vault_state_info.key)?;
+    drop(vault_state_data);
+
+    if !vault_state.is_operational() {
+        log_error!("TransferBalance: Vault is closed");
+        return Err(VaultError::VaultClosed.into());
+    }
+
+    // Derive and verify sender balance PDA
+    let (sender_balance_pda, _) = derive_user_balance_pda(
+        program_id,
+        sender_info.key,
+        vault_state_info.key,
+    )?;
+
+    if sender_balance_pda != *sender_balance_info.key {
+        log_error!("TransferBalance: Sender balance PDA mismatch. Expected: {}, Got: {}",
+             sender_balance_pda, sender_balance_info.key);
+        return Err(VaultError::InvalidPda.with_account(sender_balance_info.key));
+    }
+
+    if sender_balance_info.owner != program_id {
+        log_error!("TransferBalance: Sender balance account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(sender_balance_info.key));
+    }
+
+    let sender_balance_data = sender_balance_info.try_borrow_data()?;
+    let mut sender_balance = deserialize_user_balance_safe(&sender_balance_data, "TransferBalance")
+        .with_account(sender_balance_info.key)?;
+    drop(sender_balance_data);
+
+    // Credit accrued yield before the balance moves
+    settle_user_yield(&mut vault_state, &mut sender_balance, "TransferBalance")?;
+
+    if !sender_balance.has_sufficient_balance(amount) {
+        log_error!("TransferBalance: Insufficient sender balance. Required: {}, Available: {}",
+             amount, sender_balance.balance);
+        return Err(VaultError::InsufficientFunds.into());
+    }
+
+    // Load the recipient balance, creating it with the sender paying rent
+    let mut recipient_balance = load_or_create_user_balance(
+        program_id,
+        sender_info,
+        recipient_balance_info,
+        system_program_info,
+        &to,
+        vault_state_info.key,
+        "TransferBalance",
+    )?;
+    settle_user_yield(&mut vault_state, &mut recipient_balance, "TransferBalance")?;
+
+    // Move the balance; the vault total only changes by the yield settled above
+    sender_balance.subtract_balance(amount).map_err(|err| {
+        log_error!("TransferBalance: Failed to update sender balance: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+    recipient_balance.add_balance(amount).map_err(|err| {
+        log_error!("TransferBalance: Failed to update recipient balance: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+
+    // Save updated balances
+    let mut sender_balance_data = sender_balance_info.try_borrow_mut_data()?;
+    serialize_user_balance_safe(&sender_balance, &mut sender_balance_data, "TransferBalance")?;
+    drop(sender_balance_data);
+
+    let mut recipient_balance_data = recipient_balance_info.try_borrow_mut_data()?;
+    serialize_user_balance_safe(&recipient_balance, &mut recipient_balance_data, "TransferBalance")?;
+    drop(recipient_balance_data);
+
+    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "TransferBalance")?;
+
+    log_info!(
+        "Balance transfer successful. From: {}, To: {}, Amount: {}, Sender Balance: {}, Recipient Balance: {}",
+        sender_info.key,
+        to,
+        amount,
+        sender_balance.balance,
+        recipient_balance.balance
+    );
+
+    VaultEvent::BalanceTransferred {
+        vault: *vault_state_info.key,
+        from: *sender_info.key,
+        to,
+        amount,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process WithdrawWithPermit instruction
+/// Allows a relayer to submit a withdrawal the user authorized with an off-chain ed25519 signature
+pub fn process_withdraw_with_permit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+    nonce: u64,
+    expiry: i64,
+) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 7 {
+        log_error!("WithdrawWithPermit: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer] Relayer account (pays transaction fees)
+    // 1. [writable] Destination token account named in the permit
+    // 2. [writable] Vault token account
+    // 3. [writable] Vault state account
+    // 4. [writable] User balance account (PDA) of the permit signer
+    // 5. [] SPL Token program
+    // 6. [] Instructions sysvar
+    let relayer_info = next_account_info(account_info_iter)?;
+    let destination_token_account_info = next_account_info(account_info_iter)?;
+    let vault_token_account_info = next_account_info(account_info_iter)?;
+    let vault_state_info = next_account_info(account_info_iter)?;
+    let user_balance_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let instructions_sysvar_info = next_account_info(account_info_iter)?;
+
+    // Validate withdrawal amount
+    if amount == 0 {
+        log_error!("WithdrawWithPermit: Amount must be greater than zero");
+        return Err(VaultError::ZeroAmount.into());
+    }
+
+    // Verify relayer is signer
+    if !relayer_info.is_signer {
+        log_error!("WithdrawWithPermit: Relayer must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(relayer_info.key));
+    }
+
+    // Verify accounts are writable
+    if !destination_token_account_info.is_writable {
+        log_error!("WithdrawWithPermit: Destination token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(destination_token_account_info.key));
+    }
+    if !vault_token_account_info.is_writable {
+        log_error!("WithdrawWithPermit: Vault token account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_token_account_info.key));
+    }
+    if !vault_state_info.is_writable {
+        log_error!("WithdrawWithPermit: Vault state account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
+    }
+    if !user_balance_info.is_writable {
+        log_error!("WithdrawWithPermit: User balance account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(user_balance_info.key));
+    }
+
+    // Verify program accounts
+    if token_program_info.key != &spl_token::id() {
+        log_error!("WithdrawWithPermit: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
+    }
+    if instructions_sysvar_info.key != &solana_program::sysvar::instructions::id() {
+        log_error!("WithdrawWithPermit: Invalid Instructions sysvar");
+        return Err(VaultError::InvalidSysvar.with_account(instructions_sysvar_info.key));
+    }
+
+    // Check permit expiry
+    let clock = Clock::get()?;
+    if clock.unix_timestamp > expiry {
+        log_error!("WithdrawWithPermit: Permit expired at {}, current time {}",
+             expiry, clock.unix_timestamp);
+        return Err(VaultError::PermitExpired.into());
+    }
+
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("WithdrawWithPermit: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    // Load and validate vault state
+    let vault_state_data = vault_state_info.try_borrow_data()?;
+    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "WithdrawWithPermit")
+        .with_account(vault_state_info.key)?;
+    drop(vault_state_data);
+
+    if !vault_state.is_operational() {
+        log_error!("WithdrawWithPermit: Vault is closed");
+        return Err(VaultError::VaultClosed.into());
+    }
+
+    // Idle liquidity not reserved for queued withdrawals
+    let vault_token_amount = verify_payout_token_accounts(
+        &vault_state,
+        destination_token_account_info,
+        vault_token_account_info,
+        "WithdrawWithPermit",
+    )?;
+    let available_liquidity = vault_state.available_liquidity(vault_token_amount);
+
+    // Load user balance account
+    if user_balance_info.owner != program_id {
+        log_error!("WithdrawWithPermit: User balance account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(user_balance_info.key));
+    }
+
+    let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
+    let mut user_balance = deserialize_user_balance_safe(&user_balance_data, "WithdrawWithPermit")
+        .with_account(user_balance_info.key)?;
+
+    // The balance account must be the PDA of its recorded user within this vault
+    let (user_balance_pda, _) = derive_user_balance_pda(
+        program_id,
+        &user_balance.user,
+        vault_state_info.key,
+    )?;
+
+    if user_balance_pda != *user_balance_info.key || user_balance.vault != *vault_state_info.key {
+        log_error!("WithdrawWithPermit: User balance PDA mismatch. Expected: {}, Got: {}",
+             user_balance_pda, user_balance_info.key);
+        return Err(VaultError::InvalidPda.into());
+    }
+
+    // Verify the user signed exactly this withdrawal
+    let permit_message = withdraw_permit_message(
+        program_id,
+        vault_state_info.key,
+        destination_token_account_info.key,
+        amount,
+        nonce,
+        expiry,
+    );
+    verify_ed25519_permit(instructions_sysvar_info, &user_balance.user, &permit_message)
+        .inspect_err(|_| {
+            log_error!("WithdrawWithPermit: Permit signature verification failed for user {}",
+                 user_balance.user);
+        })?;
+
+    // Consume the nonce to prevent replay
+    user_balance.use_nonce(nonce).map_err(|err| {
+        log_error!("WithdrawWithPermit: {}. Expected: {}, Got: {}", err, user_balance.nonce, nonce);
+        VaultError::InvalidPermit
+    })?;
+
+    // Credit accrued yield so it can be withdrawn
+    settle_user_yield(&mut vault_state, &mut user_balance, "WithdrawWithPermit")?;
+
+    // Check if user has sufficient balance
+    if !user_balance.has_sufficient_balance(amount) {
+        log_error!("WithdrawWithPermit: Insufficient user balance. Required: {}, Available: {}",
+             amount, user_balance.balance);
+        return Err(VaultError::InsufficientFunds.into());
+    }
+
+    // Queue the withdrawal when idle liquidity cannot cover it; the user claims it
+    if amount > available_liquidity {
+        let pending = queue_user_withdrawal(&mut vault_state, &mut user_balance, amount, "WithdrawWithPermit")?;
+
+        serialize_user_balance_safe(&user_balance, &mut user_balance_data, "WithdrawWithPermit")?;
+        drop(user_balance_data);
+
+        let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+        serialize_vault_state(&vault_state, &mut vault_state_data, "WithdrawWithPermit")?;
+
+        log_info!(
+            "Permit withdraw queued. User: {}, Relayer: {}, Amount: {}, Nonce: {}, Available Liquidity: {}, Pending: {}",
+            user_balance.user,
+            relayer_info.key,
+            amount,
+            nonce,
+            available_liquidity,
+            pending
+        );
+
+        VaultEvent::WithdrawalQueued {
+            vault: *vault_state_info.key,
+            user: user_balance.user,
+            amount,
+            pending,
+        }.emit();
+
+        return Ok(());
+    }
+
+    transfer_from_vault(
+        &vault_state,
+        vault_state_info,
+        vault_token_account_info,
+        destination_token_account_info,
+        token_program_info,
+        amount,
+        "WithdrawWithPermit",
+    )?;
+
+    // Update user balance and vault total
+    user_balance.subtract_balance(amount).map_err(|err| {
+        log_error!("WithdrawWithPermit: Failed to update user balance: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+    vault_state.subtract_withdrawal(amount).map_err(|err| {
+        log_error!("WithdrawWithPermit: Failed to update vault total: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+
+    // Save updated user balance
+    serialize_user_balance_safe(&user_balance, &mut user_balance_data, "WithdrawWithPermit")?;
+    drop(user_balance_data);
+
+    // Save updated vault state
+    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "WithdrawWithPermit")?;
+
+    log_info!(
+        "Permit withdraw successful. User: {}, Relayer: {}, Amount: {}, Nonce: {}, New Balance: {}",
+        user_balance.user,
+        relayer_info.key,
+        amount,
+        nonce,
+        user_balance.balance
+    );
+
+    VaultEvent::WithdrewWithPermit {
+        vault: *vault_state_info.key,
+        user: user_balance.user,
+        relayer: *relayer_info.key,
+        amount,
+        nonce,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process InitializeBasket instruction
+/// Creates an empty multi-mint basket vault for the owner
+pub fn process_initialize_basket(program_id: &Pubkey, accounts: &[AccountInfo], basket_id: u64) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 3 {
+        log_error!("InitializeBasket: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer, writable] Basket owner
+    // 1. [writable] Basket state account (PDA)
+    // 2. [] System program
+    let owner_info = next_account_info(account_info_iter)?;
+    let basket_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // Verify owner is signer and writable
+    if !owner_info.is_signer {
+        log_error!("InitializeBasket: Owner must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+    if let Some(read_only) = [owner_info, basket_info].into_iter().find(|info| !info.is_writable) {
+        log_error!("InitializeBasket: Owner and basket accounts must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(read_only.key));
+    }
+    if system_program_info.key != &solana_program::system_program::id() {
+        log_error!("InitializeBasket: Invalid System program");
+        return Err(VaultError::InvalidProgramId.with_account(system_program_info.key));
+    }
+
+    // Derive and verify basket PDA
+    let (basket_pda, basket_bump) = derive_basket_pda(program_id, owner_info.key, basket_id)?;
+    if basket_pda != *basket_info.key {
+        log_error!("InitializeBasket: Basket PDA mismatch. Expected: {}, Got: {}",
+             basket_pda, basket_info.key);
+        return Err(VaultError::InvalidPda.with_account(basket_info.key));
+    }
+
+    // Verify basket account is uninitialized
+    if basket_info.owner != &solana_program::system_program::id() || basket_info.data_len() != 0 {
+        log_error!("InitializeBasket: Basket account already initialized");
+        return Err(VaultError::AccountNotInitialized.with_account(basket_info.key));
+    }
+
+    // Create basket state account
+    let rent = Rent::get()?;
+    let create_basket_ix = system_instruction::create_account(
+        owner_info.key,
+        basket_info.key,
+        rent.minimum_balance(BasketState::SIZE),
+        BasketState::SIZE as u64,
+        program_id,
+    );
+
+    let basket_id_bytes = basket_id.to_le_bytes();
+    let basket_seeds = &[
+        BASKET_SEED,
+        owner_info.key.as_ref(),
+        &basket_id_bytes,
+        &[basket_bump],
+    ];
+
+    invoke_signed(
+        &create_basket_ix,
+        &[
+            owner_info.clone(),
+            basket_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[basket_seeds],
+    ).map_err(|e| {
+        log_error!("InitializeBasket: Failed to create basket state account: {}", e);
+        e
+    })?;
+
+    let basket = BasketState::new(*owner_info.key, basket_id, basket_bump);
+    let mut basket_data = basket_info.try_borrow_mut_data()?;
+    serialize_basket_state_safe(&basket, &mut basket_data, "InitializeBasket")?;
+
+    log_info!("Basket initialized successfully. Owner: {}, Basket: {}, Basket ID: {}",
+         owner_info.key, basket_info.key, basket_id);
+
+    VaultEvent::BasketInitialized {
+        basket: *basket_info.key,
+        owner: *owner_info.key,
+        basket_id,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process AddBasketMint instruction
+/// Adds a mint to the basket and creates the basket-owned PDA token account for it
+pub fn process_add_basket_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 6 {
+        log_error!("AddBasketMint: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer, writable] Basket owner
+    // 1. [writable] Basket state account (PDA)
+    // 2. [] Token mint
+    // 3. [writable] Basket token account (PDA) for the mint
+    // 4. [] SPL Token program
+    // 5. [] System program
+    let owner_info = next_account_info(account_info_iter)?;
+    let basket_info = next_account_info(account_info_iter)?;
+    let token_mint_info = next_account_info(account_info_iter)?;
+    let basket_token_account_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // Verify owner is signer
+    if !owner_info.is_signer {
+        log_error!("AddBasketMint: Owner must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+    if let Some(read_only) = [basket_info, basket_token_account_info].into_iter().find(|info| !info.is_writable) {
+        log_error!("AddBasketMint: Basket and basket token accounts must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(read_only.key));
+    }
+
+    // Verify program accounts
+    if token_program_info.key != &spl_token::id() {
+        log_error!("AddBasketMint: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
+    }
+    if system_program_info.key != &solana_program::system_program::id() {
+        log_error!("AddBasketMint: Invalid System program");
+        return Err(VaultError::InvalidProgramId.with_account(system_program_info.key));
+    }
+
+    // Load basket and verify caller is the owner
+    let mut basket = load_basket_state(program_id, basket_info, "AddBasketMint")?;
+    if *owner_info.key != basket.owner {
+        log_error!("AddBasketMint: Caller is not the basket owner. Expected: {}, Got: {}",
+             basket.owner, owner_info.key);
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+
+    // Verify token mint
+    if token_mint_info.owner != &spl_token::id() {
+        log_error!("AddBasketMint: Token mint must be owned by SPL Token program");
+        return Err(VaultError::InvalidMint.with_account(token_mint_info.key));
+    }
+    spl_token::state::Mint::unpack(&token_mint_info.try_borrow_data()?)
+        .map_err(|_| {
+            log_error!("AddBasketMint: Failed to unpack token mint");
+            VaultError::InvalidMint
+        })?;
+
+    // Register the mint
+    basket.add_mint(*token_mint_info.key).map_err(|err| {
+        log_error!("AddBasketMint: {}", err);
+        VaultError::InvalidMint
+    })?;
+
+    // Derive and verify basket token account PDA
+    let (token_account_pda, token_account_bump) = derive_basket_token_account_pda(
+        program_id,
+        basket_info.key,
+        token_mint_info.key,
+    )?;
+    if token_account_pda != *basket_token_account_info.key {
+        log_error!("AddBasketMint: Basket token account PDA mismatch. Expected: {}, Got: {}",
+             token_account_pda, basket_token_account_info.key);
+        return Err(VaultError::InvalidPda.with_account(basket_token_account_info.key));
+    }
+
+    // Create the token account at the PDA, owned by the basket
+    let rent = Rent::get()?;
+    let create_token_account_ix = system_instruction::create_account(
+        owner_info.key,
+        basket_token_account_info.key,
+        rent.minimum_balance(spl_token::state::Account::LEN),
+        spl_token::state::Account::LEN as u64,
+        &spl_token::id(),
+    );
+
+    let token_account_seeds = &[
+        BASKET_TOKEN_SEED,
+        basket_info.key.as_ref(),
+        token_mint_info.key.as_ref(),
+        &[token_account_bump],
+    ];
+
+    invoke_signed(
+        &create_token_account_ix,
+        &[
+            owner_info.clone(),
+            basket_token_account_info.clone(),
+            system_program_info.clone(),
+        ],
+        &[token_account_seeds],
+    ).map_err(|e| {
+        log_error!("AddBasketMint: Failed to create basket token account: {}", e);
+        e
+    })?;
+
+    let initialize_token_account_ix = spl_token::instruction::initialize_account3(
+        &spl_token::id(),
+        basket_token_account_info.key,
+        token_mint_info.key,
+        basket_info.key,
+    )?;
+
+    solana_program::program::invoke(
+        &initialize_token_account_ix,
+        &[
+            basket_token_account_info.clone(),
+            token_mint_info.clone(),
+            token_program_info.clone(),
+        ],
+    ).map_err(|e| {
+        log_error!("AddBasketMint: Failed to initialize basket token account: {}", e);
+        e
+    })?;
+
+    let mut basket_data = basket_info.try_borrow_mut_data()?;
+    serialize_basket_state_safe(&basket, &mut basket_data, "AddBasketMint")?;
+
+    log_info!(
+        "Basket mint added. Basket: {}, Mint: {}, Token Account: {}, Mint Count: {}",
+        basket_info.key,
+        token_mint_info.key,
+        basket_token_account_info.key,
+        basket.mint_count
+    );
+
+    VaultEvent::BasketMintAdded {
+        basket: *basket_info.key,
+        mint: *token_mint_info.key,
+    }.emit();
+
+    Ok(())
+}
+
+/// Verify a user token account against the basket and return the mint index and token amount
+fn verify_basket_token_accounts(
+    program_id: &Pubkey,
+    basket: &BasketState,
+    basket_info: &AccountInfo,
+    user_token_account_info: &AccountInfo,
+    basket_token_account_info: &AccountInfo,
+    operation: &str,
+) -> ProcessResult<(Pubkey, usize, u64)> {
+    if user_token_account_info.owner != &spl_token::id() {
+        log_error!("{}: User token account must be owned by SPL Token program", operation);
+        return Err(VaultError::InvalidTokenAccount.with_account(user_token_account_info.key));
+    }
+
+    let user_token_data = user_token_account_info.try_borrow_data()?;
+    let user_token_account = spl_token::state::Account::unpack(&user_token_data)
+        .map_err(|_| {
+            log_error!("{}: Failed to unpack user token account", operation);
+            VaultError::InvalidTokenAccount
+        })?;
+    drop(user_token_data);
+
+    let mint_index = basket.mint_index(&user_token_account.mint).ok_or_else(|| {
+        log_error!("{}: Mint {} is not part of the basket", operation, user_token_account.mint);
+        VaultError::InvalidMint
+    })?;
+
+    let (token_account_pda, _) = derive_basket_token_account_pda(
+        program_id,
+        basket_info.key,
+        &user_token_account.mint,
+    )?;
+    if token_account_pda != *basket_token_account_info.key {
+        log_error!("{}: Basket token account PDA mismatch. Expected: {}, Got: {}",
+             operation, token_account_pda, basket_token_account_info.key);
+        return Err(VaultError::InvalidTokenAccount.with_account(basket_token_account_info.key));
+    }
+
+    Ok((user_token_account.mint, mint_index, user_token_account.amount))
+}
+
+/// Process BasketDeposit instruction
+/// Allows users to deposit any of the basket's mints
+pub fn process_basket_deposit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 7 {
+        log_error!("BasketDeposit: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer, writable] User account
+    // 1. [writable] User token account
+    // 2. [writable] Basket token account (PDA) for the mint
+    // 3. [writable] Basket state account
+    // 4. [writable] Basket balance account (PDA) for the user and mint
+    // 5. [] SPL Token program
+    // 6. [] System program (for PDA creation if needed)
+    let user_info = next_account_info(account_info_iter)?;
+    let user_token_account_info = next_account_info(account_info_iter)?;
+    let basket_token_account_info = next_account_info(account_info_iter)?;
+    let basket_info = next_account_info(account_info_iter)?;
+    let basket_balance_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // Validate deposit amount
+    if amount == 0 {
+        log_error!("BasketDeposit: Amount must be greater than zero");
+        return Err(VaultError::ZeroAmount.into());
+    }
+
+    // Verify user is signer
+    if !user_info.is_signer {
+        log_error!("BasketDeposit: User must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(user_info.key));
+    }
+
+    // Verify accounts are writable
+    if let Some(read_only) = [user_token_account_info, basket_token_account_info, basket_info, basket_balance_info].into_iter().find(|info| !info.is_writable) {
+        log_error!("BasketDeposit: Token, basket and balance accounts must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(read_only.key));
+    }
+
+    // Verify program accounts
+    if token_program_info.key != &spl_token::id() {
+        log_error!("BasketDeposit: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
+    }
+    if system_program_info.key != &solana_program::system_program::id() {
+        log_error!("BasketDeposit: Invalid System program");
+        return Err(VaultError::InvalidProgramId.with_account(system_program_info.key));
+    }
+
+    let mut basket = load_basket_state(program_id, basket_info, "BasketDeposit")?;
+    let (token_mint, mint_index, user_token_amount) = verify_basket_token_accounts(
+        program_id,
+        &basket,
+        basket_info,
+        user_token_account_info,
+        basket_token_account_info,
+        "BasketDeposit",
+    )?;
+
+    // Verify user has sufficient balance
+    if user_token_amount < amount {
+        log_error!("BasketDeposit: Insufficient user token balance. Required: {}, Available: {}",
+             amount, user_token_amount);
+        return Err(VaultError::InsufficientFunds.into());
+    }
+
+    // Derive and verify basket balance PDA
+    let (basket_balance_pda, basket_balance_bump) = derive_basket_balance_pda(
+        program_id,
+        user_info.key,
+        basket_info.key,
+        &token_mint,
+    )?;
+    if basket_balance_pda != *basket_balance_info.key {
+        log_error!("BasketDeposit: Basket balance PDA mismatch. Expected: {}, Got: {}",
+             basket_balance_pda, basket_balance_info.key);
+        return Err(VaultError::InvalidPda.with_account(basket_balance_info.key));
+    }
+
+    // Handle basket balance account creation or loading
+    let mut basket_balance = if basket_balance_info.owner == &solana_program::system_program::id() {
+        let rent = Rent::get()?;
+        let create_balance_ix = system_instruction::create_account(
+            user_info.key,
+            basket_balance_info.key,
+            rent.minimum_balance(BasketBalance::SIZE),
+            BasketBalance::SIZE as u64,
+            program_id,
+        );
+
+        let basket_balance_seeds = &[
+            BASKET_BALANCE_SEED,
+            user_info.key.as_ref(),
+            basket_info.key.as_ref(),
+            token_mint.as_ref(),
+            &[basket_balance_bump],
+        ];
+
+        invoke_signed(
+            &create_balance_ix,
+            &[
+                user_info.clone(),
+                basket_balance_info.clone(),
+                system_program_info.clone(),
+            ],
+            &[basket_balance_seeds],
+        ).map_err(|e| {
+            log_error!("BasketDeposit: Failed to create basket balance account: {}", e);
+            e
+        })?;
+
+        BasketBalance::new(*user_info.key, *basket_info.key, token_mint, basket_balance_bump)
+    } else if basket_balance_info.owner == program_id {
+        let basket_balance_data = basket_balance_info.try_borrow_data()?;
+        deserialize_basket_balance_safe(&basket_balance_data, "BasketDeposit")
+            .with_account(basket_balance_info.key)?
+    } else {
+        log_error!("BasketDeposit: Basket balance account has invalid owner");
+        return Err(VaultError::InvalidAccountOwner.with_account(basket_balance_info.key));
+    };
+
+    // Transfer tokens from user to basket
+    let transfer_ix = spl_token::instruction::transfer(
+        &spl_token::id(),
+        user_token_account_info.key,
+        basket_token_account_info.key,
+        user_info.key,
+        &[],
+        amount,
+    )?;
+
+    solana_program::program::invoke(
+        &transfer_ix,
+        &[
+            user_token_account_info.clone(),
+            basket_token_account_info.clone(),
+            user_info.clone(),
+            token_program_info.clone(),
+        ],
+    ).map_err(|e| {
+        log_error!("BasketDeposit: Token transfer failed: {}", e);
+        e
+    })?;
+
+    // Update balances with overflow protection
+    basket_balance.add_balance(amount).map_err(|err| {
+        log_error!("BasketDeposit: Failed to update basket balance: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+    basket.add_deposit(mint_index, amount).map_err(|err| {
+        log_error!("BasketDeposit: Failed to update basket total: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+
+    // Save updated accounts
+    let mut basket_balance_data = basket_balance_info.try_borrow_mut_data()?;
+    serialize_basket_balance_safe(&basket_balance, &mut basket_balance_data, "BasketDeposit")?;
+    drop(basket_balance_data);
+
+    let mut basket_data = basket_info.try_borrow_mut_data()?;
+    serialize_basket_state_safe(&basket, &mut basket_data, "BasketDeposit")?;
+
+    log_info!(
+        "Basket deposit successful. User: {}, Mint: {}, Amount: {}, New Balance: {}, Basket Total: {}",
+        user_info.key,
+        token_mint,
+        amount,
+        basket_balance.balance,
+        basket.total_deposited[mint_index]
+    );
+
+    VaultEvent::BasketDeposited {
+        basket: *basket_info.key,
+        user: *user_info.key,
+        mint: token_mint,
+        amount,
+        balance: basket_balance.balance,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process BasketWithdraw instruction
+/// Allows users to withdraw one of their basket balances
+pub fn process_basket_withdraw(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 6 {
+        log_error!("BasketWithdraw: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer] User account
+    // 1. [writable] User token account
+    // 2. [writable] Basket token account (PDA) for the mint
+    // 3. [writable] Basket state account
+    // 4. [writable] Basket balance account (PDA) for the user and mint
+    // 5. [] SPL Token program
+    let user_info = next_account_info(account_info_iter)?;
+    let user_token_account_info = next_account_info(account_info_iter)?;
+    let basket_token_account_info = next_account_info(account_info_iter)?;
+    let basket_info = next_account_info(account_info_iter)?;
+    let basket_balance_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    // Validate withdrawal amount
+    if amount == 0 {
+        log_error!("BasketWithdraw: Amount must be greater than zero");
+        return Err(VaultError::ZeroAmount.into());
+    }
+
+    // Verify user is signer
+    if !user_info.is_signer {
+        log_error!("BasketWithdraw: User must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(user_info.key));
+    }
+
+    // Verify accounts are writable
+    if let Some(read_only) = [user_token_account_info, basket_token_account_info, basket_info, basket_balance_info].into_iter().find(|info| !info.is_writable) {
+        log_error!("BasketWithdraw: Token, basket and balance accounts must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(read_only.key));
+    }
+
+    // Verify program accounts
+    if token_program_info.key != &spl_token::id() {
+        log_error!("BasketWithdraw: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
+    }
+
+    let mut basket = load_basket_state(program_id, basket_info, "BasketWithdraw")?;
+    let (token_mint, mint_index, _) = verify_basket_token_accounts(
+        program_id,
+        &basket,
+        basket_info,
+        user_token_account_info,
+        basket_token_account_info,
+        "BasketWithdraw",
+    )?;
+
+    // Derive and verify basket balance PDA
+    let (basket_balance_pda, _) = derive_basket_balance_pda(
+        program_id,
+        user_info.key,
+        basket_info.key,
+        &token_mint,
+    )?;
+    if basket_balance_pda != *basket_balance_info.key {
+        log_error!("BasketWithdraw: Basket balance PDA mismatch. Expected: {}, Got: {}",
+             basket_balance_pda, basket_balance_info.key);
+        return Err(VaultError::InvalidPda.with_account(basket_balance_info.key));
+    }
+    if basket_balance_info.owner != program_id {
+        log_error!("BasketWithdraw: Basket balance account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(basket_balance_info.key));
+    }
+
+    let basket_balance_data = basket_balance_info.try_borrow_data()?;
+    let mut basket_balance = deserialize_basket_balance_safe(&basket_balance_data, "BasketWithdraw")
+        .with_account(basket_balance_info.key)?;
+    drop(basket_balance_data);
+
+    // Check if user has sufficient balance
+    if basket_balance.balance < amount {
+        log_error!("BasketWithdraw: Insufficient basket balance. Required: {}, Available: {}",
+             amount, basket_balance.balance);
+        return Err(VaultError::InsufficientFunds.into());
+    }
+
+    // Transfer tokens from basket to user with the basket PDA as signer
+    let transfer_ix = spl_token::instruction::transfer(
+        &spl_token::id(),
+        basket_token_account_info.key,
+        user_token_account_info.key,
+        basket_info.key, // Basket state account is the authority
+        &[],
+        amount,
+    )?;
+
+    let basket_id_bytes = basket.basket_id.to_le_bytes();
+    let basket_seeds = &[
+        BASKET_SEED,
+        basket.owner.as_ref(),
+        &basket_id_bytes,
+        &[basket.bump],
+    ];
+
+    invoke_signed(
+        &transfer_ix,
+        &[
+            basket_token_account_info.clone(),
+            user_token_account_info.clone(),
+            basket_info.clone(),
+            token_program_info.clone(),
+        ],
+        &[basket_seeds],
+    ).map_err(|e| {
+        log_error!("BasketWithdraw: Token transfer failed: {}", e);
+        e
+    })?;
+
+    // Update balances with underflow protection
+    basket_balance.subtract_balance(amount).map_err(|err| {
+        log_error!("BasketWithdraw: Failed to update basket balance: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+    basket.subtract_withdrawal(mint_index, amount).map_err(|err| {
+        log_error!("BasketWithdraw: Failed to update basket total: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+
+    // Save updated accounts
+    let mut basket_balance_data = basket_balance_info.try_borrow_mut_data()?;
+    serialize_basket_balance_safe(&basket_balance, &mut basket_balance_data, "BasketWithdraw")?;
+    drop(basket_balance_data);
+
+    let mut basket_data = basket_info.try_borrow_mut_data()?;
+    serialize_basket_state_safe(&basket, &mut basket_data, "BasketWithdraw")?;
+
+    log_info!(
+        "Basket withdraw successful. User: {}, Mint: {}, Amount: {}, New Balance: {}, Basket Total: {}",
+        user_info.key,
+        token_mint,
+        amount,
+        basket_balance.balance,
+        basket.total_deposited[mint_index]
+    );
+
+    VaultEvent::BasketWithdrawn {
+        basket: *basket_info.key,
+        user: *user_info.key,
+        mint: token_mint,
+        amount,
+        balance: basket_balance.balance,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process CloseBasket instruction
+/// Closes an empty basket so no further mints, deposits or withdrawals are accepted
+pub fn process_close_basket(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 2 {
+        log_error!("CloseBasket: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer] Basket owner
+    // 1. [writable] Basket state account (PDA)
+    let owner_info = next_account_info(account_info_iter)?;
+    let basket_info = next_account_info(account_info_iter)?;
+
+    // Verify owner is signer
+    if !owner_info.is_signer {
+        log_error!("CloseBasket: Owner must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+    if !basket_info.is_writable {
+        log_error!("CloseBasket: Basket account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(basket_info.key));
+    }
+
+    // Load basket and verify caller is the owner
+    let mut basket = load_basket_state(program_id, basket_info, "CloseBasket")?;
+    if *owner_info.key != basket.owner {
+        log_error!("CloseBasket: Caller is not the basket owner. Expected: {}, Got: {}",
+             basket.owner, owner_info.key);
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+
+    // Depositors must have withdrawn every mint first
+    if !basket.is_empty() {
+        log_error!("CloseBasket: Basket still holds deposits: {:?}",
+             &basket.total_deposited[..basket.mint_count as usize]);
+        return Err(VaultError::BasketNotEmpty.with_account(basket_info.key));
+    }
+
+    basket.close();
+
+    let mut basket_data = basket_info.try_borrow_mut_data()?;
+    serialize_basket_state_safe(&basket, &mut basket_data, "CloseBasket")?;
+
+    log_info!("Basket closed successfully. Owner: {}, Basket: {}", owner_info.key, basket_info.key);
+
+    VaultEvent::BasketClosed {
+        basket: *basket_info.key,
+        owner: *owner_info.key,
+    }.emit();
+
+    Ok(())
+}
+
+/// Read the token amount held by a token account
+fn token_account_amount(token_account_info: &AccountInfo, operation: &str) -> ProcessResult<u64> {
+    let token_data = token_account_info.try_borrow_data()?;
+    let token_account = spl_token::state::Account::unpack(&token_data)
+        .map_err(|_| {
+            log_error!("{}: Failed to unpack token account {}", operation, token_account_info.key);
+            VaultError::InvalidTokenAccount
+        })?;
+    Ok(token_account.amount)
+}
+
+/// Process FlashLoan instruction
+/// Lends vault liquidity to a borrower program for the duration of its callback
+pub fn process_flash_loan(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    amount: u64,
+) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 5 {
+        log_error!("FlashLoan: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [writable] Vault state account
+    // 1. [writable] Vault token account
+    // 2. [writable] Borrower token account
+    // 3. [] Borrower program
+    // 4. [] SPL Token program
+    // 5. [] Any additional accounts, forwarded to the borrower program
+    let vault_state_info = next_account_info(account_info_iter)?;
+    let vault_token_account_info = next_account_info(account_info_iter)?;
+    let borrower_token_account_info = next_account_info(account_info_iter)?;
+    let borrower_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let callback_account_infos = account_info_iter.as_slice();
+
+    // Validate loan amount
+    if amount == 0 {
+        log_error!("FlashLoan: Amount must be greater than zero");
+        return Err(VaultError::ZeroAmount.into());
+    }
+
+    // Verify accounts are writable
+    if let Some(read_only) = [vault_state_info, vault_token_account_info, borrower_token_account_info].into_iter().find(|info| !info.is_writable) {
+        log_error!("FlashLoan: Vault state, vault token and borrower token accounts must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(read_only.key));
+    }
+
+    // Verify program accounts
+    if token_program_info.key != &spl_token::id() {
+        log_error!("FlashLoan: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
+    }
+    if !borrower_program_info.executable || borrower_program_info.key == program_id {
+        log_error!("FlashLoan: Borrower program must be an executable program other than the vault");
+        return Err(VaultError::InvalidProgramId.with_account(borrower_program_info.key));
+    }
+
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("FlashLoan: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    // Load vault state (rejects a vault that is already locked)
+    let vault_state_data = vault_state_info.try_borrow_data()?;
+    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "FlashLoan")
+        .with_account(vault_state_info.key)?;
+    drop(vault_state_data);
+
+    if !vault_state.is_operational() {
+        log_error!("FlashLoan: Vault is closed");
+        return Err(VaultError::VaultClosed.into());
+    }
+
+    if *vault_token_account_info.key != vault_state.token_account {
+        log_error!("FlashLoan: Vault token account mismatch. Expected: {}, Got: {}",
+             vault_state.token_account, vault_token_account_info.key);
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
+    }
+
+    verify_withdrawal_token_accounts(
+        &vault_state,
+        borrower_token_account_info,
+        vault_token_account_info,
+        amount,
+        "FlashLoan",
+    )?;
+
+    let fee = VaultState::flash_loan_fee(amount).map_err(|err| {
+        log_error!("FlashLoan: Failed to compute fee: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+    let balance_before = token_account_amount(vault_token_account_info, "FlashLoan")?;
+    let required_balance = balance_before.checked_add(fee).ok_or_else(|| {
+        log_error!("FlashLoan: Arithmetic overflow in required repayment");
+        VaultError::ArithmeticOverflow
+    })?;
+
+    // Lock the vault for the duration of the loan
+    vault_state.is_locked = true;
+    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "FlashLoan")?;
+    drop(vault_state_data);
+
+    // Lend the tokens
+    transfer_from_vault(
+        &vault_state,
+        vault_state_info,
+        vault_token_account_info,
+        borrower_token_account_info,
+        token_program_info,
+        amount,
+        "FlashLoan",
+    )?;
+
+    // Hand control to the borrower program
+    let mut callback_metas = vec![
+        AccountMeta::new(*borrower_token_account_info.key, false),
+        AccountMeta::new(*vault_token_account_info.key, false),
+        AccountMeta::new_readonly(*token_program_info.key, false),
+    ];
+    let mut callback_infos = vec![
+        borrower_token_account_info.clone(),
+        vault_token_account_info.clone(),
+        token_program_info.clone(),
+    ];
+    for account_info in callback_account_infos {
+        callback_metas.push(AccountMeta {
+            pubkey: *account_info.key,
+            is_signer: account_info.is_signer,
+            is_writable: account_info.is_writable,
+        });
+        callback_infos.push(account_info.clone());
+    }
+    callback_infos.push(borrower_program_info.clone());
+
+    let callback_ix = Instruction {
+        program_id: *borrower_program_info.key,
+        accounts: callback_metas,
+        data: FlashLoanCallback { amount, fee }.pack(),
+    };
+
+    solana_program::program::invoke(&callback_ix, &callback_infos).map_err(|e| {
+        log_error!("FlashLoan: Borrower callback failed: {}", e);
+        e
+    })?;
+
+    // Verify repayment of principal plus fee
+    let balance_after = token_account_amount(vault_token_account_info, "FlashLoan")?;
+    if balance_after < required_balance {
+        log_error!("FlashLoan: Loan not repaid. Required balance: {}, Actual: {}",
+             required_balance, balance_after);
+        return Err(VaultError::FlashLoanNotRepaid.into());
+    }
+
+    // Unlock the vault and share the fee among depositors
+    vault_state.is_locked = false;
+    vault_state.add_accrued_fees(fee).map_err(|err| {
+        log_error!("FlashLoan: Failed to record fee: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+    let distributed = vault_state.distribute_yield(fee).map_err(|err| {
+        log_error!("FlashLoan: Failed to distribute fee: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+    if !distributed {
+        log_info!("FlashLoan: No deposits to credit, fee of {} stays unattributed", fee);
+    }
+
+    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "FlashLoan")?;
+
+    log_info!(
+        "FlashLoan successful. Borrower program: {}, Amount: {}, Fee: {}, Accrued Fees: {}",
+        borrower_program_info.key,
+        amount,
+        fee,
+        vault_state.accrued_fees
+    );
+
+    VaultEvent::FlashLoan {
+        vault: *vault_state_info.key,
+        borrower_program: *borrower_program_info.key,
+        amount,
+        fee,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process AddStrategy instruction
+/// Registers a strategy adapter program the owner can allocate vault liquidity to
+pub fn process_add_strategy(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    cap: u64,
+) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 3 {
+        log_error!("AddStrategy: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer] Vault owner
+    // 1. [writable] Vault state account
+    // 2. [] Strategy adapter program
+    let owner_info = next_account_info(account_info_iter)?;
+    let vault_state_info = next_account_info(account_info_iter)?;
+    let strategy_program_info = next_account_info(account_info_iter)?;
+
+    // Verify owner is signer
+    if !owner_info.is_signer {
+        log_error!("AddStrategy: Owner must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+    if !vault_state_info.is_writable {
+        log_error!("AddStrategy: Vault state account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
+    }
+
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("AddStrategy: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    let vault_state_data = vault_state_info.try_borrow_data()?;
+    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "AddStrategy")
+        .with_account(vault_state_info.key)?;
+    drop(vault_state_data);
+
+    if !vault_state.is_operational() {
+        log_error!("AddStrategy: Vault is closed");
+        return Err(VaultError::VaultClosed.into());
+    }
+
+    // Verify caller is the vault owner
+    if *owner_info.key != vault_state.owner {
+        log_error!("AddStrategy: Unauthorized access. Expected owner: {}, Got: {}",
+             vault_state.owner, owner_info.key);
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+
+    // Verify the adapter is a program other than the vault
+    if !strategy_program_info.executable || strategy_program_info.key == program_id {
+        log_error!("AddStrategy: Strategy must be an executable program other than the vault");
+        return Err(VaultError::InvalidStrategy.with_account(strategy_program_info.key));
+    }
+
+    let strategy_index = vault_state.add_strategy(*strategy_program_info.key, cap).map_err(|err| {
+        log_error!("AddStrategy: {}", err);
+        VaultError::InvalidStrategy
+    })?;
+
+    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "AddStrategy")?;
+
+    log_info!(
+        "Strategy added. Vault: {}, Strategy: {}, Index: {}, Cap: {}",
+        vault_state_info.key,
+        strategy_program_info.key,
+        strategy_index,
+        cap
+    );
+
+    VaultEvent::StrategyAdded {
+        vault: *vault_state_info.key,
+        strategy: *strategy_program_info.key,
+        index: strategy_index as u8,
+        cap,
+    }.emit();
+
+    Ok(())
+}
+
+/// Accounts shared by the Allocate, Deallocate and Harvest instructions
+struct StrategyAccounts<'a, 'b> {
+    vault_state_info: &'b AccountInfo<'a>,
+    vault_token_account_info: &'b AccountInfo<'a>,
+    strategy_token_account_info: &'b AccountInfo<'a>,
+    strategy_program_info: &'b AccountInfo<'a>,
+    token_program_info: &'b AccountInfo<'a>,
+    adapter_account_infos: &'b [AccountInfo<'a>],
+}
+
+/// Load and verify the accounts of a strategy instruction for the given strategy index
+fn load_strategy_accounts<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'b [AccountInfo<'a>],
+    strategy_index: u8,
+    operation: &str,
+) -> ProcessResult<(StrategyAccounts<'a, 'b>, VaultState)> {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 6 {
+        log_error!("{}: Insufficient accounts provided", operation);
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer] Vault owner
+    // 1. [writable] Vault state account
+    // 2. [writable] Vault token account
+    // 3. [writable] Strategy token account
+    // 4. [] Strategy adapter program
+    // 5. [] SPL Token program
+    // 6. [] Any additional accounts, forwarded to the adapter
+    let owner_info = next_account_info(account_info_iter)?;
+    let vault_state_info = next_account_info(account_info_iter)?;
+    let vault_token_account_info = next_account_info(account_info_iter)?;
+    let strategy_token_account_info = next_account_info(account_info_iter)?;
+    let strategy_program_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+    let adapter_account_infos = account_info_iter.as_slice();
+
+    // Verify owner is signer
+    if !owner_info.is_signer {
+        log_error!("{}: Owner must be signer", operation);
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+
+    // Verify accounts are writable
+    if let Some(read_only) = [vault_state_info, vault_token_account_info, strategy_token_account_info].into_iter().find(|info| !info.is_writable) {
+        log_error!("{}: Vault state, vault token and strategy token accounts must be writable", operation);
+        return Err(VaultError::AccountNotWritable.with_account(read_only.key));
+    }
+
+    // Verify program accounts
+    if token_program_info.key != &spl_token::id() {
+        log_error!("{}: Invalid SPL Token program", operation);
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
+    }
+
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("{}: Vault state account not owned by program", operation);
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    let vault_state_data = vault_state_info.try_borrow_data()?;
+    let vault_state = deserialize_vault_state_safe(&vault_state_data, operation)
+        .with_account(vault_state_info.key)?;
+    drop(vault_state_data);
+
+    if !vault_state.is_operational() {
+        log_error!("{}: Vault is closed", operation);
+        return Err(VaultError::VaultClosed.into());
+    }
+
+    // Verify caller is the vault owner
+    if *owner_info.key != vault_state.owner {
+        log_error!("{}: Unauthorized access. Expected owner: {}, Got: {}",
+             operation, vault_state.owner, owner_info.key);
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+
+    if *vault_token_account_info.key != vault_state.token_account {
+        log_error!("{}: Vault token account mismatch. Expected: {}, Got: {}",
+             operation, vault_state.token_account, vault_token_account_info.key);
+        return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
+    }
+
+    // Verify the strategy is registered under this index
+    let strategy = vault_state.strategy(strategy_index).map_err(|err| {
+        log_error!("{}: {}", operation, err);
+        VaultError::InvalidStrategy
+    })?;
+    if strategy.program_id != *strategy_program_info.key {
+        log_error!("{}: Strategy program mismatch. Expected: {}, Got: {}",
+             operation, strategy.program_id, strategy_program_info.key);
+        return Err(VaultError::InvalidStrategy.with_account(strategy_program_info.key));
+    }
+
+    // Verify the strategy token account holds the vault's mint
+    if strategy_token_account_info.owner != &spl_token::id() {
+        log_error!("{}: Strategy token account must be owned by SPL Token program", operation);
+        return Err(VaultError::InvalidTokenAccount.with_account(strategy_token_account_info.key));
+    }
+    let strategy_token_data = strategy_token_account_info.try_borrow_data()?;
+    let strategy_token_account = spl_token::state::Account::unpack(&strategy_token_data)
+        .map_err(|_| {
+            log_error!("{}: Failed to unpack strategy token account", operation);
+            VaultError::InvalidTokenAccount
+        })?;
+    drop(strategy_token_data);
+
+    if strategy_token_account.mint != vault_state.token_mint {
+        log_error!("{}: Strategy token account mint mismatch", operation);
+        return Err(VaultError::InvalidMint.with_account(strategy_token_account_info.key));
+    }
+
+    Ok((
+        StrategyAccounts {
+            vault_state_info,
+            vault_token_account_info,
+            strategy_token_account_info,
+            strategy_program_info,
+            token_program_info,
+            adapter_account_infos,
+        },
+        vault_state,
+    ))
+}
+
+/// Invoke a strategy adapter with the vault state PDA as signer
+fn invoke_strategy_adapter(
+    strategy_accounts: &StrategyAccounts,
+    vault_state: &VaultState,
+    adapter_instruction: StrategyAdapterInstruction,
+    operation: &str,
+) -> ProcessResult {
+    let mut adapter_metas = vec![
+        AccountMeta::new_readonly(*strategy_accounts.vault_state_info.key, true),
+        AccountMeta::new(*strategy_accounts.vault_token_account_info.key, false),
+        AccountMeta::new(*strategy_accounts.strategy_token_account_info.key, false),
+        AccountMeta::new_readonly(*strategy_accounts.token_program_info.key, false),
+    ];
+    let mut adapter_infos = vec![
+        strategy_accounts.vault_state_info.clone(),
+        strategy_accounts.vault_token_account_info.clone(),
+        strategy_accounts.strategy_token_account_info.clone(),
+        strategy_accounts.token_program_info.clone(),
+    ];
+    for account_info in strategy_accounts.adapter_account_infos {
+        adapter_metas.push(AccountMeta {
+            pubkey: *account_info.key,
+            is_signer: account_info.is_signer,
+            is_writable: account_info.is_writable,
+        });
+        adapter_infos.push(account_info.clone());
+    }
+    adapter_infos.push(strategy_accounts.strategy_program_info.clone());
+
+    let adapter_ix = Instruction {
+        program_id: *strategy_accounts.strategy_program_info.key,
+        accounts: adapter_metas,
+        data: adapter_instruction.pack(),
+    };
+
+    let vault_id_bytes = vault_state.vault_id.to_le_bytes();
+    let vault_state_seeds = &[
+        crate::utils::VAULT_SEED,
+        vault_state.owner.as_ref(),
+        vault_state.token_mint.as_ref(),
+        &vault_id_bytes,
+        &[vault_state.bump],
+    ];
+
+    invoke_signed(&adapter_ix, &adapter_infos, &[vault_state_seeds]).map_err(|e| {
+        log_error!("{}: Strategy adapter call failed: {}", operation, e);
+        e.into()
+    })
+}
+
+/// Total assets of the vault: idle tokens in the vault token account plus strategy balances
+fn vault_total_assets(
+    vault_state: &VaultState,
+    vault_token_account_info: &AccountInfo,
+    operation: &str,
+) -> ProcessResult<u64> {
+    let idle = token_account_amount(vault_token_account_info, operation)?;
+    vault_state.total_assets(idle).map_err(|err| {
+        log_error!("{}: {}", operation, err);
+        VaultError::ArithmeticOverflow.into()
+    })
+}
+
+/// Process Allocate instruction
+/// Moves idle vault tokens into a registered strategy
+pub fn process_allocate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    strategy_index: u8,
+    amount: u64,
+) -> ProcessResult {
+    // Validate allocation amount
+    if amount == 0 {
+        log_error!("Allocate: Amount must be greater than zero");
+        return Err(VaultError::ZeroAmount.into());
+    }
+
+    let (strategy_accounts, mut vault_state) =
+        load_strategy_accounts(program_id, accounts, strategy_index, "Allocate")?;
+
+    verify_withdrawal_token_accounts(
+        &vault_state,
+        strategy_accounts.strategy_token_account_info,
+        strategy_accounts.vault_token_account_info,
+        amount,
+        "Allocate",
+    )?;
+
+    // Allocations must leave the queued withdrawals and the idle buffer in the vault
+    let idle = token_account_amount(strategy_accounts.vault_token_account_info, "Allocate")?;
+    let total_assets = vault_state.total_assets(idle).map_err(|err| {
+        log_error!("Allocate: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+    let required_idle = vault_state.required_idle(total_assets).map_err(|err| {
+        log_error!("Allocate: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+    if idle - amount < required_idle {
+        log_error!("Allocate: Liquidity buffer breached. Idle after allocation: {}, Required: {}",
+             idle - amount, required_idle);
+        return Err(VaultError::LiquidityBufferBreached.into());
+    }
+
+    vault_state.allocate_to_strategy(strategy_index, amount).map_err(|err| {
+        log_error!("Allocate: {}", err);
+        VaultError::StrategyCapExceeded
+    })?;
+
+    // Move the tokens, then notify the adapter
+    transfer_from_vault(
+        &vault_state,
+        strategy_accounts.vault_state_info,
+        strategy_accounts.vault_token_account_info,
+        strategy_accounts.strategy_token_account_info,
+        strategy_accounts.token_program_info,
+        amount,
+        "Allocate",
+    )?;
+
+    invoke_strategy_adapter(
+        &strategy_accounts,
+        &vault_state,
+        StrategyAdapterInstruction::Deposit { amount },
+        "Allocate",
+    )?;
+
+    let total_assets = vault_total_assets(&vault_state, strategy_accounts.vault_token_account_info, "Allocate")?;
+
+    let mut vault_state_data = strategy_accounts.vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "Allocate")?;
+
+    log_info!(
+        "Allocate successful. Strategy: {}, Amount: {}, Strategy Allocation: {}, Total Assets: {}",
+        strategy_accounts.strategy_program_info.key,
+        amount,
+        vault_state.strategies[strategy_index as usize].allocated,
+        total_assets
+    );
+
+    VaultEvent::Allocated {
+        vault: *strategy_accounts.vault_state_info.key,
+        strategy: *strategy_accounts.strategy_program_info.key,
+        amount,
+        total_assets,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process Deallocate instruction
+/// Pulls tokens back from a registered strategy into the vault
+pub fn process_deallocate(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    strategy_index: u8,
+    amount: u64,
+) -> ProcessResult {
+    // Validate deallocation amount
+    if amount == 0 {
+        log_error!("Deallocate: Amount must be greater than zero");
+        return Err(VaultError::ZeroAmount.into());
+    }
+
+    let (strategy_accounts, mut vault_state) =
+        load_strategy_accounts(program_id, accounts, strategy_index, "Deallocate")?;
+
+    vault_state.deallocate_from_strategy(strategy_index, amount).map_err(|err| {
+        log_error!("Deallocate: {} (harvest first to include strategy gains)", err);
+        VaultError::InsufficientFunds
+    })?;
+
+    // Ask the adapter to return the tokens and verify they arrived
+    let balance_before = token_account_amount(strategy_accounts.vault_token_account_info, "Deallocate")?;
+
+    invoke_strategy_adapter(
+        &strategy_accounts,
+        &vault_state,
+        StrategyAdapterInstruction::Withdraw { amount },
+        "Deallocate",
+    )?;
+
+    let balance_after = token_account_amount(strategy_accounts.vault_token_account_info, "Deallocate")?;
+    let returned = balance_after.saturating_sub(balance_before);
+    if returned < amount {
+        log_error!("Deallocate: Strategy returned too little. Required: {}, Returned: {}",
+             amount, returned);
+        return Err(VaultError::InsufficientFunds.into());
+    }
+
+    let total_assets = vault_total_assets(&vault_state, strategy_accounts.vault_token_account_info, "Deallocate")?;
+
+    let mut vault_state_data = strategy_accounts.vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "Deallocate")?;
+
+    log_info!(
+        "Deallocate successful. Strategy: {}, Amount: {}, Strategy Allocation: {}, Total Assets: {}",
+        strategy_accounts.strategy_program_info.key,
+        amount,
+        vault_state.strategies[strategy_index as usize].allocated,
+        total_assets
+    );
+
+    VaultEvent::Deallocated {
+        vault: *strategy_accounts.vault_state_info.key,
+        strategy: *strategy_accounts.strategy_program_info.key,
+        amount,
+        total_assets,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process Harvest instruction
+/// Refreshes a strategy's allocation from the balance reported by its adapter
+pub fn process_harvest(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    strategy_index: u8,
+) -> ProcessResult {
+    let (strategy_accounts, mut vault_state) =
+        load_strategy_accounts(program_id, accounts, strategy_index, "Harvest")?;
+
+    invoke_strategy_adapter(
+        &strategy_accounts,
+        &vault_state,
+        StrategyAdapterInstruction::ReportBalance,
+        "Harvest",
+    )?;
+
+    // Read the balance the adapter reported through return data
+    let reported_balance = match get_return_data() {
+        Some((reporter, data)) if reporter == *strategy_accounts.strategy_program_info.key => {
+            let bytes: [u8; 8] = data.as_slice().try_into().map_err(|_| {
+                log_error!("Harvest: Strategy reported {} bytes, expected 8", data.len());
+                VaultError::InvalidStrategy
+            })?;
+            u64::from_le_bytes(bytes)
+        }
+        _ => {
+            log_error!("Harvest: Strategy did not report a balance");
+            return Err(VaultError::InvalidStrategy.into());
+        }
+    };
+
+    let previous_balance = vault_state.report_strategy_balance(strategy_index, reported_balance).map_err(|err| {
+        log_error!("Harvest: {}", err);
+        VaultError::InvalidStrategy
+    })?;
+
+    // Credit the gain to depositors once earlier losses are made up
+    let distributed = vault_state.record_harvest(previous_balance, reported_balance).map_err(|err| {
+        log_error!("Harvest: Failed to account for strategy result: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+
+    let total_assets = vault_total_assets(&vault_state, strategy_accounts.vault_token_account_info, "Harvest")?;
+
+    let mut vault_state_data = strategy_accounts.vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "Harvest")?;
+
+    log_info!(
+        "Harvest successful. Strategy: {}, Previous Allocation: {}, Reported Balance: {}, Distributed: {}, Unrecovered Loss: {}, Total Assets: {}",
+        strategy_accounts.strategy_program_info.key,
+        previous_balance,
+        reported_balance,
+        distributed,
+        vault_state.strategy_loss,
+        total_assets
+    );
+
+    VaultEvent::Harvested {
+        vault: *strategy_accounts.vault_state_info.key,
+        strategy: *strategy_accounts.strategy_program_info.key,
+        previous_balance,
+        reported_balance,
+        distributed,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process SetMinIdleBps instruction
+/// Sets the minimum idle liquidity ratio allocations must respect
+pub fn process_set_min_idle_bps(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    min_idle_bps: u16,
+) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 2 {
+        log_error!("SetMinIdleBps: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer] Vault owner
+    // 1. [writable] Vault state account
+    let owner_info = next_account_info(account_info_iter)?;
+    let vault_state_info = next_account_info(account_info_iter)?;
+
+    // Verify owner is signer
+    if !owner_info.is_signer {
+        log_error!("SetMinIdleBps: Owner must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+    if !vault_state_info.is_writable {
+        log_error!("SetMinIdleBps: Vault state account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
+    }
+
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("SetMinIdleBps: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    let vault_state_data = vault_state_info.try_borrow_data()?;
+    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "SetMinIdleBps")
+        .with_account(vault_state_info.key)?;
+    drop(vault_state_data);
+
+    if !vault_state.is_operational() {
+        log_error!("SetMinIdleBps: Vault is closed");
+        return Err(VaultError::VaultClosed.into());
+    }
+
+    // Verify caller is the vault owner
+    if *owner_info.key != vault_state.owner {
+        log_error!("SetMinIdleBps: Unauthorized access. Expected owner: {}, Got: {}",
+             vault_state.owner, owner_info.key);
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+
+    vault_state.set_min_idle_bps(min_idle_bps).map_err(|err| {
+        log_error!("SetMinIdleBps: {}", err);
+        VaultError::InvalidInput
+    })?;
+
+    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "SetMinIdleBps")?;
+
+    log_info!("Minimum idle ratio set. Vault: {}, Min Idle Bps: {}", vault_state_info.key, min_idle_bps);
+
+    VaultEvent::MinIdleBpsSet {
+        vault: *vault_state_info.key,
+        min_idle_bps,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process ClaimWithdrawal instruction
+/// Pays out a queued withdrawal once the vault holds enough idle liquidity
+pub fn process_claim_withdrawal(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 6 {
+        log_error!("ClaimWithdrawal: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer] User account
+    // 1. [writable] User token account
+    // 2. [writable] Vault token account
+    // 3. [writable] Vault state account
+    // 4. [writable] User balance account (PDA)
+    // 5. [] SPL Token program
+    let user_info = next_account_info(account_info_iter)?;
+    let user_token_account_info = next_account_info(account_info_iter)?;
+    let vault_token_account_info = next_account_info(account_info_iter)?;
+    let vault_state_info = next_account_info(account_info_iter)?;
+    let user_balance_info = next_account_info(account_info_iter)?;
+    let token_program_info = next_account_info(account_info_iter)?;
+
+    // Verify user is signer
+    if !user_info.is_signer {
+        log_error!("ClaimWithdrawal: User must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(user_info.key));
+    }
+
+    // Verify accounts are writable
+    if let Some(read_only) = [user_token_account_info, vault_token_account_info, vault_state_info, user_balance_info].into_iter().find(|info| !info.is_writable) {
+        log_error!("ClaimWithdrawal: Token, vault state and user balance accounts must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(read_only.key));
+    }
+
+    // Verify program accounts
+    if token_program_info.key != &spl_token::id() {
+        log_error!("ClaimWithdrawal: Invalid SPL Token program");
+        return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
+    }
+
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("ClaimWithdrawal: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    let vault_state_data = vault_state_info.try_borrow_data()?;
+    let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "ClaimWithdrawal")
+        .with_account(vault_state_info.key)?;
+    drop(vault_state_data);
+
+    if !vault_state.is_operational() {
+        log_error!("ClaimWithdrawal: Vault is closed");
+        return Err(VaultError::VaultClosed.into());
+    }
+
+    // Derive and verify user balance PDA
+    let (user_balance_pda, _) = derive_user_balance_pda(
+        program_id,
+        user_info.key,
+        vault_state_info.key,
+    )?;
+
+    if user_balance_pda != *user_balance_info.key {
+        log_error!("ClaimWithdrawal: User balance PDA mismatch. Expected: {}, Got: {}",
+             user_balance_pda, user_balance_info.key);
+        return Err(VaultError::InvalidPda.with_account(user_balance_info.key));
+    }
+
+    if user_balance_info.owner != program_id {
+        log_error!("ClaimWithdrawal: User balance account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(user_balance_info.key));
+    }
+
+    let user_balance_data = user_balance_info.try_borrow_data()?;
+    let mut user_balance = deserialize_user_balance_safe(&user_balance_data, "ClaimWithdrawal")
+        .with_account(user_balance_info.key)?;
+    drop(user_balance_data);
+
+    // Credit yield earned while the withdrawal was queued
+    settle_user_yield(&mut vault_state, &mut user_balance, "ClaimWithdrawal")?;
+
+    let amount = user_balance.take_pending_withdrawal();
+    if amount == 0 {
+        log_error!("ClaimWithdrawal: No pending withdrawal");
+        return Err(VaultError::NoPendingWithdrawal.into());
+    }
+
+    // Verify token accounts and that the vault holds the full pending amount
+    verify_withdrawal_token_accounts(
+        &vault_state,
+        user_token_account_info,
+        vault_token_account_info,
+        amount,
+        "ClaimWithdrawal",
+    )?;
+
+    transfer_from_vault(
+        &vault_state,
+        vault_state_info,
+        vault_token_account_info,
+        user_token_account_info,
+        token_program_info,
+        amount,
+        "ClaimWithdrawal",
+    )?;
+
+    vault_state.settle_queued(amount).map_err(|err| {
+        log_error!("ClaimWithdrawal: Failed to update vault totals: {}", err);
+        VaultError::ArithmeticOverflow
+    })?;
+
+    // Save updated accounts
+    let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
+    serialize_user_balance_safe(&user_balance, &mut user_balance_data, "ClaimWithdrawal")?;
+    drop(user_balance_data);
+
+    let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+    serialize_vault_state(&vault_state, &mut vault_state_data, "ClaimWithdrawal")?;
+
+    log_info!(
+        "ClaimWithdrawal successful. User: {}, Amount: {}, Vault Queued: {}, Vault Total: {}",
+        user_info.key,
+        amount,
+        vault_state.total_queued,
+        vault_state.total_deposited
+    );
+
+    VaultEvent::WithdrawalClaimed {
+        vault: *vault_state_info.key,
+        user: *user_info.key,
+        amount,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process MigrateVault and MigrateUserBalance instructions
+/// Upgrades an account stored with the `Old` layout to the current `New` layout in place
+pub fn process_migrate_account<Old, New>(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    account_name: &str,
+    operation: &str,
+) -> ProcessResult
+where
+    Old: ProgramAccount,
+    New: ProgramAccount + From<Old>,
+{
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 3 {
+        log_error!("{}: Insufficient accounts provided", operation);
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer, writable] Payer account
+    // 1. [writable] Account to migrate
+    // 2. [] System program
+    let payer_info = next_account_info(account_info_iter)?;
+    let account_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    if !payer_info.is_signer {
+        log_error!("{}: Payer must be signer", operation);
+        return Err(VaultError::UnauthorizedAccess.with_account(payer_info.key));
+    }
+    if !account_info.is_writable {
+        log_error!("{}: {} account must be writable", operation, account_name);
+        return Err(VaultError::AccountNotWritable.with_account(account_info.key));
+    }
+    if account_info.owner != program_id {
+        log_error!("{}: {} account not owned by program", operation, account_name);
+        return Err(VaultError::InvalidAccountOwner.with_account(account_info.key));
+    }
+    if *system_program_info.key != solana_program::system_program::id() {
+        log_error!("{}: Invalid System program", operation);
+        return Err(VaultError::InvalidProgramId.with_account(system_program_info.key));
+    }
+
+    let account_data = account_info.try_borrow_data()?;
+    let from_version = match New::stored_version(&account_data) {
+        Some(version) if version == New::VERSION => {
+            log_info!("{}: {} already at version {}", operation, account_name, version);
+            return Ok(());
+        }
+        Some(version) => version,
+        None => {
+            log_error!("{}: {} discriminator mismatch", operation, account_name);
+            return Err(VaultError::InvalidAccountDiscriminator.with_account(account_info.key));
+        }
+    };
+    let migrated: New = deserialize_account_safe::<Old>(&account_data, account_name, operation)
+        .with_account(account_info.key)?
+        .into();
+    drop(account_data);
+
+    // Top up rent for the larger layout before growing the account
+    let required_lamports = Rent::get()?.minimum_balance(New::SIZE);
+    let missing_lamports = required_lamports.saturating_sub(account_info.lamports());
+    if missing_lamports > 0 {
+        solana_program::program::invoke(
+            &system_instruction::transfer(payer_info.key, account_info.key, missing_lamports),
+            &[payer_info.clone(), account_info.clone(), system_program_info.clone()],
+        ).map_err(|e| {
+            log_error!("{}: Failed to fund rent for migrated {}: {}", operation, account_name, e);
+            e
+        })?;
+    }
+
+    account_info.realloc(New::SIZE, true).map_err(|e| {
+        log_error!("{}: Failed to realloc {}: {}", operation, account_name, e);
+        e
+    })?;
+
+    let mut account_data = account_info.try_borrow_mut_data()?;
+    serialize_account_safe(&migrated, &mut account_data, account_name, operation)?;
+
+    log_info!(
+        "{} successful. Account: {}, From Version: {}, To Version: {}",
+        operation,
+        account_info.key,
+        from_version,
+        New::VERSION
+    );
+
+    VaultEvent::AccountMigrated {
+        account: *account_info.key,
+        from_version,
+        to_version: New::VERSION,
+    }.emit();
+
+    Ok(())
+}
+
+/// Process UpdateMetadata instruction
+/// Replaces the vault's display name and URI, creating the metadata account if needed
+pub fn process_update_metadata(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    args: VaultMetadataArgs,
+) -> ProcessResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // Validate minimum number of accounts
+    if accounts.len() < 5 {
+        log_error!("UpdateMetadata: Insufficient accounts provided");
+        return Err(VaultError::NotEnoughAccounts.into());
+    }
+
+    // Expected accounts:
+    // 0. [signer, writable] Vault owner
+    // 1. [] Vault state account
+    // 2. [writable] Vault metadata account (PDA)
+    // 3. [] Token mint
+    // 4. [] System program
+    let owner_info = next_account_info(account_info_iter)?;
+    let vault_state_info = next_account_info(account_info_iter)?;
+    let metadata_info = next_account_info(account_info_iter)?;
+    let token_mint_info = next_account_info(account_info_iter)?;
+    let system_program_info = next_account_info(account_info_iter)?;
+
+    // Verify owner is signer
+    if !owner_info.is_signer {
+        log_error!("UpdateMetadata: Owner must be signer");
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+    if !metadata_info.is_writable {
+        log_error!("UpdateMetadata: Metadata account must be writable");
+        return Err(VaultError::AccountNotWritable.with_account(metadata_info.key));
+    }
+    if system_program_info.key != &solana_program::system_program::id() {
+        log_error!("UpdateMetadata: Invalid System program");
+        return Err(VaultError::InvalidProgramId.with_account(system_program_info.key));
+    }
+
+    // Verify vault state account ownership
+    if vault_state_info.owner != program_id {
+        log_error!("UpdateMetadata: Vault state account not owned by program");
+        return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+    }
+
+    let vault_state_data = vault_state_info.try_borrow_data()?;
+    let vault_state = deserialize_vault_state_safe(&vault_state_data, "UpdateMetadata")
+        .with_account(vault_state_info.key)?;
+    drop(vault_state_data);
+
+    if !vault_state.is_operational() {
+        log_error!("UpdateMetadata: Vault is closed");
+        return Err(VaultError::VaultClosed.into());
+    }
+
+    // Verify caller is the vault owner
+    if *owner_info.key != vault_state.owner {
+        log_error!("UpdateMetadata: Unauthorized access. Expected owner: {}, Got: {}",
+             vault_state.owner, owner_info.key);
+        return Err(VaultError::UnauthorizedAccess.with_account(owner_info.key));
+    }
+
+    if *token_mint_info.key != vault_state.token_mint {
+        log_error!("UpdateMetadata: Token mint does not match the vault");
+        return Err(VaultError::InvalidMint.with_account(token_mint_info.key));
+    }
+
+    if metadata_info.owner == program_id {
+        let (metadata_pda, _) = derive_metadata_pda(program_id, vault_state_info.key)?;
+        if metadata_pda != *metadata_info.key {
+            log_error!("UpdateMetadata: Metadata PDA mismatch. Expected: {}, Got: {}",
+                 metadata_pda, metadata_info.key);
+            return Err(VaultError::InvalidPda.with_account(metadata_info.key));
+        }
+
+        let mut metadata_data = metadata_info.try_borrow_mut_data()?;
+        let mut metadata: VaultMetadata =
+            deserialize_account_safe(&metadata_data, "vault metadata", "UpdateMetadata")
+                .with_account(metadata_info.key)?;
+        metadata.set_name(&args.name)
+            .and_then(|_| metadata.set_uri(&args.uri))
+            .map_err(|err| {
+                log_error!("UpdateMetadata: {}", err);
+                VaultError::InvalidMetadata
+            })?;
+        serialize_account_safe(&metadata, &mut metadata_data, "vault metadata", "UpdateMetadata")?;
+    } else {
+        // First metadata for this vault: cache the mint decimals
+        let mint_data = token_mint_info.try_borrow_data()?;
+        let decimals = spl_token::state::Mint::unpack(&mint_data)
+            .map_err(|_| {
+                log_error!("UpdateMetadata: Failed to unpack token mint");
+                VaultError::InvalidMint
+            })?
+            .decimals;
+        drop(mint_data);
+
+        create_vault_metadata(
+            program_id,
+            owner_info,
+            metadata_info,
+            system_program_info,
+            vault_state_info.key,
+            &args,
+            decimals,
+            "UpdateMetadata",
+        )?;
+    }
+
+    log_info!(
+        "Metadata updated. Vault: {}, Name: {}, URI: {}",
+        vault_state_info.key,
+        args.name,
+        args.uri
+    );
+
+    VaultEvent::MetadataUpdated {
+        vault: *vault_state_info.key,
+        name: args.name,
+        uri: args.uri,
+    }.emit();
+
+    Ok(())
+}
+
+/// Deposit, DepositFor and Withdraw as they were before the zero-copy path: whole accounts
+/// are decoded and re-encoded with Borsh. Only built with the test-only `borsh-baseline`
+/// feature, as the baseline `test_zero_copy_compute_units` measures the zero-copy path against.
+#[cfg(feature = "borsh-baseline")]
+mod borsh_baseline {
+    use super::*;
+
+    /// Borsh counterpart of the zero-copy deposit logic
+    pub(super) fn deposit_into_balance(
+        program_id: &Pubkey,
+        accounts: &[AccountInfo],
+        beneficiary: Option<Pubkey>,
+        amount: u64,
+        operation: &str,
+    ) -> ProcessResult {
+        let account_info_iter = &mut accounts.iter();
+    
+        // Validate minimum number of accounts
+        if accounts.len() < 7 {
+            log_error!("{}: Insufficient accounts provided", operation);
+            return Err(VaultError::NotEnoughAccounts.into());
+        }
+    
+        // Expected accounts:
+        // 0. [signer, writable] User account (depositor)
+        // 1. [writable] User token account
+        // 2. [writable] Vault token account
+        // 3. [writable] Vault state account
+        // 4. [writable] User balance account (PDA) of the beneficiary
+        // 5. [] SPL Token program
+        // 6. [] System program (for PDA creation if needed)
+        let user_info = next_account_info(account_info_iter)?;
+        let user_token_account_info = next_account_info(account_info_iter)?;
+        let vault_token_account_info = next_account_info(account_info_iter)?;
+        let vault_state_info = next_account_info(account_info_iter)?;
+        let user_balance_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+        let system_program_info = next_account_info(account_info_iter)?;
+        let beneficiary = beneficiary.unwrap_or(*user_info.key);
+
+        // Validate deposit amount
+        if amount == 0 {
+            log_error!("{}: Amount must be greater than zero", operation);
+            return Err(VaultError::ZeroAmount.into());
+        }
+
+        // Verify user is signer
+        if !user_info.is_signer {
+            log_error!("{}: User must be signer", operation);
+            return Err(VaultError::UnauthorizedAccess.with_account(user_info.key));
+        }
+
+        // Verify accounts are writable
+        if !user_token_account_info.is_writable {
+            log_error!("{}: User token account must be writable", operation);
+            return Err(VaultError::AccountNotWritable.with_account(user_token_account_info.key));
+        }
+        if !vault_token_account_info.is_writable {
+            log_error!("{}: Vault token account must be writable", operation);
+            return Err(VaultError::AccountNotWritable.with_account(vault_token_account_info.key));
+        }
+        if !vault_state_info.is_writable {
+            log_error!("{}: Vault state account must be writable", operation);
+            return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
+        }
+        if !user_balance_info.is_writable {
+            log_error!("{}: User balance account must be writable", operation);
+            return Err(VaultError::AccountNotWritable.with_account(user_balance_info.key));
+        }
+
+        // Verify program accounts
+        if token_program_info.key != &spl_token::id() {
+            log_error!("{}: Invalid SPL Token program", operation);
+            return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
+        }
+        if system_program_info.key != &solana_program::system_program::id() {
+            log_error!("{}: Invalid System program", operation);
+            return Err(VaultError::InvalidProgramId.with_account(system_program_info.key));
+        }
+
+        // Verify vault state account ownership
+        if vault_state_info.owner != program_id {
+            log_error!("{}: Vault state account not owned by program", operation);
+            return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+        }
+
+        // Load and validate vault state
+        let vault_state_data = vault_state_info.try_borrow_data()?;
+        let mut vault_state = deserialize_vault_state_safe(&vault_state_data, operation)
+            .with_account(vault_state_info.key)?;
+        drop(vault_state_data); // Drop the read borrow early
+
+        // Check if vault is operational
+        if !vault_state.is_operational() {
+            log_error!("{}: Vault is closed", operation);
+            return Err(VaultError::VaultClosed.into());
+        }
+        let token_mint = vault_state.token_mint;
+        let vault_token_account_key = vault_state.token_account;
+
+        // Verify token accounts are owned by SPL Token program
+        if user_token_account_info.owner != &spl_token::id() {
+            log_error!("{}: User token account must be owned by SPL Token program", operation);
+            return Err(VaultError::InvalidTokenAccount.with_account(user_token_account_info.key));
+        }
+        if vault_token_account_info.owner != &spl_token::id() {
+            log_error!("{}: Vault token account must be owned by SPL Token program", operation);
+            return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
+        }
+
+        // Verify token accounts match the vault's mint
+        let user_token_data = user_token_account_info.try_borrow_data()?;
+        let user_token_account = spl_token::state::Account::unpack(&user_token_data)
+            .map_err(|_| {
+                log_error!("{}: Failed to unpack user token account", operation);
+                VaultError::InvalidTokenAccount
+            })?;
+    
+        if user_token_account.mint != token_mint {
+            log_error!("{}: User token account mint mismatch", operation);
+            return Err(VaultError::InvalidMint.with_account(user_token_account_info.key));
+        }
+
+        // Verify user has sufficient balance
+        if user_token_account.amount < amount {
+            log_error!("{}: Insufficient user token balance. Required: {}, Available: {}", 
+                 operation, amount, user_token_account.amount);
+            return Err(VaultError::InsufficientFunds.into());
+        }
+        drop(user_token_data);
+
+        // Verify vault token account
+        if *vault_token_account_info.key != vault_token_account_key {
+            log_error!("{}: Vault token account mismatch. Expected: {}, Got: {}",
+                 operation, vault_token_account_key, vault_token_account_info.key);
+            return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
+        }
+        let vault_token_data = vault_token_account_info.try_borrow_data()?;
+        let vault_token_account = spl_token::state::Account::unpack(&vault_token_data)
+            .map_err(|_| {
+                log_error!("{}: Failed to unpack vault token account", operation);
+                VaultError::InvalidTokenAccount
+            })?;
+    
+        if vault_token_account.mint != token_mint {
+            log_error!("{}: Vault token account mint mismatch", operation);
+            return Err(VaultError::InvalidMint.with_account(vault_token_account_info.key));
+        }
+        drop(vault_token_data);
+
+        // Derive and verify the beneficiary's balance PDA, creating the account on first deposit
+        let mut user_balance = load_or_create_user_balance(
+            program_id,
+            user_info,
+            user_balance_info,
+            system_program_info,
+            &beneficiary,
+            vault_state_info.key,
+            operation,
+        )?;
+
+        // Validate user balance account
+        user_balance.validate().map_err(|err| {
+            log_error!("{}: User balance validation failed: {}", operation, err);
+            VaultError::InvalidAccountData
+        })?;
+
+        // Transfer tokens from user to vault
+        let transfer_ix = spl_token::instruction::transfer(
+            &spl_token::id(),
+            user_token_account_info.key,
+            vault_token_account_info.key,
+            user_info.key,
+            &[],
+            amount,
+        )?;
+
+        solana_program::program::invoke(
+            &transfer_ix,
+            &[
+                user_token_account_info.clone(),
+                vault_token_account_info.clone(),
+                user_info.clone(),
+                token_program_info.clone(),
+            ],
+        ).map_err(|e| {
+            log_error!("{}: Token transfer failed: {}", operation, e);
+            e
+        })?;
+
+        // Update user balance and vault total with overflow protection
+        settle_user_yield(&mut vault_state, &mut user_balance, operation)?;
+        user_balance.add_balance(amount).map_err(|err| {
+            log_error!("{}: Failed to update user balance: {}", operation, err);
+            VaultError::ArithmeticOverflow
+        })?;
+        vault_state.add_deposit(amount).map_err(|err| {
+            log_error!("{}: Failed to update vault total: {}", operation, err);
+            VaultError::ArithmeticOverflow
+        })?;
+        let new_balance = user_balance.balance;
+
+        // Save updated user balance
+        let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
+        serialize_user_balance_safe(&user_balance, &mut user_balance_data, operation)?;
+        drop(user_balance_data);
+
+        // Save updated vault state
+        let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+        serialize_vault_state(&vault_state, &mut vault_state_data, operation)?;
+
+        log_info!(
+            "{} successful. Depositor: {}, Beneficiary: {}, Amount: {}, New Balance: {}, Vault Total: {}",
+            operation,
+            user_info.key,
+            beneficiary,
+            amount,
+            new_balance,
+            vault_state.total_deposited
+        );
+
+        VaultEvent::Deposited {
+            vault: *vault_state_info.key,
+            user: beneficiary,
+            depositor: *user_info.key,
+            amount,
+            balance: new_balance,
+        }.emit();
+
+        Ok(())
+    }
+
+    /// Borsh counterpart of the zero-copy Withdraw handler
+    pub(super) fn process_withdraw(program_id: &Pubkey, accounts: &[AccountInfo], amount: u64) -> ProcessResult {
+        let account_info_iter = &mut accounts.iter();
+    
+        // Validate minimum number of accounts
+        if accounts.len() < 6 {
+            log_error!("Withdraw: Insufficient accounts provided");
+            return Err(VaultError::NotEnoughAccounts.into());
+        }
+    
+        // Expected accounts:
+        // 0. [signer, writable] User account
+        // 1. [writable] User token account
+        // 2. [writable] Vault token account
+        // 3. [writable] Vault state account
+        // 4. [writable] User balance account (PDA)
+        // 5. [] SPL Token program
+        let user_info = next_account_info(account_info_iter)?;
+        let user_token_account_info = next_account_info(account_info_iter)?;
+        let vault_token_account_info = next_account_info(account_info_iter)?;
+        let vault_state_info = next_account_info(account_info_iter)?;
+        let user_balance_info = next_account_info(account_info_iter)?;
+        let token_program_info = next_account_info(account_info_iter)?;
+
+        // Validate withdrawal amount
+        if amount == 0 {
+            log_error!("Withdraw: Amount must be greater than zero");
+            return Err(VaultError::ZeroAmount.into());
+        }
+
+        // Verify user is signer
+        if !user_info.is_signer {
+            log_error!("Withdraw: User must be signer");
+            return Err(VaultError::UnauthorizedAccess.with_account(user_info.key));
+        }
+
+        // Verify accounts are writable
+        if !user_token_account_info.is_writable {
+            log_error!("Withdraw: User token account must be writable");
+            return Err(VaultError::AccountNotWritable.with_account(user_token_account_info.key));
+        }
+        if !vault_token_account_info.is_writable {
+            log_error!("Withdraw: Vault token account must be writable");
+            return Err(VaultError::AccountNotWritable.with_account(vault_token_account_info.key));
+        }
+        if !vault_state_info.is_writable {
+            log_error!("Withdraw: Vault state account must be writable");
+            return Err(VaultError::AccountNotWritable.with_account(vault_state_info.key));
+        }
+        if !user_balance_info.is_writable {
+            log_error!("Withdraw: User balance account must be writable");
+            return Err(VaultError::AccountNotWritable.with_account(user_balance_info.key));
+        }
+
+        // Verify program accounts
+        if token_program_info.key != &spl_token::id() {
+            log_error!("Withdraw: Invalid SPL Token program");
+            return Err(VaultError::InvalidProgramId.with_account(token_program_info.key));
+        }
+
+        // Verify vault state account ownership
+        if vault_state_info.owner != program_id {
+            log_error!("Withdraw: Vault state account not owned by program");
+            return Err(VaultError::InvalidAccountOwner.with_account(vault_state_info.key));
+        }
+
+        // Load and validate vault state
+        let vault_state_data = vault_state_info.try_borrow_data()?;
+        let mut vault_state = deserialize_vault_state_safe(&vault_state_data, "Withdraw")
+            .with_account(vault_state_info.key)?;
+        drop(vault_state_data);
+
+        // Check if vault is operational
+        if !vault_state.is_operational() {
+            log_error!("Withdraw: Vault is closed");
+            return Err(VaultError::VaultClosed.into());
+        }
+        let token_mint = vault_state.token_mint;
+
+        // Verify token accounts are owned by SPL Token program
+        if user_token_account_info.owner != &spl_token::id() {
+            log_error!("Withdraw: User token account must be owned by SPL Token program");
+            return Err(VaultError::InvalidTokenAccount.with_account(user_token_account_info.key));
+        }
+        if vault_token_account_info.owner != &spl_token::id() {
+            log_error!("Withdraw: Vault token account must be owned by SPL Token program");
+            return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
+        }
+
+        // Verify token accounts match the vault's mint
+        let user_token_data = user_token_account_info.try_borrow_data()?;
+        let user_token_account = spl_token::state::Account::unpack(&user_token_data)
+            .map_err(|_| {
+                log_error!("Withdraw: Failed to unpack user token account");
+                VaultError::InvalidTokenAccount
+            })?;
+    
+        if user_token_account.mint != token_mint {
+            log_error!("Withdraw: User token account mint mismatch");
+            return Err(VaultError::InvalidMint.with_account(user_token_account_info.key));
+        }
+        drop(user_token_data);
+
+        // Verify vault token account
+        if *vault_token_account_info.key != vault_state.token_account {
+            log_error!("Withdraw: Vault token account mismatch. Expected: {}, Got: {}",
+                 vault_state.token_account, vault_token_account_info.key);
+            return Err(VaultError::InvalidTokenAccount.with_account(vault_token_account_info.key));
+        }
+        let vault_token_data = vault_token_account_info.try_borrow_data()?;
+        let vault_token_account = spl_token::state::Account::unpack(&vault_token_data)
+            .map_err(|_| {
+                log_error!("Withdraw: Failed to unpack vault token account");
+                VaultError::InvalidTokenAccount
+            })?;
+    
+        if vault_token_account.mint != token_mint {
+            log_error!("Withdraw: Vault token account mint mismatch");
+            return Err(VaultError::InvalidMint.with_account(vault_token_account_info.key));
+        }
+
+        // Idle liquidity not reserved for queued withdrawals
+        let available_liquidity = vault_state.available_liquidity(vault_token_account.amount);
+        drop(vault_token_data);
+
+        // Derive and verify user balance PDA
+        let (user_balance_pda, _) = derive_user_balance_pda(
+            program_id,
+            user_info.key,
+            vault_state_info.key,
+        )?;
+
+        if user_balance_pda != *user_balance_info.key {
+            log_error!("Withdraw: User balance PDA mismatch. Expected: {}, Got: {}", 
+                 user_balance_pda, user_balance_info.key);
+            return Err(VaultError::InvalidPda.with_account(user_balance_info.key));
+        }
+
+        // Load user balance account
+        if user_balance_info.owner != program_id {
+            log_error!("Withdraw: User balance account not owned by program");
+            return Err(VaultError::InvalidAccountOwner.with_account(user_balance_info.key));
+        }
+
+        let mut user_balance_data = user_balance_info.try_borrow_mut_data()?;
+        let mut user_balance = deserialize_user_balance_safe(&user_balance_data, "Withdraw")
+            .with_account(user_balance_info.key)?;
+
+        // Validate user balance account
+        user_balance.validate().map_err(|err| {
+            log_error!("Withdraw: User balance validation failed: {}", err);
+            VaultError::InvalidAccountData
+        })?;
+
+        // Credit accrued yield so it can be withdrawn
+        settle_user_yield(&mut vault_state, &mut user_balance, "Withdraw")?;
+
+        // Check if user has sufficient balance
+        if !user_balance.has_sufficient_balance(amount) {
+            log_error!("Withdraw: Insufficient user balance. Required: {}, Available: {}", 
+                 amount, user_balance.balance);
+            return Err(VaultError::InsufficientFunds.into());
+        }
+
+        // Queue the withdrawal when idle liquidity cannot cover it
+        if amount > available_liquidity {
+            let pending = queue_user_withdrawal(&mut vault_state, &mut user_balance, amount, "Withdraw")?;
+
+            serialize_user_balance_safe(&user_balance, &mut user_balance_data, "Withdraw")?;
+            drop(user_balance_data);
+
+            let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+            serialize_vault_state(&vault_state, &mut vault_state_data, "Withdraw")?;
+
+            log_info!(
+                "Withdraw queued. User: {}, Amount: {}, Available Liquidity: {}, Pending: {}, Vault Queued: {}",
+                user_info.key,
+                amount,
+                available_liquidity,
+                pending,
+                vault_state.total_queued
+            );
+
+            VaultEvent::WithdrawalQueued {
+                vault: *vault_state_info.key,
+                user: *user_info.key,
+                amount,
+                pending,
+            }.emit();
+
+            return Ok(());
+        }
+
+        // Transfer from vault to user with vault state as signer
+        transfer_from_vault(
+            &vault_state,
+            vault_state_info,
+            vault_token_account_info,
+            user_token_account_info,
+            token_program_info,
+            amount,
+            "Withdraw",
+        )?;
+
+        // Update user balance and vault total with underflow protection
+        user_balance.subtract_balance(amount).map_err(|err| {
+            log_error!("Withdraw: Failed to update user balance: {}", err);
+            VaultError::ArithmeticOverflow
+        })?;
+        vault_state.subtract_withdrawal(amount).map_err(|err| {
+            log_error!("Withdraw: Failed to update vault total: {}", err);
+            VaultError::ArithmeticOverflow
+        })?;
+
+        // Save updated user balance
+        serialize_user_balance_safe(&user_balance, &mut user_balance_data, "Withdraw")?;
+        drop(user_balance_data);
+
+        // Save updated vault state
+        let mut vault_state_data = vault_state_info.try_borrow_mut_data()?;
+        serialize_vault_state(&vault_state, &mut vault_state_data, "Withdraw")?;
+
+        log_info!(
+            "Withdraw successful. User: {}, Amount: {}, New Balance: {}, Vault Total: {}",
+            user_info.key,
+            amount,
+            user_balance.balance,
+            vault_state.total_deposited
+        );
+
+        VaultEvent::Withdrawn {
+            vault: *vault_state_info.key,
+            user: *user_info.key,
+            amount,
+            balance: user_balance.balance,
+        }.emit();
+
+        Ok(())
+    }
+}
@@ -1,6 +1,100 @@
 use borsh::{BorshDeserialize, BorshSerialize};
+use bytemuck::{Pod, Zeroable};
 use solana_program::pubkey::Pubkey;
 
+use crate::pod::{PodBool, PodU128, PodU16, PodU64};
+
+/// Flash loan fee in basis points of the borrowed amount
+pub const FLASH_LOAN_FEE_BPS: u64 = 9;
+
+/// Basis point denominator used by fee and liquidity ratios
+pub const BPS_DENOMINATOR: u64 = 10_000;
+
+/// Fixed-point scale of `VaultState::yield_per_token`
+pub const YIELD_SCALE: u128 = 1_000_000_000_000;
+
+/// Maximum number of strategies a vault can register
+pub const MAX_STRATEGIES: usize = 4;
+
+/// Reserved bytes at the end of VaultState, consumed by future fields without a realloc
+pub const VAULT_STATE_RESERVED: usize = 40;
+
+/// Reserved bytes at the end of UserBalance, consumed by future fields without a realloc
+pub const USER_BALANCE_RESERVED: usize = 16;
+
+/// Length of the header prefixed to every program account: 8-byte discriminator + version byte
+pub const ACCOUNT_HEADER_LEN: usize = 8 + 1;
+
+/// Offset of `UserBalance::vault` in the account data, header included (after the 32-byte `user`)
+pub const USER_BALANCE_VAULT_OFFSET: usize = ACCOUNT_HEADER_LEN + 32;
+
+/// Program-owned account type. Every account is stored as its discriminator,
+/// its layout version and then the borsh-encoded body, so an account of one
+/// type can never be loaded as another.
+pub trait ProgramAccount: BorshSerialize + BorshDeserialize {
+    /// Type tag stored in the first 8 bytes of the account
+    const DISCRIMINATOR: [u8; 8];
+    /// Current layout version, stored right after the discriminator
+    const VERSION: u8;
+    /// Size of the account data, header included
+    const SIZE: usize;
+
+    /// Whether `data` starts with this account type's discriminator
+    fn has_discriminator(data: &[u8]) -> bool {
+        data.starts_with(&Self::DISCRIMINATOR)
+    }
+
+    /// Layout version stored in `data`, if it carries this account type's discriminator
+    fn stored_version(data: &[u8]) -> Option<u8> {
+        if Self::has_discriminator(data) {
+            data.get(ACCOUNT_HEADER_LEN - 1).copied()
+        } else {
+            None
+        }
+    }
+
+    /// Serialize the account, header included
+    fn to_account_bytes(&self) -> Result<Vec<u8>, &'static str> {
+        let mut data = Vec::with_capacity(Self::SIZE);
+        data.extend_from_slice(&Self::DISCRIMINATOR);
+        data.push(Self::VERSION);
+        self.serialize(&mut data).map_err(|_| "Failed to serialize account")?;
+        if data.len() != Self::SIZE {
+            return Err("Serialized account size mismatch");
+        }
+        Ok(data)
+    }
+
+    /// Deserialize account data written by `to_account_bytes`, checking size and header
+    fn from_account_bytes(data: &[u8]) -> Result<Self, &'static str> {
+        if data.len() != Self::SIZE {
+            return Err("Account data size mismatch");
+        }
+        match Self::stored_version(data) {
+            None => Err("Account discriminator mismatch"),
+            Some(version) if version != Self::VERSION => Err("Unsupported account version"),
+            Some(_) => Self::try_from_slice(&data[ACCOUNT_HEADER_LEN..])
+                .map_err(|_| "Failed to deserialize account"),
+        }
+    }
+}
+
+/// Strategy registry entry stored in the vault state
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct StrategyAllocation {
+    /// Strategy adapter program the funds are deployed to
+    pub program_id: Pubkey,
+    /// Amount currently held by the strategy, as of the last allocation or harvest
+    pub allocated: u64,
+    /// Maximum amount the owner may allocate to the strategy
+    pub cap: u64,
+}
+
+impl StrategyAllocation {
+    /// Size of StrategyAllocation when serialized
+    pub const SIZE: usize = 32 + 8 + 8; // 48 bytes
+}
+
 /// Vault state account (PDA)
 /// Stores global vault information including owner, token details, and status
 #[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
@@ -17,17 +111,41 @@ pub struct VaultState {
     pub is_closed: bool,
     /// Bump seed used for PDA derivation
     pub bump: u8,
+    /// Identifier distinguishing vaults with the same owner and mint (part of the PDA seeds)
+    pub vault_id: u64,
+    /// Reentrancy guard, set while a flash loan is in flight
+    pub is_locked: bool,
+    /// Lifetime flash loan fees, credited to depositors through `yield_per_token`
+    pub accrued_fees: u64,
+    /// Number of registered strategies
+    pub strategy_count: u8,
+    /// Strategy registry (only the first `strategy_count` entries are in use)
+    pub strategies: [StrategyAllocation; MAX_STRATEGIES],
+    /// Minimum share of total assets, in basis points, that allocations must leave idle
+    pub min_idle_bps: u16,
+    /// Withdrawals queued for lack of idle liquidity and not yet claimed
+    pub total_queued: u64,
+    /// Cumulative yield credited per deposited token, scaled by `YIELD_SCALE`
+    pub yield_per_token: u128,
+    /// Strategy losses reported by harvests and not yet made up by later gains
+    pub strategy_loss: u64,
+    /// Zeroed padding for future fields
+    pub reserved: [u8; VAULT_STATE_RESERVED],
 }
 
-impl VaultState {
-    /// Size of VaultState when serialized
-    pub const SIZE: usize = 32 + 32 + 32 + 8 + 1 + 1; // 106 bytes
+impl ProgramAccount for VaultState {
+    const DISCRIMINATOR: [u8; 8] = *b"vault_st";
+    const VERSION: u8 = 2;
+    const SIZE: usize = VaultStateV1::SIZE + 16 + 8 + VAULT_STATE_RESERVED; // 399 bytes
+}
 
+impl VaultState {
     /// Create a new VaultState instance
     pub fn new(
         owner: Pubkey,
         token_mint: Pubkey,
         token_account: Pubkey,
+        vault_id: u64,
         bump: u8,
     ) -> Self {
         Self {
@@ -37,6 +155,16 @@ impl VaultState {
             total_deposited: 0,
             is_closed: false,
             bump,
+            vault_id,
+            is_locked: false,
+            accrued_fees: 0,
+            strategy_count: 0,
+            strategies: [StrategyAllocation::default(); MAX_STRATEGIES],
+            min_idle_bps: 0,
+            total_queued: 0,
+            yield_per_token: 0,
+            strategy_loss: 0,
+            reserved: [0; VAULT_STATE_RESERVED],
         }
     }
 
@@ -67,6 +195,164 @@ impl VaultState {
         self.total_deposited = 0;
     }
 
+    /// Fee owed on a flash loan of `amount`, rounded up
+    pub fn flash_loan_fee(amount: u64) -> Result<u64, &'static str> {
+        let fee = (amount as u128)
+            .checked_mul(FLASH_LOAN_FEE_BPS as u128)
+            .ok_or("Arithmetic overflow in flash loan fee")?
+            .div_ceil(BPS_DENOMINATOR as u128);
+        u64::try_from(fee).map_err(|_| "Flash loan fee exceeds u64")
+    }
+
+    /// Record a repaid flash loan fee with overflow protection
+    pub fn add_accrued_fees(&mut self, fee: u64) -> Result<(), &'static str> {
+        self.accrued_fees = self.accrued_fees
+            .checked_add(fee)
+            .ok_or("Arithmetic overflow in accrued_fees")?;
+        Ok(())
+    }
+
+    /// Registered strategies
+    pub fn active_strategies(&self) -> &[StrategyAllocation] {
+        &self.strategies[..self.strategy_count as usize]
+    }
+
+    /// Register a strategy adapter program, returning its index
+    pub fn add_strategy(&mut self, program_id: Pubkey, cap: u64) -> Result<usize, &'static str> {
+        if self.active_strategies().iter().any(|s| s.program_id == program_id) {
+            return Err("Strategy already registered");
+        }
+        let index = self.strategy_count as usize;
+        if index >= MAX_STRATEGIES {
+            return Err("Strategy registry is full");
+        }
+        self.strategies[index] = StrategyAllocation { program_id, allocated: 0, cap };
+        self.strategy_count += 1;
+        Ok(index)
+    }
+
+    /// Look up a registered strategy by index
+    pub fn strategy(&self, index: u8) -> Result<&StrategyAllocation, &'static str> {
+        self.active_strategies().get(index as usize).ok_or("Unknown strategy index")
+    }
+
+    /// Record tokens moved into a strategy, enforcing its cap
+    pub fn allocate_to_strategy(&mut self, index: u8, amount: u64) -> Result<(), &'static str> {
+        self.strategy(index)?;
+        let strategy = &mut self.strategies[index as usize];
+        let allocated = strategy.allocated
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow in strategy allocation")?;
+        if allocated > strategy.cap {
+            return Err("Strategy cap exceeded");
+        }
+        strategy.allocated = allocated;
+        Ok(())
+    }
+
+    /// Record tokens returned from a strategy
+    pub fn deallocate_from_strategy(&mut self, index: u8, amount: u64) -> Result<(), &'static str> {
+        self.strategy(index)?;
+        let strategy = &mut self.strategies[index as usize];
+        strategy.allocated = strategy.allocated
+            .checked_sub(amount)
+            .ok_or("Arithmetic underflow in strategy allocation")?;
+        Ok(())
+    }
+
+    /// Replace a strategy's allocation with the balance it reported, returning the previous allocation
+    pub fn report_strategy_balance(&mut self, index: u8, balance: u64) -> Result<u64, &'static str> {
+        self.strategy(index)?;
+        let strategy = &mut self.strategies[index as usize];
+        Ok(std::mem::replace(&mut strategy.allocated, balance))
+    }
+
+    /// Account for a harvest moving a strategy from `previous` to `reported`. Gains first make up
+    /// earlier unrecovered losses and the rest is distributed to depositors; losses are recorded
+    /// without touching user balances. Returns the amount distributed.
+    pub fn record_harvest(&mut self, previous: u64, reported: u64) -> Result<u64, &'static str> {
+        if reported < previous {
+            self.strategy_loss = self.strategy_loss
+                .checked_add(previous - reported)
+                .ok_or("Arithmetic overflow in strategy_loss")?;
+            return Ok(0);
+        }
+        let gain = reported - previous;
+        let recovered = gain.min(self.strategy_loss);
+        self.strategy_loss -= recovered;
+        let remaining = gain - recovered;
+        Ok(if self.distribute_yield(remaining)? { remaining } else { 0 })
+    }
+
+    /// Total amount deployed to strategies
+    pub fn total_allocated(&self) -> Result<u64, &'static str> {
+        self.active_strategies().iter().try_fold(0u64, |total, s| {
+            total.checked_add(s.allocated).ok_or("Arithmetic overflow in total allocated")
+        })
+    }
+
+    /// Total assets managed by the vault: idle tokens plus strategy balances
+    pub fn total_assets(&self, idle: u64) -> Result<u64, &'static str> {
+        idle.checked_add(self.total_allocated()?)
+            .ok_or("Arithmetic overflow in total assets")
+    }
+
+    /// Set the minimum idle ratio allocations must respect
+    pub fn set_min_idle_bps(&mut self, min_idle_bps: u16) -> Result<(), &'static str> {
+        if min_idle_bps as u64 > BPS_DENOMINATOR {
+            return Err("Minimum idle ratio exceeds 100%");
+        }
+        self.min_idle_bps = min_idle_bps;
+        Ok(())
+    }
+
+    /// Idle tokens that must stay in the vault: queued withdrawals plus the
+    /// `min_idle_bps` share of total assets (rounded up)
+    pub fn required_idle(&self, total_assets: u64) -> Result<u64, &'static str> {
+        let buffer = (total_assets as u128 * self.min_idle_bps as u128)
+            .div_ceil(BPS_DENOMINATOR as u128) as u64;
+        buffer.checked_add(self.total_queued)
+            .ok_or("Arithmetic overflow in required idle liquidity")
+    }
+
+    /// Idle tokens available to immediate withdrawals, excluding liquidity reserved for the queue
+    pub fn available_liquidity(&self, idle: u64) -> u64 {
+        idle.saturating_sub(self.total_queued)
+    }
+
+    /// Record a withdrawal queued for lack of idle liquidity
+    pub fn add_queued(&mut self, amount: u64) -> Result<(), &'static str> {
+        self.total_queued = self.total_queued
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow in total_queued")?;
+        Ok(())
+    }
+
+    /// Record a claimed queued withdrawal leaving the vault
+    pub fn settle_queued(&mut self, amount: u64) -> Result<(), &'static str> {
+        self.total_queued = self.total_queued
+            .checked_sub(amount)
+            .ok_or("Arithmetic underflow in total_queued")?;
+        self.subtract_withdrawal(amount)
+    }
+
+    /// Share `amount` of yield among depositors pro rata to their balances and pending
+    /// withdrawals. Users pick up their share when their balance is next touched. Returns
+    /// false, leaving the tokens unattributed in the vault, when nothing is deposited.
+    pub fn distribute_yield(&mut self, amount: u64) -> Result<bool, &'static str> {
+        if self.total_deposited == 0 {
+            return Ok(false);
+        }
+        let increment = (amount as u128)
+            .checked_mul(YIELD_SCALE)
+            .ok_or("Arithmetic overflow in yield distribution")?
+            / self.total_deposited as u128;
+        self.yield_per_token = self.yield_per_token
+            .checked_add(increment)
+            .ok_or("Arithmetic overflow in yield_per_token")?;
+        Ok(true)
+    }
+
     /// Check if the vault is operational (not closed)
     pub fn is_operational(&self) -> bool {
         !self.is_closed
@@ -83,6 +369,9 @@ impl VaultState {
         if self.token_account == Pubkey::default() {
             return Err("Invalid token account pubkey");
         }
+        if self.strategy_count as usize > MAX_STRATEGIES {
+            return Err("Invalid strategy count");
+        }
         Ok(())
     }
 }
@@ -98,12 +387,27 @@ pub struct UserBalance {
     pub balance: u64,
     /// Bump seed used for PDA derivation
     pub bump: u8,
+    /// Third party allowed to withdraw on the user's behalf (default pubkey if none)
+    pub delegate: Pubkey,
+    /// Remaining amount the delegate may withdraw
+    pub delegated_amount: u64,
+    /// Next withdrawal permit nonce expected from the user (replay protection)
+    pub nonce: u64,
+    /// Amount queued for withdrawal, claimable once the vault has idle liquidity
+    pub pending_withdrawal: u64,
+    /// Vault `yield_per_token` at which yield was last credited to this balance
+    pub yield_checkpoint: u128,
+    /// Zeroed padding for future fields
+    pub reserved: [u8; USER_BALANCE_RESERVED],
 }
 
-impl UserBalance {
-    /// Size of UserBalance when serialized
-    pub const SIZE: usize = 32 + 32 + 8 + 1; // 73 bytes
+impl ProgramAccount for UserBalance {
+    const DISCRIMINATOR: [u8; 8] = *b"vault_ub";
+    const VERSION: u8 = 2;
+    const SIZE: usize = UserBalanceV1::SIZE + 16 + USER_BALANCE_RESERVED; // 170 bytes
+}
 
+impl UserBalance {
     /// Create a new UserBalance instance
     pub fn new(user: Pubkey, vault: Pubkey, bump: u8) -> Self {
         Self {
@@ -111,6 +415,12 @@ impl UserBalance {
             vault,
             balance: 0,
             bump,
+            delegate: Pubkey::default(),
+            delegated_amount: 0,
+            nonce: 0,
+            pending_withdrawal: 0,
+            yield_checkpoint: 0,
+            reserved: [0; USER_BALANCE_RESERVED],
         }
     }
 
@@ -130,6 +440,34 @@ impl UserBalance {
         Ok(())
     }
 
+    /// Move `amount` from the balance into the pending withdrawal queue
+    pub fn queue_withdrawal(&mut self, amount: u64) -> Result<(), &'static str> {
+        self.subtract_balance(amount)?;
+        self.pending_withdrawal = self.pending_withdrawal
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow in pending withdrawal")?;
+        Ok(())
+    }
+
+    /// Clear the pending withdrawal, returning the amount to pay out
+    pub fn take_pending_withdrawal(&mut self) -> u64 {
+        std::mem::take(&mut self.pending_withdrawal)
+    }
+
+    /// Yield accrued since the last settlement and not yet part of the balance
+    pub fn unsettled_yield(&self, yield_per_token: u128) -> Result<u64, &'static str> {
+        accrued_yield(self.balance, self.pending_withdrawal, yield_per_token, self.yield_checkpoint)
+    }
+
+    /// Credit accrued yield to the balance and advance the checkpoint. Returns the amount
+    /// credited, which the caller adds to the vault's `total_deposited`.
+    pub fn settle_yield(&mut self, yield_per_token: u128) -> Result<u64, &'static str> {
+        let credited = self.unsettled_yield(yield_per_token)?;
+        self.add_balance(credited)?;
+        self.yield_checkpoint = yield_per_token;
+        Ok(credited)
+    }
+
     /// Check if user has sufficient balance for withdrawal
     pub fn has_sufficient_balance(&self, amount: u64) -> bool {
         self.balance >= amount
@@ -145,6 +483,45 @@ impl UserBalance {
         self.balance
     }
 
+    /// Approve a delegate to withdraw up to `amount`, replacing any previous approval
+    pub fn approve_delegate(&mut self, delegate: Pubkey, amount: u64) {
+        self.delegate = delegate;
+        self.delegated_amount = amount;
+    }
+
+    /// Remove the current delegate and its allowance
+    pub fn revoke_delegate(&mut self) {
+        self.delegate = Pubkey::default();
+        self.delegated_amount = 0;
+    }
+
+    /// Check if `key` is the currently approved delegate
+    pub fn is_delegate(&self, key: &Pubkey) -> bool {
+        self.delegate != Pubkey::default() && self.delegate == *key
+    }
+
+    /// Consume delegate allowance, clearing the delegate once it is used up
+    pub fn spend_allowance(&mut self, amount: u64) -> Result<(), &'static str> {
+        self.delegated_amount = self.delegated_amount
+            .checked_sub(amount)
+            .ok_or("Insufficient delegate allowance")?;
+        if self.delegated_amount == 0 {
+            self.delegate = Pubkey::default();
+        }
+        Ok(())
+    }
+
+    /// Consume a withdrawal permit nonce; it must match the next expected nonce
+    pub fn use_nonce(&mut self, nonce: u64) -> Result<(), &'static str> {
+        if nonce != self.nonce {
+            return Err("Permit nonce mismatch");
+        }
+        self.nonce = self.nonce
+            .checked_add(1)
+            .ok_or("Arithmetic overflow in permit nonce")?;
+        Ok(())
+    }
+
     /// Validate the user balance account for consistency
     pub fn validate(&self) -> Result<(), &'static str> {
         if self.user == Pubkey::default() {
@@ -155,4 +532,729 @@ impl UserBalance {
         }
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Yield owed on a user's balance and pending withdrawal since `checkpoint`, rounded down
+fn accrued_yield(balance: u64, pending: u64, yield_per_token: u128, checkpoint: u128) -> Result<u64, &'static str> {
+    let principal = balance as u128 + pending as u128;
+    let delta = yield_per_token
+        .checked_sub(checkpoint)
+        .ok_or("Yield checkpoint ahead of the vault")?;
+    let owed = principal
+        .checked_mul(delta)
+        .ok_or("Arithmetic overflow in accrued yield")?
+        / YIELD_SCALE;
+    u64::try_from(owed).map_err(|_| "Accrued yield exceeds u64")
+}
+
+/// Maximum number of mints a basket vault can hold
+pub const MAX_BASKET_MINTS: usize = 8;
+
+/// Multi-mint vault state account (PDA)
+/// Holds a list of SPL token mints under a single owner, each with its own PDA token account
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct BasketState {
+    /// The owner of the basket who can add mints
+    pub owner: Pubkey,
+    /// Number of populated entries in `mints`
+    pub mint_count: u8,
+    /// Accepted mints; only the first `mint_count` entries are valid
+    pub mints: [Pubkey; MAX_BASKET_MINTS],
+    /// Total amount deposited per mint, indexed like `mints`
+    pub total_deposited: [u64; MAX_BASKET_MINTS],
+    /// Whether the basket is closed (no operations allowed if true)
+    pub is_closed: bool,
+    /// Bump seed used for PDA derivation
+    pub bump: u8,
+    /// Identifier distinguishing baskets of the same owner (part of the PDA seeds)
+    pub basket_id: u64,
+}
+
+impl ProgramAccount for BasketState {
+    const DISCRIMINATOR: [u8; 8] = *b"vault_bs";
+    const VERSION: u8 = 1;
+    const SIZE: usize =
+        ACCOUNT_HEADER_LEN + 32 + 1 + 32 * MAX_BASKET_MINTS + 8 * MAX_BASKET_MINTS + 1 + 1 + 8; // 372 bytes
+}
+
+impl BasketState {
+    /// Create a new, empty BasketState instance
+    pub fn new(owner: Pubkey, basket_id: u64, bump: u8) -> Self {
+        Self {
+            owner,
+            mint_count: 0,
+            mints: [Pubkey::default(); MAX_BASKET_MINTS],
+            total_deposited: [0; MAX_BASKET_MINTS],
+            is_closed: false,
+            bump,
+            basket_id,
+        }
+    }
+
+    /// Check if the basket is operational (not closed)
+    pub fn is_operational(&self) -> bool {
+        !self.is_closed
+    }
+
+    /// Whether every mint's total deposited is zero
+    pub fn is_empty(&self) -> bool {
+        self.total_deposited.iter().all(|total| *total == 0)
+    }
+
+    /// Close the basket
+    pub fn close(&mut self) {
+        self.is_closed = true;
+    }
+
+    /// Currently configured mints
+    pub fn active_mints(&self) -> &[Pubkey] {
+        &self.mints[..self.mint_count as usize]
+    }
+
+    /// Position of `mint` in the basket, if it is accepted
+    pub fn mint_index(&self, mint: &Pubkey) -> Option<usize> {
+        self.active_mints().iter().position(|m| m == mint)
+    }
+
+    /// Add a new mint to the basket
+    pub fn add_mint(&mut self, mint: Pubkey) -> Result<usize, &'static str> {
+        if self.mint_index(&mint).is_some() {
+            return Err("Mint already in basket");
+        }
+        let index = self.mint_count as usize;
+        if index >= MAX_BASKET_MINTS {
+            return Err("Basket is full");
+        }
+        self.mints[index] = mint;
+        self.mint_count += 1;
+        Ok(index)
+    }
+
+    /// Add to the total deposited for the mint at `index` with overflow protection
+    pub fn add_deposit(&mut self, index: usize, amount: u64) -> Result<(), &'static str> {
+        self.total_deposited[index] = self.total_deposited[index]
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow in basket total_deposited")?;
+        Ok(())
+    }
+
+    /// Subtract from the total deposited for the mint at `index` with underflow protection
+    pub fn subtract_withdrawal(&mut self, index: usize, amount: u64) -> Result<(), &'static str> {
+        self.total_deposited[index] = self.total_deposited[index]
+            .checked_sub(amount)
+            .ok_or("Arithmetic underflow in basket total_deposited")?;
+        Ok(())
+    }
+
+    /// Validate the basket state for consistency
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.owner == Pubkey::default() {
+            return Err("Invalid owner pubkey");
+        }
+        if self.mint_count as usize > MAX_BASKET_MINTS {
+            return Err("Invalid basket mint count");
+        }
+        if self.active_mints().iter().any(|m| *m == Pubkey::default()) {
+            return Err("Invalid basket mint pubkey");
+        }
+        Ok(())
+    }
+}
+
+/// Basket balance account (PDA)
+/// Tracks a user's balance of one mint within a basket vault
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct BasketBalance {
+    /// The user's public key
+    pub user: Pubkey,
+    /// The basket this balance belongs to
+    pub basket: Pubkey,
+    /// The mint this balance is denominated in
+    pub mint: Pubkey,
+    /// The user's current balance of `mint` in the basket
+    pub balance: u64,
+    /// Bump seed used for PDA derivation
+    pub bump: u8,
+}
+
+impl ProgramAccount for BasketBalance {
+    const DISCRIMINATOR: [u8; 8] = *b"vault_bb";
+    const VERSION: u8 = 1;
+    const SIZE: usize = ACCOUNT_HEADER_LEN + 32 + 32 + 32 + 8 + 1; // 114 bytes
+}
+
+impl BasketBalance {
+    /// Create a new BasketBalance instance
+    pub fn new(user: Pubkey, basket: Pubkey, mint: Pubkey, bump: u8) -> Self {
+        Self {
+            user,
+            basket,
+            mint,
+            balance: 0,
+            bump,
+        }
+    }
+
+    /// Add to user balance with overflow protection
+    pub fn add_balance(&mut self, amount: u64) -> Result<(), &'static str> {
+        self.balance = self.balance
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow in basket balance")?;
+        Ok(())
+    }
+
+    /// Subtract from user balance with underflow protection
+    pub fn subtract_balance(&mut self, amount: u64) -> Result<(), &'static str> {
+        self.balance = self.balance
+            .checked_sub(amount)
+            .ok_or("Insufficient basket balance for withdrawal")?;
+        Ok(())
+    }
+
+    /// Validate the basket balance account for consistency
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.user == Pubkey::default() {
+            return Err("Invalid user pubkey");
+        }
+        if self.basket == Pubkey::default() {
+            return Err("Invalid basket pubkey");
+        }
+        if self.mint == Pubkey::default() {
+            return Err("Invalid mint pubkey");
+        }
+        Ok(())
+    }
+}
+
+/// Maximum length in bytes of a vault display name
+pub const MAX_METADATA_NAME_LEN: usize = 32;
+
+/// Maximum length in bytes of a vault metadata URI
+pub const MAX_METADATA_URI_LEN: usize = 200;
+
+/// Reserved bytes at the end of VaultMetadata, consumed by future fields without a realloc
+pub const VAULT_METADATA_RESERVED: usize = 32;
+
+/// Vault metadata account (PDA)
+/// Display information for front-ends, owned by the vault owner
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct VaultMetadata {
+    /// The vault this metadata describes
+    pub vault: Pubkey,
+    /// Length of the name in bytes
+    pub name_len: u8,
+    /// UTF-8 display name; only the first `name_len` bytes are valid
+    pub name: [u8; MAX_METADATA_NAME_LEN],
+    /// Length of the URI in bytes
+    pub uri_len: u8,
+    /// UTF-8 URI of off-chain details (logo, description); only the first `uri_len` bytes are valid
+    pub uri: [u8; MAX_METADATA_URI_LEN],
+    /// Decimals of the vault mint, cached for display
+    pub decimals: u8,
+    /// Unix timestamp of the metadata creation
+    pub created_at: i64,
+    /// Bump seed used for PDA derivation
+    pub bump: u8,
+    /// Zeroed padding for future fields
+    pub reserved: [u8; VAULT_METADATA_RESERVED],
+}
+
+impl ProgramAccount for VaultMetadata {
+    const DISCRIMINATOR: [u8; 8] = *b"vault_md";
+    const VERSION: u8 = 1;
+    const SIZE: usize = ACCOUNT_HEADER_LEN
+        + 32 + 1 + MAX_METADATA_NAME_LEN + 1 + MAX_METADATA_URI_LEN + 1 + 8 + 1 + VAULT_METADATA_RESERVED; // 317 bytes
+}
+
+impl VaultMetadata {
+    /// Create metadata for `vault` with the given name and URI
+    pub fn new(
+        vault: Pubkey,
+        name: &str,
+        uri: &str,
+        decimals: u8,
+        created_at: i64,
+        bump: u8,
+    ) -> Result<Self, &'static str> {
+        let mut metadata = Self {
+            vault,
+            name_len: 0,
+            name: [0; MAX_METADATA_NAME_LEN],
+            uri_len: 0,
+            uri: [0; MAX_METADATA_URI_LEN],
+            decimals,
+            created_at,
+            bump,
+            reserved: [0; VAULT_METADATA_RESERVED],
+        };
+        metadata.set_name(name)?;
+        metadata.set_uri(uri)?;
+        Ok(metadata)
+    }
+
+    /// Display name
+    pub fn name(&self) -> &str {
+        std::str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or_default()
+    }
+
+    /// Metadata URI
+    pub fn uri(&self) -> &str {
+        std::str::from_utf8(&self.uri[..self.uri_len as usize]).unwrap_or_default()
+    }
+
+    /// Replace the display name; it must be non-empty, at most 32 bytes and free of control characters
+    pub fn set_name(&mut self, name: &str) -> Result<(), &'static str> {
+        if name.trim().is_empty() {
+            return Err("Metadata name is empty");
+        }
+        if name.len() > MAX_METADATA_NAME_LEN {
+            return Err("Metadata name is too long");
+        }
+        if name.chars().any(char::is_control) {
+            return Err("Metadata name contains control characters");
+        }
+        self.name = [0; MAX_METADATA_NAME_LEN];
+        self.name[..name.len()].copy_from_slice(name.as_bytes());
+        self.name_len = name.len() as u8;
+        Ok(())
+    }
+
+    /// Replace the URI; it may be empty, must be at most 200 bytes and free of whitespace and control characters
+    pub fn set_uri(&mut self, uri: &str) -> Result<(), &'static str> {
+        if uri.len() > MAX_METADATA_URI_LEN {
+            return Err("Metadata URI is too long");
+        }
+        if uri.chars().any(|c| c.is_control() || c.is_whitespace()) {
+            return Err("Metadata URI contains whitespace or control characters");
+        }
+        self.uri = [0; MAX_METADATA_URI_LEN];
+        self.uri[..uri.len()].copy_from_slice(uri.as_bytes());
+        self.uri_len = uri.len() as u8;
+        Ok(())
+    }
+
+    /// Validate the metadata account for consistency
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.vault == Pubkey::default() {
+            return Err("Invalid vault pubkey");
+        }
+        if self.name_len as usize > MAX_METADATA_NAME_LEN || self.uri_len as usize > MAX_METADATA_URI_LEN {
+            return Err("Invalid metadata length");
+        }
+        if std::str::from_utf8(&self.name[..self.name_len as usize]).is_err()
+            || std::str::from_utf8(&self.uri[..self.uri_len as usize]).is_err()
+        {
+            return Err("Metadata is not valid UTF-8");
+        }
+        Ok(())
+    }
+}
+
+/// Zero-copy view of a program account. The view is `#[repr(C)]` with an
+/// alignment of 1 and byte-identical to the borsh layout of `Account`, header
+/// included, so handlers can read and update fields in place.
+pub trait ZeroCopyAccount: Pod {
+    /// Borsh account type this view mirrors
+    type Account: ProgramAccount;
+
+    /// View account data, checking its size and header
+    fn load(data: &[u8]) -> Result<&Self, &'static str> {
+        check_zero_copy_header::<Self>(data)?;
+        bytemuck::try_from_bytes(data).map_err(|_| "Invalid zero-copy account data")
+    }
+
+    /// Mutably view account data, checking its size and header
+    fn load_mut(data: &mut [u8]) -> Result<&mut Self, &'static str> {
+        check_zero_copy_header::<Self>(data)?;
+        bytemuck::try_from_bytes_mut(data).map_err(|_| "Invalid zero-copy account data")
+    }
+}
+
+fn check_zero_copy_header<T: ZeroCopyAccount>(data: &[u8]) -> Result<(), &'static str> {
+    if data.len() != T::Account::SIZE {
+        return Err("Account data size mismatch");
+    }
+    match T::Account::stored_version(data) {
+        None => Err("Account discriminator mismatch"),
+        Some(version) if version != T::Account::VERSION => Err("Unsupported account version"),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Zero-copy layout of `StrategyAllocation`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct PodStrategyAllocation {
+    pub program_id: Pubkey,
+    pub allocated: PodU64,
+    pub cap: PodU64,
+}
+
+/// Zero-copy layout of a `VaultState` account
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct PodVaultState {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub owner: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_account: Pubkey,
+    pub total_deposited: PodU64,
+    pub is_closed: PodBool,
+    pub bump: u8,
+    pub vault_id: PodU64,
+    pub is_locked: PodBool,
+    pub accrued_fees: PodU64,
+    pub strategy_count: u8,
+    pub strategies: [PodStrategyAllocation; MAX_STRATEGIES],
+    pub min_idle_bps: PodU16,
+    pub total_queued: PodU64,
+    pub yield_per_token: PodU128,
+    pub strategy_loss: PodU64,
+    pub reserved: [u8; VAULT_STATE_RESERVED],
+}
+
+const _: () = assert!(std::mem::size_of::<PodVaultState>() == <VaultState as ProgramAccount>::SIZE);
+
+impl ZeroCopyAccount for PodVaultState {
+    type Account = VaultState;
+}
+
+impl PodVaultState {
+    /// Check if the vault is operational (not closed)
+    pub fn is_operational(&self) -> bool {
+        !self.is_closed.get()
+    }
+
+    /// Add to total deposited with overflow protection
+    pub fn add_deposit(&mut self, amount: u64) -> Result<(), &'static str> {
+        let total = self.total_deposited.get()
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow in total_deposited")?;
+        self.total_deposited.set(total);
+        Ok(())
+    }
+
+    /// Subtract from total deposited with underflow protection
+    pub fn subtract_withdrawal(&mut self, amount: u64) -> Result<(), &'static str> {
+        let total = self.total_deposited.get()
+            .checked_sub(amount)
+            .ok_or("Arithmetic underflow in total_deposited")?;
+        self.total_deposited.set(total);
+        Ok(())
+    }
+
+    /// Idle tokens available to immediate withdrawals, excluding liquidity reserved for the queue
+    pub fn available_liquidity(&self, idle: u64) -> u64 {
+        idle.saturating_sub(self.total_queued.get())
+    }
+
+    /// Record a newly queued withdrawal
+    pub fn add_queued(&mut self, amount: u64) -> Result<(), &'static str> {
+        let queued = self.total_queued.get()
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow in total_queued")?;
+        self.total_queued.set(queued);
+        Ok(())
+    }
+}
+
+/// Zero-copy layout of a `UserBalance` account
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+pub struct PodUserBalance {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub balance: PodU64,
+    pub bump: u8,
+    pub delegate: Pubkey,
+    pub delegated_amount: PodU64,
+    pub nonce: PodU64,
+    pub pending_withdrawal: PodU64,
+    pub yield_checkpoint: PodU128,
+    pub reserved: [u8; USER_BALANCE_RESERVED],
+}
+
+const _: () = assert!(std::mem::size_of::<PodUserBalance>() == <UserBalance as ProgramAccount>::SIZE);
+
+impl ZeroCopyAccount for PodUserBalance {
+    type Account = UserBalance;
+}
+
+impl PodUserBalance {
+    /// Add to user balance with overflow protection
+    pub fn add_balance(&mut self, amount: u64) -> Result<(), &'static str> {
+        let balance = self.balance.get()
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow in user balance")?;
+        self.balance.set(balance);
+        Ok(())
+    }
+
+    /// Subtract from user balance with underflow protection
+    pub fn subtract_balance(&mut self, amount: u64) -> Result<(), &'static str> {
+        let balance = self.balance.get()
+            .checked_sub(amount)
+            .ok_or("Insufficient balance for withdrawal")?;
+        self.balance.set(balance);
+        Ok(())
+    }
+
+    /// Move `amount` from the balance into the pending withdrawal queue
+    pub fn queue_withdrawal(&mut self, amount: u64) -> Result<(), &'static str> {
+        let pending = self.pending_withdrawal.get()
+            .checked_add(amount)
+            .ok_or("Arithmetic overflow in pending withdrawal")?;
+        self.subtract_balance(amount)?;
+        self.pending_withdrawal.set(pending);
+        Ok(())
+    }
+
+    /// Credit accrued yield to the balance and advance the checkpoint, returning the amount credited
+    pub fn settle_yield(&mut self, yield_per_token: u128) -> Result<u64, &'static str> {
+        let credited = accrued_yield(
+            self.balance.get(),
+            self.pending_withdrawal.get(),
+            yield_per_token,
+            self.yield_checkpoint.get(),
+        )?;
+        self.add_balance(credited)?;
+        self.yield_checkpoint.set(yield_per_token);
+        Ok(credited)
+    }
+
+    /// Check if user has sufficient balance for withdrawal
+    pub fn has_sufficient_balance(&self, amount: u64) -> bool {
+        self.balance.get() >= amount
+    }
+
+    /// Validate the user balance account for consistency
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if self.user == Pubkey::default() {
+            return Err("Invalid user pubkey");
+        }
+        if self.vault == Pubkey::default() {
+            return Err("Invalid vault pubkey");
+        }
+        Ok(())
+    }
+}
+
+/// Version 1 layout of VaultState, before the reserved padding was added.
+/// Only used to upgrade existing accounts with `MigrateVault`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct VaultStateV1 {
+    pub owner: Pubkey,
+    pub token_mint: Pubkey,
+    pub token_account: Pubkey,
+    pub total_deposited: u64,
+    pub is_closed: bool,
+    pub bump: u8,
+    pub vault_id: u64,
+    pub is_locked: bool,
+    pub accrued_fees: u64,
+    pub strategy_count: u8,
+    pub strategies: [StrategyAllocation; MAX_STRATEGIES],
+    pub min_idle_bps: u16,
+    pub total_queued: u64,
+}
+
+impl ProgramAccount for VaultStateV1 {
+    const DISCRIMINATOR: [u8; 8] = VaultState::DISCRIMINATOR;
+    const VERSION: u8 = 1;
+    const SIZE: usize = ACCOUNT_HEADER_LEN
+        + 32 + 32 + 32 + 8 + 1 + 1 + 8 + 1 + 8 + 1 + StrategyAllocation::SIZE * MAX_STRATEGIES + 2 + 8; // 335 bytes
+}
+
+impl From<VaultStateV1> for VaultState {
+    fn from(v1: VaultStateV1) -> Self {
+        Self {
+            owner: v1.owner,
+            token_mint: v1.token_mint,
+            token_account: v1.token_account,
+            total_deposited: v1.total_deposited,
+            is_closed: v1.is_closed,
+            bump: v1.bump,
+            vault_id: v1.vault_id,
+            is_locked: v1.is_locked,
+            accrued_fees: v1.accrued_fees,
+            strategy_count: v1.strategy_count,
+            strategies: v1.strategies,
+            min_idle_bps: v1.min_idle_bps,
+            total_queued: v1.total_queued,
+            yield_per_token: 0,
+            strategy_loss: 0,
+            reserved: [0; VAULT_STATE_RESERVED],
+        }
+    }
+}
+
+/// Version 1 layout of UserBalance, before the reserved padding was added.
+/// Only used to upgrade existing accounts with `MigrateUserBalance`.
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, PartialEq)]
+pub struct UserBalanceV1 {
+    pub user: Pubkey,
+    pub vault: Pubkey,
+    pub balance: u64,
+    pub bump: u8,
+    pub delegate: Pubkey,
+    pub delegated_amount: u64,
+    pub nonce: u64,
+    pub pending_withdrawal: u64,
+}
+
+impl ProgramAccount for UserBalanceV1 {
+    const DISCRIMINATOR: [u8; 8] = UserBalance::DISCRIMINATOR;
+    const VERSION: u8 = 1;
+    const SIZE: usize = ACCOUNT_HEADER_LEN + 32 + 32 + 8 + 1 + 32 + 8 + 8 + 8; // 138 bytes
+}
+
+impl From<UserBalanceV1> for UserBalance {
+    fn from(v1: UserBalanceV1) -> Self {
+        Self {
+            user: v1.user,
+            vault: v1.vault,
+            balance: v1.balance,
+            bump: v1.bump,
+            delegate: v1.delegate,
+            delegated_amount: v1.delegated_amount,
+            nonce: v1.nonce,
+            pending_withdrawal: v1.pending_withdrawal,
+            yield_checkpoint: 0,
+            reserved: [0; USER_BALANCE_RESERVED],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account_header_roundtrip_and_type_checks() {
+        let vault = VaultState::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 7, 255);
+        let data = vault.to_account_bytes().unwrap();
+        assert_eq!(data.len(), VaultState::SIZE);
+        assert_eq!(&data[..8], &VaultState::DISCRIMINATOR);
+        assert_eq!(VaultState::stored_version(&data), Some(VaultState::VERSION));
+        assert_eq!(VaultState::from_account_bytes(&data).unwrap(), vault);
+
+        // Another account type padded to the same length is rejected by its discriminator
+        let mut balance = UserBalance::new(Pubkey::new_unique(), Pubkey::new_unique(), 254)
+            .to_account_bytes()
+            .unwrap();
+        balance.resize(VaultState::SIZE, 0);
+        assert_eq!(VaultState::from_account_bytes(&balance), Err("Account discriminator mismatch"));
+
+        // Unknown layout versions are rejected
+        let mut future = data.clone();
+        future[ACCOUNT_HEADER_LEN - 1] = VaultState::VERSION + 1;
+        assert_eq!(VaultState::from_account_bytes(&future), Err("Unsupported account version"));
+    }
+
+    #[test]
+    fn test_vault_metadata_validation() {
+        let vault = Pubkey::new_unique();
+        let mut metadata = VaultMetadata::new(vault, "Blue Chip", "https://example.com/v.json", 9, 1_700_000_000, 253).unwrap();
+        assert_eq!(metadata.name(), "Blue Chip");
+        assert_eq!(metadata.uri(), "https://example.com/v.json");
+
+        let data = metadata.to_account_bytes().unwrap();
+        assert_eq!(data.len(), VaultMetadata::SIZE);
+        assert_eq!(VaultMetadata::from_account_bytes(&data).unwrap(), metadata);
+
+        // Multi-byte UTF-8 counts by bytes
+        assert!(metadata.set_name(&"é".repeat(16)).is_ok());
+        assert_eq!(metadata.set_name(&"é".repeat(17)), Err("Metadata name is too long"));
+        assert_eq!(metadata.set_name(" "), Err("Metadata name is empty"));
+        assert_eq!(metadata.set_name("bad\nname"), Err("Metadata name contains control characters"));
+        assert_eq!(metadata.set_uri("has space"), Err("Metadata URI contains whitespace or control characters"));
+        assert_eq!(metadata.set_uri(&"u".repeat(MAX_METADATA_URI_LEN + 1)), Err("Metadata URI is too long"));
+        assert!(metadata.set_uri("").is_ok());
+        assert!(metadata.validate().is_ok());
+    }
+
+    #[test]
+    fn test_zero_copy_views_match_borsh_layout() {
+        let mut vault = VaultState::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 3, 250);
+        vault.add_deposit(500).unwrap();
+        vault.add_strategy(Pubkey::new_unique(), 1_000).unwrap();
+        vault.set_min_idle_bps(1_500).unwrap();
+        let mut data = vault.to_account_bytes().unwrap();
+
+        let view = PodVaultState::load(&data).unwrap();
+        assert_eq!(view.owner, vault.owner);
+        assert_eq!(view.vault_id.get(), 3);
+        assert_eq!(view.total_deposited.get(), 500);
+        assert_eq!(view.strategy_count, 1);
+        assert_eq!(view.strategies[0].cap.get(), 1_000);
+        assert_eq!(view.min_idle_bps.get(), 1_500);
+
+        // In-place updates are visible to the borsh decoder
+        let view = PodVaultState::load_mut(&mut data).unwrap();
+        view.add_deposit(250).unwrap();
+        view.add_queued(100).unwrap();
+        vault.add_deposit(250).unwrap();
+        vault.add_queued(100).unwrap();
+        assert_eq!(VaultState::from_account_bytes(&data).unwrap(), vault);
+
+        let mut balance = UserBalance::new(Pubkey::new_unique(), Pubkey::new_unique(), 251);
+        let mut data = balance.to_account_bytes().unwrap();
+        let view = PodUserBalance::load_mut(&mut data).unwrap();
+        view.add_balance(80).unwrap();
+        view.queue_withdrawal(30).unwrap();
+        balance.add_balance(80).unwrap();
+        balance.queue_withdrawal(30).unwrap();
+        assert_eq!(UserBalance::from_account_bytes(&data).unwrap(), balance);
+
+        // Views check the header like the borsh loader does
+        assert!(PodVaultState::load(&data).is_err());
+    }
+
+    #[test]
+    fn test_yield_is_credited_pro_rata() {
+        let mut vault = VaultState::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 0, 255);
+        assert_eq!(vault.distribute_yield(100), Ok(false));
+
+        let mut alice = UserBalance::new(Pubkey::new_unique(), Pubkey::new_unique(), 255);
+        let mut bob = UserBalance::new(Pubkey::new_unique(), Pubkey::new_unique(), 255);
+        alice.add_balance(300).unwrap();
+        bob.add_balance(100).unwrap();
+        bob.queue_withdrawal(40).unwrap();
+        vault.add_deposit(400).unwrap();
+
+        // Pending withdrawals keep earning until claimed
+        assert_eq!(vault.distribute_yield(100), Ok(true));
+        assert_eq!(alice.unsettled_yield(vault.yield_per_token), Ok(75));
+        assert_eq!(bob.unsettled_yield(vault.yield_per_token), Ok(25));
+
+        // Settling is idempotent and a later depositor earns nothing retroactively
+        assert_eq!(alice.settle_yield(vault.yield_per_token), Ok(75));
+        assert_eq!(alice.settle_yield(vault.yield_per_token), Ok(0));
+        assert_eq!(alice.balance, 375);
+        let mut carol = UserBalance::new(Pubkey::new_unique(), Pubkey::new_unique(), 255);
+        assert_eq!(carol.settle_yield(vault.yield_per_token), Ok(0));
+        assert_eq!(carol.unsettled_yield(vault.yield_per_token), Ok(0));
+    }
+
+    #[test]
+    fn test_harvest_gains_offset_losses_before_distribution() {
+        let mut vault = VaultState::new(Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), 0, 255);
+        let mut user = UserBalance::new(Pubkey::new_unique(), Pubkey::new_unique(), 255);
+        user.add_balance(1_000).unwrap();
+        vault.add_deposit(1_000).unwrap();
+
+        assert_eq!(vault.record_harvest(500, 450), Ok(0));
+        assert_eq!(vault.strategy_loss, 50);
+
+        // The first 50 of the gain only restores the lost principal
+        assert_eq!(vault.record_harvest(450, 530), Ok(30));
+        assert_eq!(vault.strategy_loss, 0);
+        assert_eq!(user.unsettled_yield(vault.yield_per_token), Ok(30));
+
+        assert_eq!(vault.record_harvest(530, 530), Ok(0));
+        assert_eq!(user.unsettled_yield(vault.yield_per_token), Ok(30));
+    }
+}
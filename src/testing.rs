@@ -0,0 +1,859 @@
+//! In-process test environment for the vault and the programs that call it.
+//!
+//! [`VaultTestEnv::builder`] starts a `solana-program-test` bank running the vault
+//! natively, creates a mint, funds each user with SOL and tokens in their associated
+//! token account, and initializes a vault owned by the payer. Instructions are built
+//! with [`VaultClient`], so the vault and its users hold associated token accounts.
+//!
+//! Users are addressed by index. Transactions are paid and signed by the payer, and
+//! by the acting user for user instructions; the `assert_*` helpers panic with the
+//! expected and actual values.
+//!
+//! Events the vault emits are written to the transaction logs as `Program data:` lines,
+//! as on a validator, so [`VaultTestEnv::process_with_events`] can decode them.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    instruction::{AccountMeta, Instruction},
+    program_pack::Pack,
+    program_stubs::{set_syscall_stubs, SyscallStubs},
+    pubkey::Pubkey,
+    system_instruction,
+};
+use solana_program_test::{
+    get_runtime_environment_key, invoke_builtin_function, BanksClient, BanksClientError,
+    BanksTransactionResultWithMetadata, EbpfError, EbpfVm, InvokeContext, ProgramTest, ProgramTestBanksClientExt,
+};
+use solana_sdk::{
+    hash::Hash,
+    signature::{Keypair, Signature, Signer},
+    transaction::{Transaction, TransactionError},
+};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::state::{Account as TokenAccount, Mint};
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    sync::{Arc, Once, OnceLock},
+};
+
+use crate::{
+    client::VaultClient,
+    error::VaultError,
+    events::{decode_logs, VaultEvent},
+    instruction::{ed25519_permit_instruction, VaultInstruction, VaultMetadataArgs},
+    state::{BasketBalance, BasketState, ProgramAccount, UserBalance, VaultMetadata, VaultState},
+    utils::{derive_basket_balance_pda, derive_basket_pda, derive_basket_token_account_pda, withdraw_permit_message},
+};
+
+/// Lamports given to each user for fees and user balance rent
+const USER_LAMPORTS: u64 = 1_000_000_000;
+
+/// Builder of a [`VaultTestEnv`]
+pub struct VaultTestEnvBuilder {
+    users: usize,
+    user_funds: u64,
+    decimals: u8,
+    vault_id: u64,
+    metadata: Option<VaultMetadataArgs>,
+    initialize: bool,
+    sbf: bool,
+    program_test: ProgramTest,
+}
+
+impl VaultTestEnvBuilder {
+    /// Number of funded users (default 2)
+    pub fn users(mut self, users: usize) -> Self {
+        self.users = users;
+        self
+    }
+
+    /// Tokens minted to each user (default 1,000,000)
+    pub fn user_funds(mut self, amount: u64) -> Self {
+        self.user_funds = amount;
+        self
+    }
+
+    /// Decimals of the mint (default 6)
+    pub fn decimals(mut self, decimals: u8) -> Self {
+        self.decimals = decimals;
+        self
+    }
+
+    /// Vault id (default 0)
+    pub fn vault_id(mut self, vault_id: u64) -> Self {
+        self.vault_id = vault_id;
+        self
+    }
+
+    /// Create the vault metadata account at initialization
+    pub fn metadata(mut self, metadata: VaultMetadataArgs) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    /// Leave the vault uninitialized, e.g. to test `Initialize` itself
+    pub fn uninitialized(mut self) -> Self {
+        self.initialize = false;
+        self
+    }
+
+    /// Run the vault from its `cargo build-sbf` build rather than natively, so compute
+    /// units are metered. The build is looked up like any program-test `.so`, e.g. in
+    /// `SBF_OUT_DIR`, as are programs added through `configure` afterwards; events are
+    /// then logged by the runtime itself.
+    pub fn sbf(mut self) -> Self {
+        self.sbf = true;
+        self.program_test.prefer_bpf(true);
+        self
+    }
+
+    /// Adjust the bank before it starts, e.g. to add the program under test
+    pub fn configure(mut self, configure: impl FnOnce(&mut ProgramTest)) -> Self {
+        configure(&mut self.program_test);
+        self
+    }
+
+    /// Start the bank and create the mint, the funded users and the vault
+    pub async fn build(mut self) -> VaultTestEnv {
+        let entrypoint = if self.sbf { None } else { Some(vault_entrypoint as _) };
+        self.program_test.add_program("solana_vault_contract", crate::id(), entrypoint);
+        let (banks_client, payer, recent_blockhash) = self.program_test.start().await;
+        LogDataStubs::install();
+        let mint = Keypair::new();
+        let client = VaultClient::new(payer.pubkey(), mint.pubkey(), self.vault_id).unwrap();
+        let mut env = VaultTestEnv {
+            banks_client,
+            payer,
+            mint,
+            users: (0..self.users).map(|_| Keypair::new()).collect(),
+            client,
+            recent_blockhash,
+            sent: HashSet::new(),
+        };
+
+        let payer = env.payer.pubkey();
+        let mint = env.mint.pubkey();
+        let rent = env.banks_client.get_rent().await.unwrap();
+        let mut setup = vec![
+            system_instruction::create_account(
+                &payer,
+                &mint,
+                rent.minimum_balance(Mint::LEN),
+                Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_mint(&spl_token::id(), &mint, &payer, None, self.decimals).unwrap(),
+            env.client.create_token_account(&payer, &payer),
+        ];
+        env.process(&setup, &[&env.mint.insecure_clone()]).await.unwrap();
+
+        // A few users per transaction keeps it under the size limit
+        let users: Vec<Pubkey> = env.users.iter().map(Signer::pubkey).collect();
+        for users in users.chunks(4) {
+            setup.clear();
+            for &user in users {
+                setup.push(system_instruction::transfer(&payer, &user, USER_LAMPORTS));
+                setup.push(env.client.create_token_account(&payer, &user));
+                setup.push(
+                    spl_token::instruction::mint_to(
+                        &spl_token::id(),
+                        &mint,
+                        &env.client.token_account(&user),
+                        &payer,
+                        &[],
+                        self.user_funds,
+                    )
+                    .unwrap(),
+                );
+            }
+            env.process(&setup, &[]).await.unwrap();
+        }
+
+        if self.initialize {
+            env.initialize(self.metadata).await.unwrap();
+        }
+        env
+    }
+}
+
+/// A bank with a vault owned by the payer and funded users
+pub struct VaultTestEnv {
+    pub banks_client: BanksClient,
+    /// Fee payer and vault owner
+    pub payer: Keypair,
+    pub mint: Keypair,
+    pub users: Vec<Keypair>,
+    /// Instruction builder for the vault
+    pub client: VaultClient,
+    recent_blockhash: Hash,
+    sent: HashSet<Signature>,
+}
+
+/// A strategy registered with [`VaultTestEnv::add_strategy`]
+pub struct TestStrategy {
+    /// Strategy adapter program
+    pub adapter: Pubkey,
+    /// Token account holding the allocated funds
+    pub token_account: Pubkey,
+    /// Accounts forwarded to the adapter
+    pub adapter_accounts: Vec<AccountMeta>,
+    /// Index of the strategy in the vault state
+    pub index: u8,
+}
+
+impl VaultTestEnv {
+    /// Builder with 2 users holding 1,000,000 tokens of a 6-decimal mint
+    pub fn builder() -> VaultTestEnvBuilder {
+        let mut program_test = ProgramTest::default();
+        program_test.prefer_bpf(false);
+        VaultTestEnvBuilder {
+            users: 2,
+            user_funds: 1_000_000,
+            decimals: 6,
+            vault_id: 0,
+            metadata: None,
+            initialize: true,
+            sbf: false,
+            program_test,
+        }
+    }
+
+    /// User `index`
+    pub fn user(&self, index: usize) -> &Keypair {
+        &self.users[index]
+    }
+
+    /// Send `instructions` in one transaction, paid and signed by the payer and
+    /// signed by `signers`, with the latest blockhash. Repeating a transaction waits
+    /// for a fresh blockhash instead of being rejected as already processed.
+    pub async fn process(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), BanksClientError> {
+        let transaction = self.transaction(instructions, signers).await?;
+        self.banks_client.process_transaction(transaction).await
+    }
+
+    /// Like [`process`](Self::process), returning the result with the transaction logs and
+    /// compute units instead of failing on a transaction error
+    pub async fn process_with_metadata(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<BanksTransactionResultWithMetadata, BanksClientError> {
+        let transaction = self.transaction(instructions, signers).await?;
+        self.banks_client.process_transaction_with_metadata(transaction).await
+    }
+
+    /// Like [`process`](Self::process), returning the vault events decoded from the transaction logs
+    pub async fn process_with_events(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<Vec<VaultEvent>, BanksClientError> {
+        let processed = self.process_with_metadata(instructions, signers).await?;
+        processed.result?;
+        let logs = processed.metadata.map(|metadata| metadata.log_messages).unwrap_or_default();
+        Ok(decode_logs(&crate::id(), &logs))
+    }
+
+    /// Like [`process`](Self::process), returning the compute units the transaction consumed
+    pub async fn process_with_compute_units(
+        &mut self,
+        instructions: &[Instruction],
+        signers: &[&Keypair],
+    ) -> Result<u64, BanksClientError> {
+        let processed = self.process_with_metadata(instructions, signers).await?;
+        processed.result?;
+        Ok(processed.metadata.map_or(0, |metadata| metadata.compute_units_consumed))
+    }
+
+    async fn transaction(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<Transaction, BanksClientError> {
+        // A cached blockhash expires in long runs, so sign with the latest one
+        let latest_blockhash = self.banks_client.get_latest_blockhash().await?;
+        if latest_blockhash != self.recent_blockhash {
+            self.recent_blockhash = latest_blockhash;
+            self.sent.clear();
+        }
+        let mut all_signers = vec![&self.payer];
+        all_signers.extend(signers.iter().copied().filter(|signer| signer.pubkey() != self.payer.pubkey()));
+        let mut transaction =
+            Transaction::new_signed_with_payer(instructions, Some(&self.payer.pubkey()), &all_signers, self.recent_blockhash);
+        if !self.sent.insert(transaction.signatures[0]) {
+            self.recent_blockhash = self.banks_client.get_new_latest_blockhash(&self.recent_blockhash).await?;
+            transaction.sign(&all_signers, self.recent_blockhash);
+            self.sent.insert(transaction.signatures[0]);
+        }
+        Ok(transaction)
+    }
+
+    /// Initialize the vault, for environments built [`uninitialized`](VaultTestEnvBuilder::uninitialized)
+    pub async fn initialize(&mut self, metadata: Option<VaultMetadataArgs>) -> Result<(), BanksClientError> {
+        let instructions = self.client.initialize(metadata).unwrap();
+        self.process(&instructions, &[]).await
+    }
+
+    /// Deposit `amount` from user `user`'s token account
+    pub async fn deposit(&mut self, user: usize, amount: u64) -> Result<(), BanksClientError> {
+        let user = self.users[user].insecure_clone();
+        let instructions = self.client.deposit(&user.pubkey(), amount).unwrap();
+        self.process(&instructions, &[&user]).await
+    }
+
+    /// Withdraw `amount` of user `user`'s balance, or queue it
+    pub async fn withdraw(&mut self, user: usize, amount: u64) -> Result<(), BanksClientError> {
+        let user = self.users[user].insecure_clone();
+        let instructions = self.client.withdraw(&user.pubkey(), amount).unwrap();
+        self.process(&instructions, &[&user]).await
+    }
+
+    /// Claim user `user`'s queued withdrawal
+    pub async fn claim_withdrawal(&mut self, user: usize) -> Result<(), BanksClientError> {
+        let user = self.users[user].insecure_clone();
+        let instructions = self.client.claim_withdrawal(&user.pubkey()).unwrap();
+        self.process(&instructions, &[&user]).await
+    }
+
+    /// Owner withdraws every token in the vault
+    pub async fn withdraw_all(&mut self) -> Result<(), BanksClientError> {
+        let instructions = self.client.withdraw_all();
+        self.process(&instructions, &[]).await
+    }
+
+    /// Owner closes the vault
+    pub async fn close(&mut self) -> Result<(), BanksClientError> {
+        let instructions = self.client.close();
+        self.process(&instructions, &[]).await
+    }
+
+    /// User `user` allows user `delegate` to withdraw up to `amount` of their balance
+    pub async fn approve_delegate(&mut self, user: usize, delegate: usize, amount: u64) -> Result<(), BanksClientError> {
+        let user = self.users[user].insecure_clone();
+        let delegate = self.users[delegate].pubkey();
+        let instruction = VaultInstruction::approve_delegate(
+            &self.client.program_id(),
+            &user.pubkey(),
+            &self.client.vault_state(),
+            &self.client.user_balance(&user.pubkey()).unwrap(),
+            &delegate,
+            amount,
+        );
+        self.process(&[instruction], &[&user]).await
+    }
+
+    /// User `user` revokes their delegate
+    pub async fn revoke_delegate(&mut self, user: usize) -> Result<(), BanksClientError> {
+        let user = self.users[user].insecure_clone();
+        let instruction = VaultInstruction::revoke_delegate(
+            &self.client.program_id(),
+            &user.pubkey(),
+            &self.client.vault_state(),
+            &self.client.user_balance(&user.pubkey()).unwrap(),
+        );
+        self.process(&[instruction], &[&user]).await
+    }
+
+    /// User `delegate` withdraws `amount` of user `user`'s balance to their own token account
+    pub async fn withdraw_as_delegate(&mut self, delegate: usize, user: usize, amount: u64) -> Result<(), BanksClientError> {
+        let delegate = self.users[delegate].insecure_clone();
+        let user = self.users[user].pubkey();
+        let instruction = VaultInstruction::withdraw_as_delegate(
+            &self.client.program_id(),
+            &delegate.pubkey(),
+            &self.client.token_account(&delegate.pubkey()),
+            &self.client.vault_token_account(),
+            &self.client.vault_state(),
+            &self.client.user_balance(&user).unwrap(),
+            amount,
+        );
+        self.process(&[instruction], &[&delegate]).await
+    }
+
+    /// Ed25519 check and `WithdrawWithPermit` paying `amount` to user `user`'s token account,
+    /// signed by `signer` and relayed by the payer, so the transaction needs no other signer
+    pub fn withdraw_permit(&self, user: usize, signer: usize, amount: u64, nonce: u64, expiry: i64) -> [Instruction; 2] {
+        let user = self.users[user].pubkey();
+        let signer = &self.users[signer];
+        let destination = self.client.token_account(&user);
+        let vault_state = self.client.vault_state();
+        let message =
+            withdraw_permit_message(&self.client.program_id(), &vault_state, &destination, amount, nonce, expiry);
+        let signature: [u8; 64] = signer.sign_message(&message).as_ref().try_into().unwrap();
+        [
+            ed25519_permit_instruction(&signer.pubkey(), &signature, &message),
+            VaultInstruction::withdraw_with_permit(
+                &self.client.program_id(),
+                &self.payer.pubkey(),
+                &destination,
+                &self.client.vault_token_account(),
+                &vault_state,
+                &self.client.user_balance(&user).unwrap(),
+                amount,
+                nonce,
+                expiry,
+            ),
+        ]
+    }
+
+    /// Register strategy `adapter` with `cap`, holding its funds in a new token account of
+    /// `authority` and forwarding `adapter_accounts` to it
+    pub async fn add_strategy(
+        &mut self,
+        adapter: Pubkey,
+        authority: &Pubkey,
+        adapter_accounts: Vec<AccountMeta>,
+        cap: u64,
+    ) -> Result<TestStrategy, BanksClientError> {
+        let token_account = self.create_token_account(authority).await;
+        let (program_id, owner, vault_state) = (self.client.program_id(), self.payer.pubkey(), self.client.vault_state());
+        let instruction = VaultInstruction::add_strategy(&program_id, &owner, &vault_state, &adapter, cap);
+        self.process(&[instruction], &[]).await?;
+        let index = self.vault_state().await.active_strategies().len() as u8 - 1;
+        Ok(TestStrategy {
+            adapter,
+            token_account,
+            adapter_accounts,
+            index,
+        })
+    }
+
+    /// Owner moves `amount` of idle liquidity into `strategy`
+    pub async fn allocate(&mut self, strategy: &TestStrategy, amount: u64) -> Result<(), BanksClientError> {
+        let instruction = VaultInstruction::allocate(
+            &self.client.program_id(),
+            &self.payer.pubkey(),
+            &self.client.vault_state(),
+            &self.client.vault_token_account(),
+            &strategy.token_account,
+            &strategy.adapter,
+            &strategy.adapter_accounts,
+            strategy.index,
+            amount,
+        );
+        self.process(&[instruction], &[]).await
+    }
+
+    /// Owner pulls `amount` back from `strategy`
+    pub async fn deallocate(&mut self, strategy: &TestStrategy, amount: u64) -> Result<(), BanksClientError> {
+        let instruction = VaultInstruction::deallocate(
+            &self.client.program_id(),
+            &self.payer.pubkey(),
+            &self.client.vault_state(),
+            &self.client.vault_token_account(),
+            &strategy.token_account,
+            &strategy.adapter,
+            &strategy.adapter_accounts,
+            strategy.index,
+            amount,
+        );
+        self.process(&[instruction], &[]).await
+    }
+
+    /// Owner books the gain or loss of `strategy`
+    pub async fn harvest(&mut self, strategy: &TestStrategy) -> Result<(), BanksClientError> {
+        let instruction = VaultInstruction::harvest(
+            &self.client.program_id(),
+            &self.payer.pubkey(),
+            &self.client.vault_state(),
+            &self.client.vault_token_account(),
+            &strategy.token_account,
+            &strategy.adapter,
+            &strategy.adapter_accounts,
+            strategy.index,
+        );
+        self.process(&[instruction], &[]).await
+    }
+
+    /// Owner sets the share of total assets that must stay idle
+    pub async fn set_min_idle_bps(&mut self, min_idle_bps: u16) -> Result<(), BanksClientError> {
+        let (program_id, owner, vault_state) = (self.client.program_id(), self.payer.pubkey(), self.client.vault_state());
+        let instruction = VaultInstruction::set_min_idle_bps(&program_id, &owner, &vault_state, min_idle_bps);
+        self.process(&[instruction], &[]).await
+    }
+
+    /// Mint `amount` of the vault's mint to `token_account`, e.g. as strategy yield
+    pub async fn mint_to(&mut self, token_account: &Pubkey, amount: u64) {
+        let payer = self.payer.pubkey();
+        let instruction =
+            spl_token::instruction::mint_to(&spl_token::id(), &self.mint.pubkey(), token_account, &payer, &[], amount).unwrap();
+        self.process(&[instruction], &[]).await.unwrap();
+    }
+
+    /// Create another mint with the vault's decimals and give every user `user_funds` of it
+    /// in their associated token account
+    pub async fn create_mint(&mut self, user_funds: u64) -> Pubkey {
+        let mint = Keypair::new();
+        let payer = self.payer.pubkey();
+        let decimals = Mint::unpack(&self.banks_client.get_account(self.mint.pubkey()).await.unwrap().unwrap().data)
+            .unwrap()
+            .decimals;
+        let rent = self.banks_client.get_rent().await.unwrap();
+        let mut instructions = vec![
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                rent.minimum_balance(Mint::LEN),
+                Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_mint(&spl_token::id(), &mint.pubkey(), &payer, None, decimals).unwrap(),
+        ];
+        for user in self.users.iter().map(Signer::pubkey) {
+            instructions.push(spl_associated_token_account::instruction::create_associated_token_account_idempotent(
+                &payer,
+                &user,
+                &mint.pubkey(),
+                &spl_token::id(),
+            ));
+            instructions.push(
+                spl_token::instruction::mint_to(
+                    &spl_token::id(),
+                    &mint.pubkey(),
+                    &get_associated_token_address(&user, &mint.pubkey()),
+                    &payer,
+                    &[],
+                    user_funds,
+                )
+                .unwrap(),
+            );
+        }
+        self.process(&instructions, &[&mint]).await.unwrap();
+        mint.pubkey()
+    }
+
+    /// Basket `basket_id` of the payer
+    pub fn basket(&self, basket_id: u64) -> Pubkey {
+        derive_basket_pda(&crate::id(), &self.payer.pubkey(), basket_id).unwrap().0
+    }
+
+    /// Token account basket `basket_id` holds `mint` in
+    pub fn basket_token_account(&self, basket_id: u64, mint: &Pubkey) -> Pubkey {
+        derive_basket_token_account_pda(&crate::id(), &self.basket(basket_id), mint).unwrap().0
+    }
+
+    /// Payer creates basket `basket_id`
+    pub async fn initialize_basket(&mut self, basket_id: u64) -> Result<(), BanksClientError> {
+        let instruction =
+            VaultInstruction::initialize_basket(&crate::id(), &self.payer.pubkey(), &self.basket(basket_id), basket_id);
+        self.process(&[instruction], &[]).await
+    }
+
+    /// Payer registers `mint` in basket `basket_id`
+    pub async fn add_basket_mint(&mut self, basket_id: u64, mint: &Pubkey) -> Result<(), BanksClientError> {
+        let instruction = VaultInstruction::add_basket_mint(
+            &crate::id(),
+            &self.payer.pubkey(),
+            &self.basket(basket_id),
+            mint,
+            &self.basket_token_account(basket_id, mint),
+        );
+        self.process(&[instruction], &[]).await
+    }
+
+    /// `BasketDeposit` of `amount` of `mint` from user `user`'s associated token account
+    fn basket_deposit_instruction(&self, user: usize, basket_id: u64, mint: &Pubkey, amount: u64) -> Instruction {
+        let user = self.users[user].pubkey();
+        let basket = self.basket(basket_id);
+        VaultInstruction::basket_deposit(
+            &crate::id(),
+            &user,
+            &get_associated_token_address(&user, mint),
+            &self.basket_token_account(basket_id, mint),
+            &basket,
+            &derive_basket_balance_pda(&crate::id(), &user, &basket, mint).unwrap().0,
+            amount,
+        )
+    }
+
+    /// `BasketWithdraw` of `amount` of `mint` to user `user`'s associated token account
+    fn basket_withdraw_instruction(&self, user: usize, basket_id: u64, mint: &Pubkey, amount: u64) -> Instruction {
+        let user = self.users[user].pubkey();
+        let basket = self.basket(basket_id);
+        VaultInstruction::basket_withdraw(
+            &crate::id(),
+            &user,
+            &get_associated_token_address(&user, mint),
+            &self.basket_token_account(basket_id, mint),
+            &basket,
+            &derive_basket_balance_pda(&crate::id(), &user, &basket, mint).unwrap().0,
+            amount,
+        )
+    }
+
+    /// User `user` deposits `amount` of `mint` into basket `basket_id`
+    pub async fn basket_deposit(
+        &mut self,
+        user: usize,
+        basket_id: u64,
+        mint: &Pubkey,
+        amount: u64,
+    ) -> Result<(), BanksClientError> {
+        let instruction = self.basket_deposit_instruction(user, basket_id, mint, amount);
+        let user = self.users[user].insecure_clone();
+        self.process(&[instruction], &[&user]).await
+    }
+
+    /// User `user` withdraws `amount` of `mint` from basket `basket_id`
+    pub async fn basket_withdraw(
+        &mut self,
+        user: usize,
+        basket_id: u64,
+        mint: &Pubkey,
+        amount: u64,
+    ) -> Result<(), BanksClientError> {
+        let instruction = self.basket_withdraw_instruction(user, basket_id, mint, amount);
+        let user = self.users[user].insecure_clone();
+        self.process(&[instruction], &[&user]).await
+    }
+
+    /// Payer closes basket `basket_id`
+    pub async fn close_basket(&mut self, basket_id: u64) -> Result<(), BanksClientError> {
+        let instruction = VaultInstruction::close_basket(&crate::id(), &self.payer.pubkey(), &self.basket(basket_id));
+        self.process(&[instruction], &[]).await
+    }
+
+    /// Vault state, panicking if it does not exist or does not decode
+    pub async fn vault_state(&mut self) -> VaultState {
+        let address = self.client.vault_state();
+        let account = self.banks_client.get_account(address).await.unwrap().expect("vault state account not found");
+        VaultState::from_account_bytes(&account.data).unwrap()
+    }
+
+    /// Balance account of user `user`, if it was created
+    pub async fn user_balance(&mut self, user: usize) -> Option<UserBalance> {
+        let address = self.client.user_balance(&self.users[user].pubkey()).unwrap();
+        let account = self.banks_client.get_account(address).await.unwrap()?;
+        Some(UserBalance::from_account_bytes(&account.data).unwrap())
+    }
+
+    /// Vault metadata, panicking if it does not exist or does not decode
+    pub async fn metadata(&mut self) -> VaultMetadata {
+        let address = self.client.metadata().unwrap();
+        let account = self.banks_client.get_account(address).await.unwrap().expect("metadata account not found");
+        VaultMetadata::from_account_bytes(&account.data).unwrap()
+    }
+
+    /// Basket `basket_id`, panicking if it does not exist or does not decode
+    pub async fn basket_state(&mut self, basket_id: u64) -> BasketState {
+        let address = self.basket(basket_id);
+        let account = self.banks_client.get_account(address).await.unwrap().expect("basket account not found");
+        BasketState::from_account_bytes(&account.data).unwrap()
+    }
+
+    /// Balance of user `user` in `mint` of basket `basket_id`, panicking if it does not exist
+    pub async fn basket_balance(&mut self, user: usize, basket_id: u64, mint: &Pubkey) -> BasketBalance {
+        let address = derive_basket_balance_pda(&crate::id(), &self.users[user].pubkey(), &self.basket(basket_id), mint)
+            .unwrap()
+            .0;
+        let account = self.banks_client.get_account(address).await.unwrap().expect("basket balance account not found");
+        BasketBalance::from_account_bytes(&account.data).unwrap()
+    }
+
+    /// Create an empty token account of the vault's mint owned by `owner`, besides its associated one
+    pub async fn create_token_account(&mut self, owner: &Pubkey) -> Pubkey {
+        let account = Keypair::new();
+        let payer = self.payer.pubkey();
+        let rent = self.banks_client.get_rent().await.unwrap();
+        let instructions = [
+            system_instruction::create_account(
+                &payer,
+                &account.pubkey(),
+                rent.minimum_balance(TokenAccount::LEN),
+                TokenAccount::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_account3(&spl_token::id(), &account.pubkey(), &self.mint.pubkey(), owner)
+                .unwrap(),
+        ];
+        self.process(&instructions, &[&account]).await.unwrap();
+        account.pubkey()
+    }
+
+    /// Token amount in the token account at `address`
+    pub async fn token_account_amount(&mut self, address: &Pubkey) -> u64 {
+        let account = self.banks_client.get_account(*address).await.unwrap().expect("token account not found");
+        TokenAccount::unpack(&account.data).unwrap().amount
+    }
+
+    /// Token amount in the associated token account of `wallet`
+    pub async fn token_balance(&mut self, wallet: &Pubkey) -> u64 {
+        let address = self.client.token_account(wallet);
+        self.token_account_amount(&address).await
+    }
+
+    /// Token amount in the associated token account of `wallet` for `mint`
+    pub async fn mint_token_balance(&mut self, wallet: &Pubkey, mint: &Pubkey) -> u64 {
+        self.token_account_amount(&get_associated_token_address(wallet, mint)).await
+    }
+
+    /// Tokens held by the vault
+    pub async fn vault_token_balance(&mut self) -> u64 {
+        let vault_state = self.client.vault_state();
+        self.token_balance(&vault_state).await
+    }
+
+    /// Assert user `user`'s vault balance, treating a missing account as zero
+    pub async fn assert_user_balance(&mut self, user: usize, expected: u64) {
+        let balance = self.user_balance(user).await.map_or(0, |balance| balance.balance);
+        assert_eq!(balance, expected, "vault balance of user {}", user);
+    }
+
+    /// Assert user `user`'s token account amount
+    pub async fn assert_token_balance(&mut self, user: usize, expected: u64) {
+        let wallet = self.users[user].pubkey();
+        assert_eq!(self.token_balance(&wallet).await, expected, "token balance of user {}", user);
+    }
+
+    /// Assert the vault's `total_deposited`
+    pub async fn assert_vault_total(&mut self, expected: u64) {
+        assert_eq!(self.vault_state().await.total_deposited, expected, "vault total_deposited");
+    }
+}
+
+thread_local! {
+    /// Address of the invoke context of the vault instruction running on this thread, or 0
+    static VAULT_INVOKE_CONTEXT: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Native vault entrypoint: `processor!(crate::process_instruction)`, recording the
+/// invoke context while the instruction runs so [`LogDataStubs`] can reach its logs
+fn vault_entrypoint(vm: *mut EbpfVm<InvokeContext>, _arg0: u64, _arg1: u64, _arg2: u64, _arg3: u64, _arg4: u64) {
+    // Same pointer adjustment as `processor!`
+    let vm = unsafe {
+        &mut *((vm as *mut u64).offset(-(get_runtime_environment_key() as isize)) as *mut EbpfVm<InvokeContext>)
+    };
+    let invoke_context = &mut *vm.context_object_pointer;
+    let previous = VAULT_INVOKE_CONTEXT.replace(invoke_context as *mut InvokeContext as usize);
+    vm.program_result = invoke_builtin_function(crate::process_instruction, invoke_context)
+        .map_err(EbpfError::SyscallError)
+        .into();
+    VAULT_INVOKE_CONTEXT.set(previous);
+}
+
+/// Syscall stubs writing the vault's `sol_log_data` output to the transaction logs, where
+/// the `solana-program-test` stubs print it to stdout. Everything else is forwarded to the
+/// stubs `solana-program-test` installed.
+struct LogDataStubs {
+    inner: Arc<OnceLock<Box<dyn SyscallStubs>>>,
+}
+
+impl LogDataStubs {
+    /// Wrap the current stubs, once per process. Syscalls racing the swap wait for it.
+    fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            let inner = Arc::new(OnceLock::new());
+            let previous = set_syscall_stubs(Box::new(LogDataStubs { inner: inner.clone() }));
+            let _ = inner.set(previous);
+        });
+    }
+
+    fn inner(&self) -> &dyn SyscallStubs {
+        self.inner.wait().as_ref()
+    }
+}
+
+impl SyscallStubs for LogDataStubs {
+    fn sol_log_data(&self, fields: &[&[u8]]) {
+        let address = VAULT_INVOKE_CONTEXT.get();
+        if address == 0 {
+            return self.inner().sol_log_data(fields);
+        }
+        // SAFETY: set by `vault_entrypoint` for as long as the instruction runs on this thread
+        let invoke_context = unsafe { &*(address as *const InvokeContext) };
+        if let Some(log_collector) = invoke_context.get_log_collector() {
+            let fields: Vec<String> = fields.iter().map(|field| STANDARD.encode(field)).collect();
+            log_collector.borrow_mut().log(&format!("Program data: {}", fields.join(" ")));
+        }
+    }
+
+    fn sol_log(&self, message: &str) {
+        self.inner().sol_log(message)
+    }
+
+    fn sol_log_compute_units(&self) {
+        self.inner().sol_log_compute_units()
+    }
+
+    fn sol_remaining_compute_units(&self) -> u64 {
+        self.inner().sol_remaining_compute_units()
+    }
+
+    fn sol_invoke_signed(
+        &self,
+        instruction: &Instruction,
+        account_infos: &[AccountInfo],
+        signers_seeds: &[&[&[u8]]],
+    ) -> ProgramResult {
+        self.inner().sol_invoke_signed(instruction, account_infos, signers_seeds)
+    }
+
+    fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_clock_sysvar(var_addr)
+    }
+
+    fn sol_get_epoch_schedule_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_epoch_schedule_sysvar(var_addr)
+    }
+
+    fn sol_get_fees_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_fees_sysvar(var_addr)
+    }
+
+    fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_rent_sysvar(var_addr)
+    }
+
+    fn sol_get_epoch_rewards_sysvar(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_epoch_rewards_sysvar(var_addr)
+    }
+
+    fn sol_get_last_restart_slot(&self, var_addr: *mut u8) -> u64 {
+        self.inner().sol_get_last_restart_slot(var_addr)
+    }
+
+    fn sol_get_return_data(&self) -> Option<(Pubkey, Vec<u8>)> {
+        self.inner().sol_get_return_data()
+    }
+
+    fn sol_set_return_data(&self, data: &[u8]) {
+        self.inner().sol_set_return_data(data)
+    }
+
+    fn sol_get_processed_sibling_instruction(&self, index: usize) -> Option<Instruction> {
+        self.inner().sol_get_processed_sibling_instruction(index)
+    }
+
+    fn sol_get_stack_height(&self) -> u64 {
+        self.inner().sol_get_stack_height()
+    }
+}
+
+/// Assertions on the result of a processed transaction
+pub trait TransactionResultExt {
+    /// Assert that the transaction failed with `expected` from the vault program
+    fn assert_error(self, expected: VaultError);
+}
+
+impl TransactionResultExt for Result<(), BanksClientError> {
+    fn assert_error(self, expected: VaultError) {
+        let error = match self {
+            Ok(()) => panic!("transaction succeeded, expected {:?}", expected),
+            Err(BanksClientError::TransactionError(error))
+            | Err(BanksClientError::SimulationError { err: error, .. }) => error,
+            Err(error) => panic!("transaction was not processed: {}, expected {:?}", error, expected),
+        };
+        assert_eq!(
+            VaultError::from_transaction_error(&error).map(|(_, error)| error),
+            Some(expected),
+            "unexpected transaction error: {}",
+            error
+        );
+    }
+}
+
+impl TransactionResultExt for Result<(), TransactionError> {
+    fn assert_error(self, expected: VaultError) {
+        self.map_err(BanksClientError::TransactionError).assert_error(expected)
+    }
+}
@@ -11,16 +11,30 @@ pub const VAULT_SEED: &[u8] = b"vault";
 /// Seeds for user balance PDA derivation
 pub const USER_BALANCE_SEED: &[u8] = b"user_balance";
 
-/// Derive vault state PDA from owner and token mint
+/// Seeds for basket state PDA derivation
+pub const BASKET_SEED: &[u8] = b"basket";
+
+/// Seeds for basket token account PDA derivation
+pub const BASKET_TOKEN_SEED: &[u8] = b"basket_token";
+
+/// Seeds for basket balance PDA derivation
+pub const BASKET_BALANCE_SEED: &[u8] = b"basket_balance";
+
+/// Seeds for vault metadata PDA derivation
+pub const METADATA_SEED: &[u8] = b"metadata";
+
+/// Derive vault state PDA from owner, token mint and vault id
 pub fn derive_vault_state_pda(
     program_id: &Pubkey,
     owner: &Pubkey,
     token_mint: &Pubkey,
+    vault_id: u64,
 ) -> Result<(Pubkey, u8), ProgramError> {
     let seeds = &[
         VAULT_SEED,
         owner.as_ref(),
         token_mint.as_ref(),
+        &vault_id.to_le_bytes(),
     ];
     
     Ok(Pubkey::find_program_address(seeds, program_id))
@@ -41,26 +55,88 @@ pub fn derive_user_balance_pda(
     Ok(Pubkey::find_program_address(seeds, program_id))
 }
 
+/// Derive basket state PDA from owner and basket id
+pub fn derive_basket_pda(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    basket_id: u64,
+) -> Result<(Pubkey, u8), ProgramError> {
+    let seeds = &[
+        BASKET_SEED,
+        owner.as_ref(),
+        &basket_id.to_le_bytes(),
+    ];
+    
+    Ok(Pubkey::find_program_address(seeds, program_id))
+}
+
+/// Derive the basket's token account PDA for a mint
+pub fn derive_basket_token_account_pda(
+    program_id: &Pubkey,
+    basket: &Pubkey,
+    token_mint: &Pubkey,
+) -> Result<(Pubkey, u8), ProgramError> {
+    let seeds = &[
+        BASKET_TOKEN_SEED,
+        basket.as_ref(),
+        token_mint.as_ref(),
+    ];
+    
+    Ok(Pubkey::find_program_address(seeds, program_id))
+}
+
+/// Derive basket balance PDA from user, basket and mint
+pub fn derive_basket_balance_pda(
+    program_id: &Pubkey,
+    user: &Pubkey,
+    basket: &Pubkey,
+    token_mint: &Pubkey,
+) -> Result<(Pubkey, u8), ProgramError> {
+    let seeds = &[
+        BASKET_BALANCE_SEED,
+        user.as_ref(),
+        basket.as_ref(),
+        token_mint.as_ref(),
+    ];
+    
+    Ok(Pubkey::find_program_address(seeds, program_id))
+}
+
+/// Derive vault metadata PDA from the vault state account
+pub fn derive_metadata_pda(
+    program_id: &Pubkey,
+    vault_state: &Pubkey,
+) -> Result<(Pubkey, u8), ProgramError> {
+    let seeds = &[
+        METADATA_SEED,
+        vault_state.as_ref(),
+    ];
+    
+    Ok(Pubkey::find_program_address(seeds, program_id))
+}
+
 /// Verify vault state PDA derivation
 pub fn verify_vault_state_pda(
     program_id: &Pubkey,
     vault_state: &Pubkey,
     owner: &Pubkey,
     token_mint: &Pubkey,
+    vault_id: u64,
     bump: u8,
 ) -> Result<(), ProgramError> {
     let seeds = &[
         VAULT_SEED,
         owner.as_ref(),
         token_mint.as_ref(),
+        &vault_id.to_le_bytes(),
         &[bump],
     ];
     
     let expected_pda = Pubkey::create_program_address(seeds, program_id)
-        .map_err(|_| VaultError::InvalidInput)?;
+        .map_err(|_| VaultError::InvalidPda)?;
     
     if expected_pda != *vault_state {
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidPda.into());
     }
     
     Ok(())
@@ -82,10 +158,10 @@ pub fn verify_user_balance_pda(
     ];
     
     let expected_pda = Pubkey::create_program_address(seeds, program_id)
-        .map_err(|_| VaultError::InvalidInput)?;
+        .map_err(|_| VaultError::InvalidPda)?;
     
     if expected_pda != *user_balance {
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::InvalidPda.into());
     }
     
     Ok(())
@@ -102,11 +178,15 @@ mod tests {
         let owner = Pubkey::new_unique();
         let token_mint = Pubkey::new_unique();
         
-        let (pda, bump) = derive_vault_state_pda(&program_id, &owner, &token_mint).unwrap();
+        let (pda, bump) = derive_vault_state_pda(&program_id, &owner, &token_mint, 0).unwrap();
         
         // Verify the PDA can be recreated with the same inputs
-        let verification = verify_vault_state_pda(&program_id, &pda, &owner, &token_mint, bump);
+        let verification = verify_vault_state_pda(&program_id, &pda, &owner, &token_mint, 0, bump);
         assert!(verification.is_ok());
+
+        // A different vault id yields a different vault
+        let (other_pda, _) = derive_vault_state_pda(&program_id, &owner, &token_mint, 1).unwrap();
+        assert_ne!(pda, other_pda);
     }
 
     #[test]
@@ -129,7 +209,7 @@ mod tests {
         let token_mint = Pubkey::new_unique();
         let wrong_pda = Pubkey::new_unique();
         
-        let verification = verify_vault_state_pda(&program_id, &wrong_pda, &owner, &token_mint, 255);
+        let verification = verify_vault_state_pda(&program_id, &wrong_pda, &owner, &token_mint, 0, 255);
         assert!(verification.is_err());
     }
 
@@ -143,13 +223,42 @@ mod tests {
         let verification = verify_user_balance_pda(&program_id, &wrong_pda, &user, &vault_state, 255);
         assert!(verification.is_err());
     }
+
+    #[test]
+    fn test_parse_ed25519_permit_instruction() {
+        let signer = Pubkey::new_unique();
+        let message = withdraw_permit_message(
+            &system_program::id(),
+            &Pubkey::new_unique(),
+            &Pubkey::new_unique(),
+            1000,
+            0,
+            i64::MAX,
+        );
+
+        let ix = crate::instruction::ed25519_permit_instruction(&signer, &[7u8; 64], &message);
+        let (parsed_signer, parsed_message) = parse_ed25519_instruction_data(&ix.data).unwrap();
+        assert_eq!(parsed_signer, signer);
+        assert_eq!(parsed_message, message.as_slice());
+    }
+
+    #[test]
+    fn test_parse_ed25519_rejects_external_offsets() {
+        let signer = Pubkey::new_unique();
+        let mut ix = crate::instruction::ed25519_permit_instruction(&signer, &[7u8; 64], b"permit");
+        // Point the public key at another instruction in the transaction
+        ix.data[8..10].copy_from_slice(&0u16.to_le_bytes());
+        assert!(parse_ed25519_instruction_data(&ix.data).is_err());
+    }
 }
 
 /// Account validation utilities
 use solana_program::{
     account_info::AccountInfo,
+    ed25519_program,
     program_pack::Pack,
     system_program,
+    sysvar::instructions::{load_current_index_checked, load_instruction_at_checked},
 };
 
 /// Verify that an account is a signer
@@ -163,7 +272,7 @@ pub fn verify_signer(account: &AccountInfo) -> Result<(), ProgramError> {
 /// Verify that an account is writable
 pub fn verify_writable(account: &AccountInfo) -> Result<(), ProgramError> {
     if !account.is_writable {
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::AccountNotWritable.into());
     }
     Ok(())
 }
@@ -231,7 +340,7 @@ pub fn verify_rent_exempt(
     rent: &solana_program::sysvar::rent::Rent,
 ) -> Result<(), ProgramError> {
     if !rent.is_exempt(account.lamports(), account.data_len()) {
-        return Err(VaultError::InvalidInput.into());
+        return Err(VaultError::NotRentExempt.into());
     }
     Ok(())
 }
@@ -287,6 +396,91 @@ pub fn validate_user_accounts(
     Ok(())
 }
 
+/// Domain separator prefixed to every withdrawal permit message
+pub const PERMIT_DOMAIN: &[u8] = b"vault_withdraw_permit";
+
+/// Size of the ed25519 signature offsets header (signature count, padding and one offsets entry)
+pub const ED25519_OFFSETS_HEADER_LEN: usize = 2 + 14;
+
+/// Build the message a user signs off-chain to authorize a relayed withdrawal
+pub fn withdraw_permit_message(
+    program_id: &Pubkey,
+    vault_state: &Pubkey,
+    destination_token_account: &Pubkey,
+    amount: u64,
+    nonce: u64,
+    expiry: i64,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(PERMIT_DOMAIN.len() + 32 * 3 + 8 * 3);
+    message.extend_from_slice(PERMIT_DOMAIN);
+    message.extend_from_slice(program_id.as_ref());
+    message.extend_from_slice(vault_state.as_ref());
+    message.extend_from_slice(destination_token_account.as_ref());
+    message.extend_from_slice(&amount.to_le_bytes());
+    message.extend_from_slice(&nonce.to_le_bytes());
+    message.extend_from_slice(&expiry.to_le_bytes());
+    message
+}
+
+/// Extract the signer pubkey and message from ed25519 program instruction data.
+/// Only a single signature whose data lives inside the same instruction is accepted.
+pub fn parse_ed25519_instruction_data(data: &[u8]) -> Result<(Pubkey, &[u8]), ProgramError> {
+    if data.len() < ED25519_OFFSETS_HEADER_LEN || data[0] != 1 {
+        return Err(VaultError::InvalidPermit.into());
+    }
+
+    let read_u16 = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+    let signature_instruction_index = read_u16(4);
+    let public_key_offset = read_u16(6) as usize;
+    let public_key_instruction_index = read_u16(8);
+    let message_data_offset = read_u16(10) as usize;
+    let message_data_size = read_u16(12) as usize;
+    let message_instruction_index = read_u16(14);
+
+    // Offsets pointing into other instructions could verify data we never see
+    if signature_instruction_index != u16::MAX
+        || public_key_instruction_index != u16::MAX
+        || message_instruction_index != u16::MAX
+    {
+        return Err(VaultError::InvalidPermit.into());
+    }
+
+    let public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(VaultError::InvalidPermit)?;
+    let message = data
+        .get(message_data_offset..message_data_offset + message_data_size)
+        .ok_or(VaultError::InvalidPermit)?;
+
+    let public_key = Pubkey::try_from(public_key).map_err(|_| VaultError::InvalidPermit)?;
+    Ok((public_key, message))
+}
+
+/// Verify that the instruction preceding the current one is an ed25519 signature
+/// verification of `expected_message` by `expected_signer`
+pub fn verify_ed25519_permit(
+    instructions_sysvar: &AccountInfo,
+    expected_signer: &Pubkey,
+    expected_message: &[u8],
+) -> Result<(), ProgramError> {
+    let current_index = load_current_index_checked(instructions_sysvar)?;
+    if current_index == 0 {
+        return Err(VaultError::InvalidPermit.into());
+    }
+
+    let ed25519_ix = load_instruction_at_checked(current_index as usize - 1, instructions_sysvar)?;
+    if ed25519_ix.program_id != ed25519_program::id() {
+        return Err(VaultError::InvalidPermit.into());
+    }
+
+    let (signer, message) = parse_ed25519_instruction_data(&ed25519_ix.data)?;
+    if signer != *expected_signer || message != expected_message {
+        return Err(VaultError::InvalidPermit.into());
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod validation_tests {
     use super::*;
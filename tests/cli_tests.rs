@@ -0,0 +1,156 @@
+use solana_program::{instruction::Instruction, program_pack::Pack, pubkey::Pubkey, system_instruction};
+use solana_program_test::*;
+use solana_sdk::{
+    signature::{Keypair, Signature, Signer},
+    transaction::Transaction,
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+use solana_vault_contract::{
+    cli::{parse_args, run, CliError, Command, Output, OutputFormat, VaultBackend},
+    VaultError,
+};
+
+/// Runs CLI commands against program-test instead of an RPC node
+struct BanksBackend(BanksClient);
+
+impl VaultBackend for BanksBackend {
+    async fn account_data(&mut self, address: &Pubkey) -> Result<Option<Vec<u8>>, CliError> {
+        let account = self.0.get_account(*address).await.map_err(|e| CliError::Rpc(e.to_string()))?;
+        Ok(account.map(|account| account.data))
+    }
+
+    async fn send(&mut self, instructions: &[Instruction], signer: &Keypair) -> Result<Signature, CliError> {
+        let recent_blockhash = self.0.get_latest_blockhash().await.map_err(|e| CliError::Rpc(e.to_string()))?;
+        let transaction =
+            Transaction::new_signed_with_payer(instructions, Some(&signer.pubkey()), &[signer], recent_blockhash);
+        let signature = transaction.signatures[0];
+        self.0
+            .process_transaction(transaction)
+            .await
+            .map_err(|e| match e {
+                BanksClientError::TransactionError(error) => CliError::Transaction(error),
+                e => CliError::Rpc(e.to_string()),
+            })?;
+        Ok(signature)
+    }
+}
+
+fn create_program_test() -> ProgramTest {
+    let mut program_test = ProgramTest::new(
+        "solana_vault_contract",
+        solana_vault_contract::id(),
+        processor!(solana_vault_contract::process_instruction),
+    );
+    program_test.prefer_bpf(false);
+    program_test
+}
+
+/// Create a mint and fund the payer's associated token account with `amount`
+async fn create_funded_mint(banks_client: &mut BanksClient, payer: &Keypair, amount: u64) -> Pubkey {
+    let rent = banks_client.get_rent().await.unwrap();
+    let mint = Keypair::new();
+    let payer_ata = spl_associated_token_account::get_associated_token_address(&payer.pubkey(), &mint.pubkey());
+    let instructions = [
+        system_instruction::create_account(
+            &payer.pubkey(),
+            &mint.pubkey(),
+            rent.minimum_balance(Mint::LEN),
+            Mint::LEN as u64,
+            &spl_token::id(),
+        ),
+        spl_token::instruction::initialize_mint(&spl_token::id(), &mint.pubkey(), &payer.pubkey(), None, 6).unwrap(),
+        spl_associated_token_account::instruction::create_associated_token_account(
+            &payer.pubkey(),
+            &payer.pubkey(),
+            &mint.pubkey(),
+            &spl_token::id(),
+        ),
+        spl_token::instruction::mint_to(&spl_token::id(), &mint.pubkey(), &payer_ata, &payer.pubkey(), &[], amount)
+            .unwrap(),
+    ];
+    let recent_blockhash = banks_client.get_latest_blockhash().await.unwrap();
+    let transaction =
+        Transaction::new_signed_with_payer(&instructions, Some(&payer.pubkey()), &[payer, &mint], recent_blockhash);
+    banks_client.process_transaction(transaction).await.unwrap();
+    mint.pubkey()
+}
+
+async fn token_balance(backend: &mut BanksBackend, address: &Pubkey) -> u64 {
+    let data = backend.account_data(address).await.unwrap().unwrap();
+    TokenAccount::unpack(&data).unwrap().amount
+}
+
+/// Parse `args` as typed on the command line and run the command
+async fn vault_cli(backend: &mut BanksBackend, signer: &Keypair, args: &str) -> Result<Output, CliError> {
+    let (config, command) = parse_args(std::iter::once("vault-cli").chain(args.split_whitespace()))?;
+    run(backend, &config, signer, command).await
+}
+
+#[tokio::test]
+async fn test_cli_vault_lifecycle() {
+    let (mut banks_client, payer, _) = create_program_test().start().await;
+    let mint = create_funded_mint(&mut banks_client, &payer, 1_000).await;
+    let payer_ata = spl_associated_token_account::get_associated_token_address(&payer.pubkey(), &mint);
+    let mut backend = BanksBackend(banks_client);
+
+    let output = vault_cli(&mut backend, &payer, &format!("init --mint {}", mint)).await.unwrap();
+    let Output::Transaction { command, vault, .. } = output else { panic!("init should send a transaction") };
+    assert_eq!(command, "init");
+
+    vault_cli(&mut backend, &payer, &format!("deposit --mint {} 600", mint)).await.unwrap();
+    vault_cli(&mut backend, &payer, &format!("withdraw --mint {} 200", mint)).await.unwrap();
+    assert_eq!(token_balance(&mut backend, &payer_ata).await, 600);
+
+    let output = vault_cli(&mut backend, &payer, &format!("show-vault --mint {} --output json", mint)).await.unwrap();
+    let json = output.to_json();
+    assert_eq!(json["address"], vault.to_string());
+    assert_eq!(json["total_deposited"], 400);
+    assert_eq!(json["is_closed"], false);
+    assert!(output.render(OutputFormat::Human).contains("Total deposited: 400"));
+
+    let output = vault_cli(&mut backend, &payer, &format!("show-balance --mint {}", mint)).await.unwrap();
+    let Output::Account { account, .. } = &output else { panic!("show-balance should decode the balance") };
+    let balance = account.as_user_balance().unwrap();
+    assert_eq!(balance.user, payer.pubkey());
+    assert_eq!(balance.balance, 400);
+
+    // Program errors come back decoded
+    let result = vault_cli(&mut backend, &payer, &format!("withdraw --mint {} 500", mint)).await;
+    let Err(CliError::Vault { error, .. }) = result else { panic!("expected a vault error, got {:?}", result) };
+    assert_eq!(error, VaultError::InsufficientFunds);
+
+    vault_cli(&mut backend, &payer, &format!("withdraw-all --mint {}", mint)).await.unwrap();
+    assert_eq!(token_balance(&mut backend, &payer_ata).await, 1_000);
+    vault_cli(&mut backend, &payer, &format!("close --mint {}", mint)).await.unwrap();
+
+    let output = vault_cli(&mut backend, &payer, &format!("show-vault --mint {}", mint)).await.unwrap();
+    assert!(output.render(OutputFormat::Human).contains("Status:          closed"));
+
+    // A vault id that was never initialized is reported as missing
+    let result = vault_cli(&mut backend, &payer, &format!("show-vault --mint {} --vault-id 9", mint)).await;
+    assert!(matches!(result, Err(CliError::AccountNotFound(_))));
+}
+
+#[test]
+fn test_cli_parses_global_options() {
+    let mint = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let args = format!(
+        "vault-cli --url http://localhost:9000 --keypair /tmp/ops.json show-balance --mint {} --owner {} --vault-id 3 --output json",
+        mint, owner
+    );
+    let (config, command) = parse_args(args.split_whitespace()).unwrap();
+    assert_eq!(config.rpc_url, "http://localhost:9000");
+    assert_eq!(config.keypair_path, "/tmp/ops.json");
+    assert_eq!(config.output, OutputFormat::Json);
+    assert_eq!(config.program_id, solana_vault_contract::id());
+    let Command::ShowBalance { vault, user } = command else { panic!("expected show-balance") };
+    assert_eq!(vault.mint, mint);
+    assert_eq!(vault.owner, Some(owner));
+    assert_eq!(vault.vault_id, 3);
+    assert_eq!(user, None);
+
+    assert!(parse_args(["vault-cli", "deposit", "--mint", "not-a-key", "5"]).is_err());
+    assert!(parse_args(["vault-cli", "withdraw", "--mint", &mint.to_string()]).is_err());
+}
@@ -0,0 +1,309 @@
+//! Generates the Shank-style JSON IDL in `idl/solana_vault_contract.json` from the
+//! sources and checks the checked-in copy is current.
+//!
+//! Instructions, their account lists and arguments come from `VaultInstruction` and
+//! its "Accounts expected" doc comments, account and type layouts from `state.rs`
+//! and `instruction.rs`, and errors from `VaultError`. Discriminators, versions and
+//! sizes are taken from the compiled `ProgramAccount` impls.
+//!
+//! Regenerate with `UPDATE_IDL=1 cargo test --test idl`.
+
+use serde_json::{json, Value};
+use solana_vault_contract::state::{
+    BasketBalance, BasketState, ProgramAccount, UserBalance, VaultMetadata, VaultState, MAX_BASKET_MINTS,
+    MAX_METADATA_NAME_LEN, MAX_METADATA_URI_LEN, MAX_STRATEGIES, USER_BALANCE_RESERVED, VAULT_METADATA_RESERVED,
+    VAULT_STATE_RESERVED,
+};
+
+const IDL_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/idl/solana_vault_contract.json");
+
+fn source(path: &str) -> String {
+    std::fs::read_to_string(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), path)).unwrap()
+}
+
+/// Lines of the body of `header` (e.g. `pub enum VaultError {`) up to its closing brace
+fn block<'a>(source: &'a str, header: &str) -> Vec<&'a str> {
+    source
+        .lines()
+        .skip_while(|line| !line.starts_with(header))
+        .skip(1)
+        .take_while(|line| *line != "}")
+        .collect()
+}
+
+/// `vault_id` -> `vaultId`, `Vault state` -> `vaultState`
+fn camel_case(words: &str) -> String {
+    let mut result = String::new();
+    for (i, word) in words.split(['_', ' ']).filter(|w| !w.is_empty()).enumerate() {
+        let lower = word.to_lowercase();
+        if i == 0 {
+            result.push_str(&lower);
+        } else {
+            let mut chars = lower.chars();
+            result.extend(chars.next().map(|c| c.to_ascii_uppercase()));
+            result.push_str(chars.as_str());
+        }
+    }
+    result
+}
+
+fn array_len(name: &str) -> usize {
+    match name {
+        "MAX_STRATEGIES" => MAX_STRATEGIES,
+        "MAX_BASKET_MINTS" => MAX_BASKET_MINTS,
+        "VAULT_STATE_RESERVED" => VAULT_STATE_RESERVED,
+        "USER_BALANCE_RESERVED" => USER_BALANCE_RESERVED,
+        "MAX_METADATA_NAME_LEN" => MAX_METADATA_NAME_LEN,
+        "MAX_METADATA_URI_LEN" => MAX_METADATA_URI_LEN,
+        "VAULT_METADATA_RESERVED" => VAULT_METADATA_RESERVED,
+        literal => literal.parse().unwrap_or_else(|_| panic!("Unknown array length {}", literal)),
+    }
+}
+
+/// Rust type to IDL type
+fn idl_type(ty: &str) -> Value {
+    let ty = ty.trim();
+    if let Some(inner) = ty.strip_prefix("Option<").and_then(|t| t.strip_suffix('>')) {
+        return json!({ "option": idl_type(inner) });
+    }
+    if let Some(inner) = ty.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        let (element, len) = inner.split_once(';').unwrap();
+        return json!({ "array": [idl_type(element), array_len(len.trim())] });
+    }
+    match ty {
+        "Pubkey" => json!("publicKey"),
+        "String" => json!("string"),
+        "u8" | "u16" | "u32" | "u64" | "u128" | "i64" | "bool" => json!(ty),
+        defined => json!({ "defined": defined }),
+    }
+}
+
+/// Fields of `pub struct name`, with their doc comments
+fn struct_fields(source: &str, name: &str) -> Vec<Value> {
+    let mut fields = Vec::new();
+    let mut docs = Vec::new();
+    for line in block(source, &format!("pub struct {} {{", name)) {
+        let line = line.trim();
+        if let Some(doc) = line.strip_prefix("///") {
+            docs.push(doc.trim().to_string());
+        } else if let Some(field) = line.strip_prefix("pub ") {
+            let (field_name, ty) = field.trim_end_matches(',').split_once(':').unwrap();
+            fields.push(json!({
+                "name": camel_case(field_name),
+                "type": idl_type(ty),
+                "docs": std::mem::take(&mut docs),
+            }));
+        }
+    }
+    fields
+}
+
+/// `[signer, writable] Vault owner` -> IDL account
+fn idl_account(flags: &str, description: &str) -> Value {
+    let flags: Vec<&str> = flags.split(',').map(str::trim).collect();
+    let mut name = description.split(['(', ',']).next().unwrap().trim();
+    if let Some(index) = name.find(" account") {
+        name = &name[..index];
+    }
+    let mut account = json!({
+        "name": camel_case(name),
+        "isMut": flags.contains(&"writable"),
+        "isSigner": flags.contains(&"signer"),
+        "docs": [description],
+    });
+    if description.contains("only when") {
+        account["isOptional"] = json!(true);
+    }
+    account
+}
+
+fn instructions(source: &str) -> Vec<Value> {
+    let mut instructions: Vec<Value> = Vec::new();
+    let mut docs: Vec<String> = Vec::new();
+    for line in block(source, "pub enum VaultInstruction {") {
+        let line = line.trim();
+        if let Some(doc) = line.strip_prefix("///") {
+            docs.push(doc.trim().to_string());
+            continue;
+        }
+        if line.is_empty() {
+            continue;
+        }
+
+        let (name, fields) = match line.split_once('{') {
+            Some((name, fields)) => (name.trim(), fields.trim_end_matches(',').trim().trim_end_matches('}')),
+            None => (line.trim_end_matches(','), ""),
+        };
+        let args: Vec<Value> = fields
+            .split(',')
+            .filter(|field| !field.trim().is_empty())
+            .map(|field| {
+                let (arg, ty) = field.split_once(':').unwrap();
+                json!({ "name": camel_case(arg.trim()), "type": idl_type(ty) })
+            })
+            .collect();
+
+        let split = docs.iter().position(|doc| doc.starts_with("Accounts expected:")).unwrap_or_else(|| {
+            panic!("{} has no \"Accounts expected:\" doc", name)
+        });
+        let description: Vec<&String> = docs[..split].iter().filter(|doc| !doc.is_empty()).collect();
+        let mut instruction = json!({
+            "name": camel_case(&name.chars().enumerate().fold(String::new(), |mut words, (i, c)| {
+                if i > 0 && c.is_uppercase() {
+                    words.push(' ');
+                }
+                words.push(c);
+                words
+            })),
+            "docs": description,
+            "args": args,
+            "discriminant": { "type": "u8", "value": instructions.len() },
+        });
+
+        let mut accounts = Vec::new();
+        if let Some(other) = docs[split].strip_prefix("Accounts expected: same as `") {
+            let other = other.trim_end_matches('`');
+            let other = instructions
+                .iter()
+                .find(|ix| ix["name"] == camel_case(other))
+                .unwrap_or_else(|| panic!("{} refers to unknown instruction {}", name, other));
+            accounts = other["accounts"].as_array().unwrap().clone();
+            instruction["remainingAccounts"] = other["remainingAccounts"].clone();
+        }
+        for doc in &docs[split + 1..] {
+            let Some((_, rest)) = doc.split_once(". [") else { continue };
+            let (flags, description) = rest.split_once("] ").unwrap();
+            if description.starts_with("Any additional accounts") {
+                instruction["remainingAccounts"] = json!(description);
+            } else {
+                accounts.push(idl_account(flags, description));
+            }
+        }
+        instruction["accounts"] = json!(accounts);
+        if instruction["remainingAccounts"].is_null() {
+            instruction.as_object_mut().unwrap().remove("remainingAccounts");
+        }
+
+        instructions.push(instruction);
+        docs.clear();
+    }
+    instructions
+}
+
+fn program_account<T: ProgramAccount>(state: &str, name: &str) -> Value {
+    json!({
+        "name": name,
+        "discriminator": T::DISCRIMINATOR.to_vec(),
+        "version": T::VERSION,
+        "size": T::SIZE,
+        "type": { "kind": "struct", "fields": struct_fields(state, name) },
+    })
+}
+
+fn errors(source: &str) -> Vec<Value> {
+    let mut errors = Vec::new();
+    let mut message = None;
+    for line in block(source, "pub enum VaultError {") {
+        let line = line.trim();
+        if let Some(msg) = line.strip_prefix("#[error(\"").and_then(|l| l.strip_suffix("\")]")) {
+            message = Some(msg.to_string());
+        } else if !line.is_empty() && !line.starts_with("//") {
+            let (name, code) = line.trim_end_matches(',').split_once(" = ").unwrap();
+            errors.push(json!({
+                "code": code.parse::<u32>().unwrap(),
+                "name": name,
+                "msg": message.take().unwrap(),
+            }));
+        }
+    }
+    errors
+}
+
+fn generate_idl() -> Value {
+    let instruction_source = source("src/instruction.rs");
+    let state = source("src/state.rs");
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "name": "solana_vault_contract",
+        "instructions": instructions(&instruction_source),
+        "accounts": [
+            program_account::<VaultState>(&state, "VaultState"),
+            program_account::<UserBalance>(&state, "UserBalance"),
+            program_account::<BasketState>(&state, "BasketState"),
+            program_account::<BasketBalance>(&state, "BasketBalance"),
+            program_account::<VaultMetadata>(&state, "VaultMetadata"),
+        ],
+        "types": [
+            {
+                "name": "StrategyAllocation",
+                "type": { "kind": "struct", "fields": struct_fields(&state, "StrategyAllocation") },
+            },
+            {
+                "name": "VaultMetadataArgs",
+                "type": { "kind": "struct", "fields": struct_fields(&instruction_source, "VaultMetadataArgs") },
+            },
+        ],
+        "errors": errors(&source("src/error.rs")),
+        "metadata": {
+            "origin": "shank",
+            "address": solana_vault_contract::id().to_string(),
+            "accountHeader": "Every account starts with its 8-byte discriminator and a version byte, followed by the Borsh-encoded fields",
+        },
+    })
+}
+
+#[test]
+fn test_idl_is_up_to_date() {
+    let generated = serde_json::to_string_pretty(&generate_idl()).unwrap() + "\n";
+    if std::env::var_os("UPDATE_IDL").is_some() {
+        std::fs::create_dir_all(std::path::Path::new(IDL_PATH).parent().unwrap()).unwrap();
+        std::fs::write(IDL_PATH, &generated).unwrap();
+    }
+    let checked_in = std::fs::read_to_string(IDL_PATH).unwrap_or_default();
+    assert!(
+        checked_in == generated,
+        "{} is out of date; regenerate it with `UPDATE_IDL=1 cargo test --test idl`",
+        IDL_PATH
+    );
+}
+
+#[test]
+fn test_idl_matches_instruction_encoding() {
+    use borsh::BorshSerialize;
+    use solana_vault_contract::instruction::VaultInstruction;
+
+    let idl = generate_idl();
+    let instructions = idl["instructions"].as_array().unwrap();
+    let expected = [
+        (VaultInstruction::Deposit { amount: 1 }, "deposit"),
+        (VaultInstruction::Close, "close"),
+        (VaultInstruction::SetMinIdleBps { min_idle_bps: 1 }, "setMinIdleBps"),
+        (
+            VaultInstruction::UpdateMetadata {
+                metadata: solana_vault_contract::instruction::VaultMetadataArgs { name: "a".into(), uri: String::new() },
+            },
+            "updateMetadata",
+        ),
+        (VaultInstruction::CloseBasket, "closeBasket"),
+    ];
+    for (instruction, name) in expected {
+        let discriminant = instruction.try_to_vec().unwrap()[0];
+        assert_eq!(instructions[discriminant as usize]["name"], name);
+    }
+
+    let deposit = &instructions[1];
+    assert_eq!(deposit["accounts"].as_array().unwrap().len(), 7);
+    assert_eq!(deposit["accounts"][0], json!({
+        "name": "user",
+        "isMut": true,
+        "isSigner": true,
+        "docs": ["User account"],
+    }));
+    use solana_vault_contract::VaultError;
+    for error in idl["errors"].as_array().unwrap() {
+        let vault_error = VaultError::from_code(error["code"].as_u64().unwrap() as u32).unwrap();
+        assert_eq!(format!("{:?}", vault_error), error["name"]);
+        assert_eq!(vault_error.to_string(), error["msg"]);
+    }
+    assert_eq!(idl["errors"].as_array().unwrap().len(), VaultError::InsufficientLiquidity as usize + 1);
+}
@@ -0,0 +1,198 @@
+//! Property tests of the vault's accounting.
+//!
+//! Random sequences of deposits, withdrawals and claims across several users run
+//! against the program, optionally ending with the owner sweeping the vault with
+//! `WithdrawAll` and/or `Close`. After every step we check that:
+//!
+//! - until the owner sweeps the vault, the depositors' ledger (balance plus pending
+//!   withdrawal) equals `total_deposited` and the vault token account holds at least
+//!   `total_deposited`
+//! - a sweep sends every vault token to the owner and leaves each user's balance and
+//!   pending withdrawal exactly as it was; `WithdrawAll` resets `total_deposited`,
+//!   `Close` leaves it as it was
+//! - tokens are conserved between the users, the vault and the owner
+//! - nothing succeeds once the vault is closed
+//!
+//! Every case starts a fresh [`VaultTestEnv`], so the default case count is low;
+//! raise it with `PROPTEST_CASES`.
+
+use proptest::prelude::*;
+use solana_program_test::BanksClientError;
+use solana_sdk::signature::Signer;
+
+use solana_vault_contract::{testing::VaultTestEnv, VaultError};
+
+const USERS: usize = 4;
+const USER_FUNDS: u64 = 1_000;
+const MAX_STEPS: usize = 24;
+const DEFAULT_CASES: u32 = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Deposit { user: usize, amount: u64 },
+    Withdraw { user: usize, amount: u64 },
+    ClaimWithdrawal { user: usize },
+    WithdrawAll,
+    Close,
+}
+
+fn user_step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        4 => (0..USERS, 0..=USER_FUNDS / 2).prop_map(|(user, amount)| Step::Deposit { user, amount }),
+        4 => (0..USERS, 0..=USER_FUNDS / 2).prop_map(|(user, amount)| Step::Withdraw { user, amount }),
+        1 => (0..USERS).prop_map(|user| Step::ClaimWithdrawal { user }),
+    ]
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        8 => user_step(),
+        1 => Just(Step::WithdrawAll),
+        1 => Just(Step::Close),
+    ]
+}
+
+/// User steps, then optionally the owner's sweeps; once closed, any steps, all of
+/// which must fail. The ledger no longer backs the users' claims after a sweep, so
+/// nothing but a close follows one.
+fn steps() -> impl Strategy<Value = Vec<Step>> {
+    let sweeps = prop_oneof![
+        Just(vec![]),
+        Just(vec![Step::WithdrawAll]),
+        Just(vec![Step::Close]),
+        Just(vec![Step::WithdrawAll, Step::Close]),
+    ];
+    (prop::collection::vec(user_step(), 1..=MAX_STEPS), sweeps, prop::collection::vec(step(), 0..=4)).prop_map(
+        |(mut steps, sweeps, after_close)| {
+            let closes = sweeps.contains(&Step::Close);
+            steps.extend(sweeps);
+            if closes {
+                steps.extend(after_close);
+            }
+            steps
+        },
+    )
+}
+
+async fn run(env: &mut VaultTestEnv, step: &Step) -> Result<(), BanksClientError> {
+    match *step {
+        Step::Deposit { user, amount } => env.deposit(user, amount).await,
+        Step::Withdraw { user, amount } => env.withdraw(user, amount).await,
+        Step::ClaimWithdrawal { user } => env.claim_withdrawal(user).await,
+        Step::WithdrawAll => env.withdraw_all().await,
+        Step::Close => env.close().await,
+    }
+}
+
+/// Each user's balance and pending withdrawal
+async fn claims(env: &mut VaultTestEnv) -> Vec<(u64, u64)> {
+    let mut claims = Vec::with_capacity(USERS);
+    for user in 0..USERS {
+        let claim = env.user_balance(user).await.map(|balance| (balance.balance, balance.pending_withdrawal));
+        claims.push(claim.unwrap_or_default());
+    }
+    claims
+}
+
+/// Sum of the users' balances and pending withdrawals
+fn ledger(claims: &[(u64, u64)]) -> u128 {
+    claims.iter().map(|&(balance, pending)| balance as u128 + pending as u128).sum()
+}
+
+/// Tokens held by the users, the vault and the owner
+async fn circulating_tokens(env: &mut VaultTestEnv) -> u64 {
+    let owner = env.payer.pubkey();
+    let mut total = env.vault_token_balance().await + env.token_balance(&owner).await;
+    for user in 0..USERS {
+        let user = env.user(user).pubkey();
+        total += env.token_balance(&user).await;
+    }
+    total
+}
+
+async fn check_invariants(steps: Vec<Step>) -> Result<(), TestCaseError> {
+    let mut env = VaultTestEnv::builder().users(USERS).user_funds(USER_FUNDS).build().await;
+    let owner = env.payer.pubkey();
+    // The users' claims as the owner swept the vault
+    let mut swept: Option<Vec<(u64, u64)>> = None;
+    let mut closed = false;
+
+    for (i, step) in steps.iter().enumerate() {
+        let before = env.vault_state().await;
+        let vault_tokens_before = env.vault_token_balance().await;
+        let owner_tokens_before = env.token_balance(&owner).await;
+        let claims_before = claims(&mut env).await;
+        let result = run(&mut env, step).await;
+
+        if closed {
+            let Err(BanksClientError::TransactionError(error)) = &result else {
+                return Err(TestCaseError::fail(format!("step {} ({:?}) on a closed vault: {:?}", i, step, result)));
+            };
+            let error = VaultError::from_transaction_error(error).map(|(_, error)| error);
+            prop_assert!(
+                matches!(error, Some(VaultError::VaultClosed | VaultError::ZeroAmount)),
+                "step {} ({:?}) on a closed vault failed with {:?}",
+                i,
+                step,
+                error
+            );
+        }
+
+        let vault_state = env.vault_state().await;
+        let vault_tokens = env.vault_token_balance().await;
+        let claims = claims(&mut env).await;
+        match step {
+            Step::WithdrawAll | Step::Close if !closed => {
+                prop_assert!(result.is_ok(), "step {} ({:?}) failed: {:?}", i, step, result);
+                prop_assert_eq!(vault_tokens, 0);
+                prop_assert_eq!(env.token_balance(&owner).await, owner_tokens_before + vault_tokens_before);
+                prop_assert_eq!(&claims, &claims_before, "claims changed by step {} ({:?})", i, step);
+                let total_deposited = if *step == Step::WithdrawAll { 0 } else { before.total_deposited };
+                prop_assert_eq!(vault_state.total_deposited, total_deposited);
+                swept.get_or_insert(claims_before);
+                closed |= *step == Step::Close;
+            }
+            _ => match &swept {
+                Some(swept) => {
+                    prop_assert_eq!(&claims, swept, "claims changed by step {} ({:?})", i, step);
+                    prop_assert_eq!(vault_tokens, 0);
+                }
+                None => {
+                    prop_assert_eq!(
+                        ledger(&claims),
+                        vault_state.total_deposited as u128,
+                        "ledger mismatch after step {} ({:?}, {:?})",
+                        i,
+                        step,
+                        result
+                    );
+                    prop_assert!(
+                        vault_tokens >= vault_state.total_deposited,
+                        "vault holds {} tokens but owes {} after step {} ({:?})",
+                        vault_tokens,
+                        vault_state.total_deposited,
+                        i,
+                        step
+                    );
+                }
+            },
+        }
+        prop_assert_eq!(circulating_tokens(&mut env).await, USERS as u64 * USER_FUNDS);
+        prop_assert_eq!(vault_state.is_closed, closed);
+    }
+    Ok(())
+}
+
+fn cases() -> u32 {
+    std::env::var("PROPTEST_CASES").ok().and_then(|cases| cases.parse().ok()).unwrap_or(DEFAULT_CASES)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig { cases: cases(), ..ProptestConfig::default() })]
+
+    #[test]
+    fn test_accounting_invariants(steps in steps()) {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(check_invariants(steps))?;
+    }
+}
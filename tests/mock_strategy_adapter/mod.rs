@@ -0,0 +1,78 @@
+//! Mock strategy adapter used by the integration tests.
+//!
+//! Allocated tokens sit in a strategy token account owned by the adapter's
+//! authority PDA, which must be passed as the first additional account.
+//! The reported balance is simply that token account's amount.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke_signed, set_return_data},
+    program_error::ProgramError,
+    program_pack::Pack,
+    pubkey::Pubkey,
+};
+use spl_token::state::Account as TokenAccount;
+
+use solana_vault_contract::instruction::StrategyAdapterInstruction;
+
+/// Seed of the PDA that owns the strategy token account
+pub const AUTHORITY_SEED: &[u8] = b"authority";
+
+/// Derive the adapter authority PDA
+pub fn authority(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[AUTHORITY_SEED], program_id)
+}
+
+/// Accounts: vault state (signer), vault token, strategy token, token program, authority PDA
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let instruction = StrategyAdapterInstruction::unpack(data)?;
+    let [vault_state_info, vault_token_info, strategy_token_info, token_program_info, authority_info, ..] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    if !vault_state_info.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    match instruction {
+        StrategyAdapterInstruction::Deposit { amount } => {
+            msg!("MockAdapter: Received {}", amount);
+            Ok(())
+        }
+        StrategyAdapterInstruction::Withdraw { amount } => {
+            let (authority_pda, bump) = authority(program_id);
+            if authority_pda != *authority_info.key {
+                return Err(ProgramError::InvalidSeeds);
+            }
+            let transfer_ix = spl_token::instruction::transfer(
+                &spl_token::id(),
+                strategy_token_info.key,
+                vault_token_info.key,
+                authority_info.key,
+                &[],
+                amount,
+            )?;
+            invoke_signed(
+                &transfer_ix,
+                &[
+                    strategy_token_info.clone(),
+                    vault_token_info.clone(),
+                    authority_info.clone(),
+                    token_program_info.clone(),
+                ],
+                &[&[AUTHORITY_SEED, &[bump]]],
+            )
+        }
+        StrategyAdapterInstruction::ReportBalance => {
+            let strategy_token = TokenAccount::unpack(&strategy_token_info.try_borrow_data()?)?;
+            set_return_data(&strategy_token.amount.to_le_bytes());
+            Ok(())
+        }
+    }
+}
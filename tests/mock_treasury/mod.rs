@@ -0,0 +1,82 @@
+//! Mock DAO treasury used by the integration tests.
+//!
+//! The treasury PDA holds a token account and deposits into / withdraws from the
+//! vault through `solana_vault_contract::cpi`, signing with its seeds.
+//! Instruction data: tag byte (0 = deposit, 1 = withdraw) followed by the u64 amount.
+
+use solana_program::{
+    account_info::AccountInfo,
+    entrypoint::ProgramResult,
+    program_error::ProgramError,
+    pubkey::Pubkey,
+};
+
+use solana_vault_contract::cpi;
+
+/// Seed of the treasury PDA
+pub const TREASURY_SEED: &[u8] = b"treasury";
+
+/// Derive the treasury PDA
+pub fn treasury(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TREASURY_SEED], program_id)
+}
+
+/// Instruction data for a deposit (`0`) or withdrawal (`1`) of `amount`
+pub fn instruction_data(tag: u8, amount: u64) -> Vec<u8> {
+    let mut data = vec![tag];
+    data.extend_from_slice(&amount.to_le_bytes());
+    data
+}
+
+/// Accounts: treasury PDA, treasury token, vault token, vault state, treasury balance,
+/// token program, system program, vault program
+pub fn process_instruction(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+) -> ProgramResult {
+    let [treasury_info, treasury_token_info, vault_token_info, vault_state_info, balance_info, token_program_info, system_program_info, vault_program_info, ..] =
+        accounts
+    else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+    let (&tag, amount) = data.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+    let amount = u64::from_le_bytes(amount.try_into().map_err(|_| ProgramError::InvalidInstructionData)?);
+
+    let (treasury_pda, bump) = treasury(program_id);
+    if treasury_pda != *treasury_info.key {
+        return Err(ProgramError::InvalidSeeds);
+    }
+    let signer_seeds: &[&[&[u8]]] = &[&[TREASURY_SEED, &[bump]]];
+
+    match tag {
+        0 => cpi::deposit(
+            cpi::DepositAccounts {
+                vault_program: vault_program_info.clone(),
+                user: treasury_info.clone(),
+                user_token_account: treasury_token_info.clone(),
+                vault_token_account: vault_token_info.clone(),
+                vault_state: vault_state_info.clone(),
+                user_balance: balance_info.clone(),
+                token_program: token_program_info.clone(),
+                system_program: system_program_info.clone(),
+            },
+            amount,
+            signer_seeds,
+        ),
+        1 => cpi::withdraw(
+            cpi::WithdrawAccounts {
+                vault_program: vault_program_info.clone(),
+                user: treasury_info.clone(),
+                user_token_account: treasury_token_info.clone(),
+                vault_token_account: vault_token_info.clone(),
+                vault_state: vault_state_info.clone(),
+                user_balance: balance_info.clone(),
+                token_program: token_program_info.clone(),
+            },
+            amount,
+            signer_seeds,
+        ),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}